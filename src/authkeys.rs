@@ -0,0 +1,34 @@
+// authorized_keys files watched by the integrity monitor: the configured files, or
+// authorized_keys and authorized_keys2 of every home directory listed in /etc/passwd
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const PASSWD: &str = "/etc/passwd";
+
+// Files and the user owning each, when known
+pub fn files(configured: Option<&[PathBuf]>) -> Vec<(PathBuf, Option<String>)> {
+    if let Some(paths) = configured {
+        return paths.iter().map(|p| (p.clone(), None)).collect();
+    }
+
+    let passwd = fs::read_to_string(PASSWD).unwrap_or_default();
+    let mut files: Vec<(PathBuf, Option<String>)> = Vec::new();
+    for line in passwd.lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        let (user, home) = match (fields.first(), fields.get(5)) {
+            (Some(user), Some(home)) if !home.is_empty() && *home != "/" => (user, home),
+            _ => continue,
+        };
+        if !Path::new(home).is_dir() {
+            continue;
+        }
+        for name in ["authorized_keys", "authorized_keys2"] {
+            let path = Path::new(home).join(".ssh").join(name);
+            if !files.iter().any(|(p, _)| *p == path) {
+                files.push((path, Some(user.to_string())));
+            }
+        }
+    }
+    files
+}
@@ -0,0 +1,601 @@
+// Pluggable sources of authentication log lines: journald, a syslog file such as
+// /var/log/auth.log or /var/log/secure (with rotation handling), or a file replayed once.
+// Every source resumes from a cursor string persisted by the caller.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::journal;
+
+// Bytes read from the end of a log file the first time it is tailed
+const INITIAL_TAIL_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    // Unique and stable identifier of the line, used to deduplicate stored events
+    pub cursor: String,
+    pub timestamp_us: u64,
+    pub message: String,
+}
+
+impl LogRecord {
+    pub fn timestamp_secs(&self) -> u64 {
+        self.timestamp_us / 1_000_000
+    }
+}
+
+pub trait AuthLogSource: Send {
+    // Key under which the position of this source is persisted
+    fn state_key(&self) -> String;
+
+    // Returns the records after `position` and the position to resume from next time
+    fn read(&mut self, position: Option<&str>) -> io::Result<(Vec<LogRecord>, Option<String>)>;
+}
+
+// Builds the source described by `kind` ("auto", "journald", "file" or "replay")
+pub fn from_config(
+    kind: &str,
+    units: &[String],
+    log_file: Option<&Path>,
+    replay_file: Option<&Path>,
+) -> io::Result<Box<dyn AuthLogSource>> {
+    let file_source = || -> io::Result<Box<dyn AuthLogSource>> {
+        let path = log_file
+            .map(Path::to_path_buf)
+            .or_else(default_log_file)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no auth log file found"))?;
+        Ok(Box::new(FileSource::new(path)))
+    };
+
+    match kind {
+        "journald" => Ok(Box::new(JournaldSource::new(units.to_vec()))),
+        "file" => file_source(),
+        "replay" => {
+            let path = replay_file
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no replay file configured"))?;
+            Ok(Box::new(ReplaySource::from_file(path)?))
+        }
+        _ => {
+            if journalctl_available() {
+                Ok(Box::new(JournaldSource::new(units.to_vec())))
+            } else {
+                file_source()
+            }
+        }
+    }
+}
+
+fn journalctl_available() -> bool {
+    Command::new("journalctl")
+        .arg("--version")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+// /var/log/auth.log on Debian/Ubuntu, /var/log/secure on RHEL
+fn default_log_file() -> Option<PathBuf> {
+    ["/var/log/auth.log", "/var/log/secure"]
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.exists())
+}
+
+pub struct JournaldSource {
+    units: Vec<String>,
+}
+
+impl JournaldSource {
+    pub fn new(units: Vec<String>) -> Self {
+        JournaldSource { units }
+    }
+}
+
+impl AuthLogSource for JournaldSource {
+    fn state_key(&self) -> String {
+        "ssh_journal_cursor".to_string()
+    }
+
+    fn read(&mut self, position: Option<&str>) -> io::Result<(Vec<LogRecord>, Option<String>)> {
+        let entries = journal::read_entries(&self.units, position)?;
+        let next = entries
+            .last()
+            .map(|e| e.cursor.clone())
+            .or_else(|| position.map(str::to_string));
+        let records = entries
+            .into_iter()
+            .map(|e| LogRecord {
+                message: e.syslog_message(),
+                cursor: e.cursor,
+                timestamp_us: e.realtime_us,
+            })
+            .collect();
+        Ok((records, next))
+    }
+}
+
+// Tails a syslog file. The position is "<inode>:<generation>:<offset>"; when the inode
+// changes the rest of the rotated file (<path>.1) is read first, and a file shorter than
+// the offset (copytruncate) is read from the start as the next generation, so its lines
+// get new cursors.
+pub struct FileSource {
+    path: PathBuf,
+}
+
+impl FileSource {
+    pub fn new(path: PathBuf) -> Self {
+        FileSource { path }
+    }
+
+    fn read_from(&self, path: &Path, file: FilePosition, records: &mut Vec<LogRecord>) -> io::Result<u64> {
+        let FilePosition { inode, generation, offset } = file;
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+
+        // Only consume complete lines, a partially written one is read next time
+        let complete = match buffer.iter().rposition(|&b| b == b'\n') {
+            Some(i) => i + 1,
+            None => return Ok(offset),
+        };
+
+        let now = now_us();
+        let mut line_offset = offset;
+        for line in buffer[..complete].split(|&b| b == b'\n') {
+            let length = line.len() as u64 + 1;
+            if !line.is_empty() {
+                let message = String::from_utf8_lossy(line).into_owned();
+                records.push(LogRecord {
+                    cursor: format!("{}:{}:{}:{}", self.path.display(), inode, generation, line_offset),
+                    timestamp_us: parse_syslog_timestamp(&message, now).unwrap_or(now),
+                    message,
+                });
+            }
+            line_offset += length;
+        }
+        Ok(offset + complete as u64)
+    }
+}
+
+impl AuthLogSource for FileSource {
+    fn state_key(&self) -> String {
+        format!("auth_file_position:{}", self.path.display())
+    }
+
+    fn read(&mut self, position: Option<&str>) -> io::Result<(Vec<LogRecord>, Option<String>)> {
+        let metadata = fs::metadata(&self.path)?;
+        let inode = metadata.ino();
+        let mut records = Vec::new();
+
+        let start = match position.and_then(FilePosition::parse) {
+            Some(saved) if saved.inode == inode => {
+                if metadata.len() < saved.offset {
+                    FilePosition { offset: 0, generation: saved.generation + 1, ..saved }
+                } else {
+                    saved
+                }
+            }
+            Some(saved) => {
+                // Rotated: finish the previous file if it is still around
+                let rotated = PathBuf::from(format!("{}.1", self.path.display()));
+                if fs::metadata(&rotated).map(|m| m.ino() == saved.inode).unwrap_or(false) {
+                    self.read_from(&rotated, saved, &mut records)?;
+                }
+                FilePosition { inode, generation: 0, offset: 0 }
+            }
+            None => FilePosition { inode, generation: 0, offset: start_of_tail(&self.path, metadata.len())? },
+        };
+
+        let end = self.read_from(&self.path, start, &mut records)?;
+        Ok((records, Some(FilePosition { offset: end, ..start }.to_string())))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FilePosition {
+    inode: u64,
+    // Times the file was truncated in place
+    generation: u64,
+    offset: u64,
+}
+
+impl FilePosition {
+    // Positions saved before generations were "<inode>:<offset>"
+    fn parse(value: &str) -> Option<Self> {
+        let fields = value
+            .split(':')
+            .map(|f| f.parse::<u64>().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        match fields[..] {
+            [inode, offset] => Some(FilePosition { inode, generation: 0, offset }),
+            [inode, generation, offset] => Some(FilePosition { inode, generation, offset }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FilePosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.inode, self.generation, self.offset)
+    }
+}
+
+// Offset of the first complete line within the last INITIAL_TAIL_BYTES of the file
+fn start_of_tail(path: &Path, length: u64) -> io::Result<u64> {
+    if length <= INITIAL_TAIL_BYTES {
+        return Ok(0);
+    }
+    let start = length - INITIAL_TAIL_BYTES;
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut buffer = vec![0; INITIAL_TAIL_BYTES as usize];
+    let read = file.read(&mut buffer)?;
+    Ok(buffer[..read]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|i| start + i as u64 + 1)
+        .unwrap_or(length))
+}
+
+// Returns the lines of a fixed file once, then nothing. Used to replay captured logs
+// (e.g. in tests or to backfill the database).
+pub struct ReplaySource {
+    name: String,
+    lines: Vec<String>,
+}
+
+impl ReplaySource {
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(Self::from_lines(
+            &path.display().to_string(),
+            content.lines().map(str::to_string).collect(),
+        ))
+    }
+
+    pub fn from_lines(name: &str, lines: Vec<String>) -> Self {
+        ReplaySource {
+            name: name.to_string(),
+            lines,
+        }
+    }
+}
+
+impl AuthLogSource for ReplaySource {
+    fn state_key(&self) -> String {
+        format!("auth_replay_position:{}", self.name)
+    }
+
+    fn read(&mut self, position: Option<&str>) -> io::Result<(Vec<LogRecord>, Option<String>)> {
+        let start = position.and_then(|p| p.parse::<usize>().ok()).unwrap_or(0);
+        let now = now_us();
+        let records = self
+            .lines
+            .iter()
+            .enumerate()
+            .skip(start)
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(i, line)| LogRecord {
+                cursor: format!("replay:{}:{}", self.name, i),
+                timestamp_us: parse_syslog_timestamp(line, now).unwrap_or(now),
+                message: line.clone(),
+            })
+            .collect();
+        Ok((records, Some(self.lines.len().max(start).to_string())))
+    }
+}
+
+fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+// Parses the timestamp at the start of a syslog line, either RFC 3339
+// ("2024-10-18T10:00:00.123456+02:00 host ...") or the traditional local time without
+// a year ("Oct 18 10:00:00 host ..."), for which the year placing it closest before
+// `now_us` (with a day of tolerance) is used
+pub fn parse_syslog_timestamp(line: &str, now_us: u64) -> Option<u64> {
+    let first = line.split_whitespace().next()?;
+    if first.len() >= 19 && first.as_bytes()[4] == b'-' && first.as_bytes()[10] == b'T' {
+        return parse_rfc3339(first);
+    }
+
+    let mut fields = line.split_whitespace();
+    let month = month_number(fields.next()?)?;
+    let day = fields.next()?.parse::<i32>().ok()?;
+    let mut time = fields.next()?.split(':');
+    let hour = time.next()?.parse::<i32>().ok()?;
+    let minute = time.next()?.parse::<i32>().ok()?;
+    let second = time.next()?.parse::<i32>().ok()?;
+
+    let now_secs = (now_us / 1_000_000) as i64;
+    let current_year = civil_from_days(now_secs.div_euclid(86_400)).0;
+    for year in [current_year, current_year - 1] {
+        let secs = local_to_epoch(year, month, day, hour, minute, second)?;
+        if secs <= now_secs + 86_400 {
+            return Some(secs.max(0) as u64 * 1_000_000);
+        }
+    }
+    None
+}
+
+fn parse_rfc3339(value: &str) -> Option<u64> {
+    let year = value.get(0..4)?.parse::<i64>().ok()?;
+    let month = value.get(5..7)?.parse::<u32>().ok()?;
+    let day = value.get(8..10)?.parse::<u32>().ok()?;
+    let hour = value.get(11..13)?.parse::<i64>().ok()?;
+    let minute = value.get(14..16)?.parse::<i64>().ok()?;
+    let second = value.get(17..19)?.parse::<i64>().ok()?;
+
+    let mut rest = &value[19..];
+    let mut micros = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits: String = fraction.chars().take_while(|c| c.is_ascii_digit()).collect();
+        rest = &fraction[digits.len()..];
+        micros = format!("{:0<6}", &digits[..digits.len().min(6)]).parse::<i64>().ok()?;
+    }
+    let offset_secs = match rest {
+        "" | "Z" => 0,
+        _ => {
+            let sign = if rest.starts_with('-') { -1 } else { 1 };
+            let hours = rest.get(1..3)?.parse::<i64>().ok()?;
+            let minutes = rest.get(4..6)?.parse::<i64>().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second - offset_secs;
+    if secs < 0 {
+        return None;
+    }
+    Some(secs as u64 * 1_000_000 + micros as u64)
+}
+
+fn month_number(name: &str) -> Option<i32> {
+    let months = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    months.iter().position(|&m| m == name).map(|i| i as i32 + 1)
+}
+
+// Converts a local date and time to seconds since the epoch, honouring the system timezone
+pub fn local_to_epoch(year: i64, month: i32, day: i32, hour: i32, minute: i32, second: i32) -> Option<i64> {
+    // SAFETY: tm is fully initialised and mktime only reads/normalises it
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        tm.tm_year = (year - 1900) as i32;
+        tm.tm_mon = month - 1;
+        tm.tm_mday = day;
+        tm.tm_hour = hour;
+        tm.tm_min = minute;
+        tm.tm_sec = second;
+        tm.tm_isdst = -1;
+        let secs = libc::mktime(&mut tm);
+        if secs == -1 {
+            None
+        } else {
+            Some(secs as i64)
+        }
+    }
+}
+
+// "2024-03-09 14:05:31" in the system timezone, for seconds since the epoch
+pub fn format_local_time(secs: u64) -> String {
+    // SAFETY: localtime_r only writes to the tm it is given
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        let time = secs as libc::time_t;
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return secs.to_string();
+        }
+        tm
+    };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's algorithm)
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// (year, month, day) of a number of days since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn append(path: &Path, text: &str) {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(text.as_bytes())
+            .unwrap();
+    }
+
+    fn messages(records: &[LogRecord]) -> Vec<&str> {
+        records.iter().map(|r| r.message.as_str()).collect()
+    }
+
+    const LINE_1: &str = "Oct 18 10:00:01 node sshd[1]: Failed password for root from 192.0.2.1 port 1 ssh2";
+    const LINE_2: &str = "Oct 18 10:00:02 node sshd[2]: Failed password for root from 192.0.2.2 port 2 ssh2";
+    const LINE_3: &str = "Oct 18 10:00:03 node sshd[3]: Failed password for root from 192.0.2.3 port 3 ssh2";
+
+    #[test]
+    fn file_source_finishes_the_rotated_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, &format!("{}\n", LINE_1));
+        let mut source = FileSource::new(path.clone());
+        let (records, position) = source.read(None).unwrap();
+        assert_eq!(messages(&records), [LINE_1]);
+
+        // Written just before the rotation, not read yet
+        append(&path, &format!("{}\n", LINE_2));
+        fs::rename(&path, dir.path().join("auth.log.1")).unwrap();
+        append(&path, &format!("{}\n", LINE_3));
+
+        let (records, position) = source.read(position.as_deref()).unwrap();
+        assert_eq!(messages(&records), [LINE_2, LINE_3]);
+        let inode = fs::metadata(&path).unwrap().ino();
+        assert_eq!(position, Some(format!("{}:0:{}", inode, LINE_3.len() + 1)));
+
+        let (records, _) = source.read(position.as_deref()).unwrap();
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn file_source_rereads_a_truncated_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, &format!("{}\n{}\n", LINE_1, LINE_2));
+        let mut source = FileSource::new(path.clone());
+        let db = crate::migrations::test_database();
+        let store = |records: &[LogRecord]| {
+            for record in records {
+                let event = crate::security::parse_line(&record.message).unwrap();
+                crate::security::insert(&db, record.timestamp_us, Some(&record.cursor), &event, None).unwrap();
+            }
+        };
+        let (records, position) = source.read(None).unwrap();
+        store(&records);
+
+        // copytruncate keeps the inode and empties the file: the new first line is at
+        // offset 0 again, and is still stored
+        fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(0).unwrap();
+        append(&path, &format!("{}\n", LINE_3));
+        let (records, position) = source.read(position.as_deref()).unwrap();
+        assert_eq!(messages(&records), [LINE_3]);
+        store(&records);
+        let stored: i64 = db.query_row("SELECT COUNT(*) FROM security_events", [], |row| row.get(0)).unwrap();
+        assert_eq!(stored, 3);
+
+        let inode = fs::metadata(&path).unwrap().ino();
+        assert_eq!(position, Some(format!("{}:1:{}", inode, LINE_3.len() + 1)));
+    }
+
+    #[test]
+    fn file_source_resumes_from_a_position_without_generation() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, &format!("{}\n{}\n", LINE_1, LINE_2));
+        let inode = fs::metadata(&path).unwrap().ino();
+        let (records, position) = FileSource::new(path)
+            .read(Some(&format!("{}:{}", inode, LINE_1.len() + 1)))
+            .unwrap();
+        assert_eq!(messages(&records), [LINE_2]);
+        assert_eq!(position, Some(format!("{}:0:{}", inode, LINE_1.len() + LINE_2.len() + 2)));
+    }
+
+    #[test]
+    fn file_source_waits_for_complete_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, &format!("{}\n{}", LINE_1, &LINE_2[..20]));
+        let mut source = FileSource::new(path.clone());
+        let (records, position) = source.read(None).unwrap();
+        assert_eq!(messages(&records), [LINE_1]);
+
+        append(&path, &format!("{}\n", &LINE_2[20..]));
+        let (records, _) = source.read(position.as_deref()).unwrap();
+        assert_eq!(messages(&records), [LINE_2]);
+        // Cursors are the byte offsets of the lines, stable across reads
+        let inode = fs::metadata(&path).unwrap().ino();
+        assert_eq!(records[0].cursor, format!("{}:{}:0:{}", path.display(), inode, LINE_1.len() + 1));
+    }
+
+    #[test]
+    fn file_source_starts_with_the_tail_of_a_large_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("auth.log");
+        let line = format!("{}\n", LINE_1);
+        append(&path, &line.repeat((INITIAL_TAIL_BYTES as usize / line.len()) * 2));
+        let (records, _) = FileSource::new(path).read(None).unwrap();
+        assert!(!records.is_empty());
+        assert!(records.len() <= INITIAL_TAIL_BYTES as usize / line.len());
+        assert!(records.iter().all(|r| r.message == LINE_1));
+    }
+
+    #[test]
+    fn replay_source_returns_the_lines_once() {
+        let mut source = ReplaySource::from_lines("capture", vec![LINE_1.to_string(), String::new(), LINE_2.to_string()]);
+        let (records, position) = source.read(None).unwrap();
+        assert_eq!(messages(&records), [LINE_1, LINE_2]);
+        assert_eq!(records[1].cursor, "replay:capture:2");
+        let (records, _) = source.read(position.as_deref()).unwrap();
+        assert!(records.is_empty());
+    }
+
+    fn local_us(year: i64, month: i32, day: i32, hour: i32, minute: i32, second: i32) -> u64 {
+        local_to_epoch(year, month, day, hour, minute, second).unwrap() as u64 * 1_000_000
+    }
+
+    #[test]
+    fn syslog_timestamps_without_a_year() {
+        let new_year = local_us(2026, 1, 1, 0, 30, 0);
+        // Written just before midnight: last year
+        assert_eq!(
+            parse_syslog_timestamp("Dec 31 23:59:58 node sshd[1]: x", new_year),
+            Some(local_us(2025, 12, 31, 23, 59, 58))
+        );
+        assert_eq!(
+            parse_syslog_timestamp("Jan  1 00:10:00 node sshd[1]: x", new_year),
+            Some(local_us(2026, 1, 1, 0, 10, 0))
+        );
+        // Up to a day ahead (clock skew) stays in the current year, later is last year
+        assert_eq!(
+            parse_syslog_timestamp("Jan  1 12:00:00 node sshd[1]: x", new_year),
+            Some(local_us(2026, 1, 1, 12, 0, 0))
+        );
+        assert_eq!(
+            parse_syslog_timestamp("Mar  3 08:00:00 node sshd[1]: x", new_year),
+            Some(local_us(2025, 3, 3, 8, 0, 0))
+        );
+        assert_eq!(parse_syslog_timestamp("sshd[1]: no timestamp", new_year), None);
+    }
+
+    #[test]
+    fn rfc3339_timestamps() {
+        assert_eq!(
+            parse_syslog_timestamp("2026-10-18T10:00:00.123456+02:00 node sshd[1]: x", 0),
+            Some(1_792_310_400_123_456)
+        );
+        assert_eq!(parse_syslog_timestamp("2026-10-18T08:00:00Z node sshd[1]: x", 0), Some(1_792_310_400_000_000));
+    }
+}
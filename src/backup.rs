@@ -0,0 +1,463 @@
+// Online backups of metrics.db with SQLite's backup API, safe while the monitor is
+// collecting: all pages are copied in a single read transaction, which WAL mode lets run
+// alongside the writes. Backups can be gzip-compressed and rotated; a restore validates the
+// backup and its schema version before copying it over the live database.
+
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, OpenFlags};
+
+use crate::authlog;
+use crate::db;
+use crate::migrations;
+
+// Directory and number of backups kept by default
+pub const DEFAULT_DIR: &str = "backups";
+pub const DEFAULT_KEEP: usize = 7;
+
+// Backups are named metrics-<UTC time>.db or .db.gz, with -<n> after the time for further
+// backups within the same second
+const PREFIX: &str = "metrics-";
+
+pub struct Options {
+    pub dir: PathBuf,
+    // Backups kept after rotation, 0 for all
+    pub keep: usize,
+    pub compress: bool,
+}
+
+impl Options {
+    // `[--dir <dir>] [--keep <n>] [--compress]`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options {
+            dir: PathBuf::from(DEFAULT_DIR),
+            keep: DEFAULT_KEEP,
+            compress: false,
+        };
+        let mut rest = args;
+        while let [flag, tail @ ..] = rest {
+            rest = match (flag.as_str(), tail) {
+                ("--compress", _) => {
+                    options.compress = true;
+                    tail
+                }
+                ("--dir", [value, tail @ ..]) => {
+                    options.dir = PathBuf::from(value);
+                    tail
+                }
+                ("--keep", [value, tail @ ..]) => {
+                    options.keep = value.parse().map_err(|_| format!("invalid count '{}'", value))?;
+                    tail
+                }
+                _ => return Err(format!("unknown option or missing value '{}'", flag)),
+            };
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    Db(rusqlite::Error),
+    Io(io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackupError::Db(e) => write!(f, "database error: {}", e),
+            BackupError::Io(e) => write!(f, "file error: {}", e),
+            BackupError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for BackupError {
+    fn from(e: rusqlite::Error) -> Self {
+        BackupError::Db(e)
+    }
+}
+
+impl From<io::Error> for BackupError {
+    fn from(e: io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+// Takes a timestamped backup in `options.dir`, then deletes the oldest ones beyond
+// `options.keep`. Returns the backup and the deleted ones
+pub fn backup(db: &Connection, options: &Options, now: u64) -> Result<(PathBuf, Vec<PathBuf>), BackupError> {
+    fs::create_dir_all(&options.dir)?;
+    let extension = if options.compress { "db.gz" } else { "db" };
+    let mut path = options.dir.join(format!("{}{}.{}", PREFIX, stamp(now), extension));
+    let mut sequence = 0;
+    while path.exists() {
+        sequence += 1;
+        path = options.dir.join(format!("{}{}-{}.{}", PREFIX, stamp(now), sequence, extension));
+    }
+    write_copy(db, &path)?;
+    let removed = if options.keep > 0 { rotate(&options.dir, options.keep)? } else { Vec::new() };
+    Ok((path, removed))
+}
+
+// One-off copy of `db` (the live database at `live`) at `path`, which must not exist yet
+pub fn snapshot(db: &Connection, path: &Path, live: &Path) -> Result<(), BackupError> {
+    if same_file(path, live) {
+        return Err(BackupError::Invalid(format!("{} is the live database", path.display())));
+    }
+    if path.exists() {
+        return Err(BackupError::Invalid(format!("{} already exists", path.display())));
+    }
+    write_copy(db, path)
+}
+
+// Consistent copy of `db` at `path`, gzip-compressed when it ends with .gz. The copy is made
+// under a temporary name, so an interrupted one is never taken for a backup
+fn write_copy(db: &Connection, path: &Path) -> Result<(), BackupError> {
+    let partial = with_suffix(path, ".partial");
+    let partial_db = with_suffix(path, ".partial.db");
+    let result = (|| {
+        if is_compressed(path) {
+            copy(db, &partial_db)?;
+            compress(&partial_db, &partial)?;
+        } else {
+            copy(db, &partial)?;
+        }
+        fs::rename(&partial, path)?;
+        Ok(())
+    })();
+    let _ = fs::remove_file(&partial_db);
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+// Replaces the content of `db` (the live database at `path`) with the backup at `source`,
+// after a copy of the current content to <path>.pre-restore. Returns the schema version of
+// the backup, which is migrated on the next start when older than the current one
+pub fn restore(db: &mut Connection, path: &Path, source: &Path) -> Result<u32, BackupError> {
+    if monitor_running() {
+        return Err(BackupError::Invalid(
+            "the monitor is running in this directory: stop it before restoring".to_string(),
+        ));
+    }
+
+    let unpacked = with_suffix(path, ".restore");
+    let result = (|| {
+        let source = if is_compressed(source) {
+            decompress(source, &unpacked)?;
+            unpacked.as_path()
+        } else {
+            source
+        };
+        let backup = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let version = validate(&backup)?;
+
+        write_copy(db, &with_suffix(path, ".pre-restore"))?;
+        // Through the backup API rather than by replacing the file, so the live database's
+        // WAL cannot be replayed over the restored pages
+        let restore = Backup::new(&backup, db)?;
+        copy_all(&restore)?;
+        Ok(version)
+    })();
+    let _ = fs::remove_file(&unpacked);
+    result
+}
+
+// A backup is restorable when intact and written by this version of the monitor or an
+// older one
+fn validate(backup: &Connection) -> Result<u32, BackupError> {
+    let check: String = backup.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    if check != "ok" {
+        return Err(BackupError::Invalid(format!("backup is corrupt: {}", check)));
+    }
+    let version = migrations::current_version(backup)?;
+    if version > migrations::latest_version() {
+        return Err(BackupError::Invalid(format!(
+            "backup schema version {} is newer than the latest known ({}): upgrade the monitor",
+            version,
+            migrations::latest_version()
+        )));
+    }
+    // Versions before migrations had no user_version but a metrics table
+    if version == 0 && db::object_type(backup, "metrics")?.is_none() {
+        return Err(BackupError::Invalid("not a monitor database (no schema version)".to_string()));
+    }
+    Ok(version)
+}
+
+fn copy(db: &Connection, dest: &Path) -> Result<(), BackupError> {
+    let _ = fs::remove_file(dest);
+    let mut copy = Connection::open(dest)?;
+    copy_all(&Backup::new(db, &mut copy)?)?;
+    // A single self-contained file, without -wal
+    copy.pragma_update_and_check(None, "journal_mode", "DELETE", |_| Ok(()))?;
+    let check: String = copy.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    if check != "ok" {
+        return Err(BackupError::Invalid(format!("copy failed the integrity check: {}", check)));
+    }
+    Ok(())
+}
+
+// All pages in one step: a single read transaction, so writes made meanwhile by the monitor
+// neither restart the copy nor wait for it
+fn copy_all(backup: &Backup) -> Result<(), BackupError> {
+    match backup.step(-1)? {
+        StepResult::Done => Ok(()),
+        _ => Err(BackupError::Invalid("database busy, try again".to_string())),
+    }
+}
+
+fn compress(from: &Path, to: &Path) -> io::Result<()> {
+    let mut input = BufReader::new(File::open(from)?);
+    let mut encoder = GzEncoder::new(BufWriter::new(File::create(to)?), Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.flush()
+}
+
+fn decompress(from: &Path, to: &Path) -> io::Result<()> {
+    let mut decoder = GzDecoder::new(BufReader::new(File::open(from)?));
+    let mut output = BufWriter::new(File::create(to)?);
+    io::copy(&mut decoder, &mut output)?;
+    output.flush()
+}
+
+// Deletes the oldest backups of `dir` beyond the `keep` newest. Other files, including
+// the temporary ones of a backup in progress, are left alone
+fn rotate(dir: &Path, keep: usize) -> io::Result<Vec<PathBuf>> {
+    let mut backups: Vec<((String, u32), PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter_map(|path| {
+            let key = path.file_name()?.to_str().and_then(backup_key)?;
+            Some(((key.0.to_string(), key.1), path))
+        })
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    let removed: Vec<PathBuf> = backups.drain(..excess).map(|(_, path)| path).collect();
+    for path in &removed {
+        fs::remove_file(path)?;
+    }
+    Ok(removed)
+}
+
+// Time and sequence number of a backup named by `backup`, None for other names
+fn backup_key(name: &str) -> Option<(&str, u32)> {
+    let rest = name.strip_prefix(PREFIX)?;
+    let rest = rest.strip_suffix(".db.gz").or_else(|| rest.strip_suffix(".db"))?;
+    let (time, sequence) = match rest.split_once('-') {
+        Some((time, sequence)) => (time, sequence.parse().ok()?),
+        None => (rest, 0),
+    };
+    let is_stamp = time.len() == 16
+        && time.bytes().enumerate().all(|(i, b)| match i {
+            8 => b == b'T',
+            15 => b == b'Z',
+            _ => b.is_ascii_digit(),
+        });
+    is_stamp.then_some((time, sequence))
+}
+
+// Another monitor started from the same directory, which keeps its own connection to the
+// database open and would go on writing over a restore
+pub fn monitor_running() -> bool {
+    let (Ok(exe), Ok(cwd)) = (env::current_exe(), env::current_dir()) else {
+        return false;
+    };
+    let own_pid = std::process::id().to_string();
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return false;
+    };
+    entries.filter_map(|entry| entry.ok()).any(|entry| {
+        let name = entry.file_name();
+        let Some(pid) = name.to_str() else {
+            return false;
+        };
+        pid != own_pid
+            && pid.bytes().all(|b| b.is_ascii_digit())
+            && fs::read_link(entry.path().join("exe")).is_ok_and(|e| e.file_name() == exe.file_name())
+            && fs::read_link(entry.path().join("cwd")).is_ok_and(|c| c == cwd)
+    })
+}
+
+// Also through another name or a hard link
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// "20261017T080000Z" for seconds since the epoch
+fn stamp(secs: u64) -> String {
+    let (year, month, day) = authlog::civil_from_days((secs / 86_400) as i64);
+    let day_secs = secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs % 3600 / 60,
+        day_secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const NOW: u64 = 1_792_310_400;
+
+    fn names(paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn options(dir: &TempDir, keep: usize, compress: bool) -> Options {
+        Options {
+            dir: dir.path().to_path_buf(),
+            keep,
+            compress,
+        }
+    }
+
+    fn listing(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn backup_names() {
+        assert_eq!(backup_key("metrics-20261018T080000Z.db"), Some(("20261018T080000Z", 0)));
+        assert_eq!(backup_key("metrics-20261018T080000Z-2.db.gz"), Some(("20261018T080000Z", 2)));
+        for other in [
+            "metrics-20261018T080000Z.db.partial",
+            "metrics-20261018T080000Z.db.gz.partial",
+            "metrics-20261018T080000Z.db.gz.partial.db",
+            "metrics-20261018T080000Z.db-wal",
+            "metrics-notes.db",
+            "metrics-20261018T080000Z-x.db",
+            "metrics.db",
+        ] {
+            assert_eq!(backup_key(other), None, "{}", other);
+        }
+    }
+
+    #[test]
+    fn backups_within_a_second_get_distinct_names() {
+        let dir = TempDir::new().unwrap();
+        let db = migrations::test_database();
+        let (first, _) = backup(&db, &options(&dir, 0, false), NOW).unwrap();
+        let (second, _) = backup(&db, &options(&dir, 0, false), NOW).unwrap();
+        let (compressed, _) = backup(&db, &options(&dir, 0, true), NOW).unwrap();
+        assert_eq!(
+            names(&[first, second, compressed]),
+            [
+                "metrics-20261018T080000Z.db",
+                "metrics-20261018T080000Z-1.db",
+                "metrics-20261018T080000Z.db.gz"
+            ]
+        );
+    }
+
+    #[test]
+    fn rotation_keeps_the_newest_backups() {
+        let dir = TempDir::new().unwrap();
+        let db = migrations::test_database();
+        backup(&db, &options(&dir, 0, false), NOW - 3600).unwrap();
+        backup(&db, &options(&dir, 0, true), NOW - 60).unwrap();
+        backup(&db, &options(&dir, 0, false), NOW - 60).unwrap();
+        backup(&db, &options(&dir, 0, false), NOW - 60).unwrap();
+        // A backup in progress and unrelated files
+        for other in ["metrics-20261018T080000Z.db.gz.partial.db", "metrics-20261018T080000Z.db.gz.partial", "notes.txt"] {
+            fs::write(dir.path().join(other), "").unwrap();
+        }
+
+        let (_, removed) = backup(&db, &options(&dir, 2, false), NOW).unwrap();
+        assert_eq!(
+            names(&removed),
+            ["metrics-20261018T070000Z.db", "metrics-20261018T075900Z.db", "metrics-20261018T075900Z.db.gz"]
+        );
+        assert_eq!(
+            listing(dir.path()),
+            [
+                "metrics-20261018T075900Z-1.db",
+                "metrics-20261018T080000Z.db",
+                "metrics-20261018T080000Z.db.gz.partial",
+                "metrics-20261018T080000Z.db.gz.partial.db",
+                "notes.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshots_never_replace_a_file() {
+        let dir = TempDir::new().unwrap();
+        let live = dir.path().join("metrics.db");
+        let db = Connection::open(&live).unwrap();
+        db.execute_batch("PRAGMA journal_mode = WAL; CREATE TABLE t (x); INSERT INTO t VALUES (1);").unwrap();
+
+        let error = snapshot(&db, &live, &live).unwrap_err();
+        assert_eq!(error.to_string(), format!("{} is the live database", live.display()));
+        let error = snapshot(&db, &dir.path().join(".").join("metrics.db"), &live).unwrap_err();
+        assert!(error.to_string().ends_with("is the live database"), "{}", error);
+        let rows: i64 = db.query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 1);
+
+        let existing = dir.path().join("notes.db");
+        fs::write(&existing, "notes").unwrap();
+        let error = snapshot(&db, &existing, &live).unwrap_err();
+        assert_eq!(error.to_string(), format!("{} already exists", existing.display()));
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "notes");
+
+        let copy = dir.path().join("copy.db");
+        snapshot(&db, &copy, &live).unwrap();
+        assert!(snapshot(&db, &copy, &live).is_err());
+        assert_eq!(listing(dir.path()), ["copy.db", "metrics.db", "metrics.db-shm", "metrics.db-wal", "notes.db"]);
+    }
+
+    #[test]
+    fn restores_a_compressed_backup() {
+        let dir = TempDir::new().unwrap();
+        let source = migrations::test_database();
+        source.execute("INSERT INTO state (key, value) VALUES ('marker', 'backup')", []).unwrap();
+        let path = dir.path().join("copy.db.gz");
+        snapshot(&source, &path, &dir.path().join("metrics.db")).unwrap();
+
+        let live = dir.path().join("metrics.db");
+        let mut db = Connection::open(&live).unwrap();
+        assert_eq!(restore(&mut db, &live, &path).unwrap(), migrations::latest_version());
+        let marker: String = db.query_row("SELECT value FROM state WHERE key = 'marker'", [], |row| row.get(0)).unwrap();
+        assert_eq!(marker, "backup");
+        assert!(with_suffix(&live, ".pre-restore").exists());
+    }
+}
@@ -0,0 +1,447 @@
+// Full-screen browser of the security_events table: pages of rows loaded from SQLite with
+// status/category/source/user/time range filters, incremental search and sorting
+
+use crossterm::event::KeyCode;
+use rusqlite::types::Value;
+use rusqlite::{Connection, Result};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph, Row, Table, TableState},
+    Frame,
+};
+
+use crate::authlog::format_local_time;
+use crate::security::Severity;
+
+// Rows loaded per page
+const PAGE_SIZE: usize = 100;
+
+// Time ranges cycled through with `r`: label and length in seconds (None: everything)
+const TIME_RANGES: [(&str, Option<u64>); 5] = [
+    ("all", None),
+    ("1h", Some(3600)),
+    ("24h", Some(86_400)),
+    ("7d", Some(7 * 86_400)),
+    ("30d", Some(30 * 86_400)),
+];
+
+// Status filters cycled through with `s`
+const STATUSES: [Option<&str>; 4] = [None, Some("Success"), Some("Failed"), Some("Info")];
+
+// Category filters cycled through with `k`
+const CATEGORIES: [Option<&str>; 8] = [
+    None,
+    Some("ssh"),
+    Some("sudo"),
+    Some("su"),
+    Some("login"),
+    Some("account"),
+    Some("authorized_keys"),
+    Some("integrity"),
+];
+
+// Log panel and browser color of a security event
+pub fn severity_color(severity: Severity) -> Color {
+    match severity {
+        Severity::Info => Color::Gray,
+        Severity::Notice => Color::Green,
+        Severity::Warning => Color::Yellow,
+        Severity::Critical => Color::Red,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortColumn {
+    Time,
+    Source,
+    User,
+    Status,
+    Severity,
+    Event,
+    Country,
+}
+
+impl SortColumn {
+    fn label(&self) -> &'static str {
+        match self {
+            SortColumn::Time => "time",
+            SortColumn::Source => "source",
+            SortColumn::User => "user",
+            SortColumn::Status => "status",
+            SortColumn::Severity => "severity",
+            SortColumn::Event => "event",
+            SortColumn::Country => "country",
+        }
+    }
+
+    fn sql(&self) -> &'static str {
+        match self {
+            SortColumn::Time => "timestamp_us",
+            SortColumn::Source => "source",
+            SortColumn::User => "actor",
+            SortColumn::Status => "status",
+            // By rank rather than alphabetically
+            SortColumn::Severity => {
+                "CASE severity WHEN 'critical' THEN 3 WHEN 'warning' THEN 2 WHEN 'notice' THEN 1 ELSE 0 END"
+            }
+            SortColumn::Event => "event",
+            SortColumn::Country => "country",
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            SortColumn::Time => SortColumn::Source,
+            SortColumn::Source => SortColumn::User,
+            SortColumn::User => SortColumn::Status,
+            SortColumn::Status => SortColumn::Severity,
+            SortColumn::Severity => SortColumn::Event,
+            SortColumn::Event => SortColumn::Country,
+            SortColumn::Country => SortColumn::Time,
+        }
+    }
+}
+
+// Text field being typed into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Input {
+    Search,
+    Source,
+    User,
+}
+
+#[derive(Debug, Clone)]
+struct LogRow {
+    timestamp: u64,
+    severity: Option<Severity>,
+    status: String,
+    category: String,
+    event: String,
+    source: Option<String>,
+    country: Option<String>,
+    actor: Option<String>,
+    target: Option<String>,
+    // Details, or the auth method of SSH events
+    details: Option<String>,
+}
+
+pub struct LogBrowser {
+    status: usize,
+    category: usize,
+    time_range: usize,
+    // Prefix of the source IP, TTY or path, e.g. "203.0.113."
+    source: String,
+    // Prefix of the acting user
+    user: String,
+    // Text searched in the user, target, source, event, details and GeoIP columns
+    search: String,
+    sort: SortColumn,
+    descending: bool,
+    input: Option<Input>,
+    page: usize,
+    rows: Vec<LogRow>,
+    // Rows matching the filters, over all pages
+    total: usize,
+    state: TableState,
+}
+
+impl LogBrowser {
+    pub fn open(db: &Connection, now: u64) -> Result<Self> {
+        let mut browser = LogBrowser {
+            status: 0,
+            category: 0,
+            time_range: 0,
+            source: String::new(),
+            user: String::new(),
+            search: String::new(),
+            sort: SortColumn::Time,
+            descending: true,
+            input: None,
+            page: 0,
+            rows: Vec::new(),
+            total: 0,
+            state: TableState::default(),
+        };
+        browser.reload(db, now)?;
+        Ok(browser)
+    }
+
+    // Whether keys go to a text field (so `q` doesn't quit)
+    pub fn is_typing(&self) -> bool {
+        self.input.is_some()
+    }
+
+    // Handles a key; returns false when the browser should close
+    pub fn handle_key(&mut self, db: &Connection, key: KeyCode, now: u64) -> Result<bool> {
+        if let Some(input) = self.input {
+            let field = match input {
+                Input::Search => &mut self.search,
+                Input::Source => &mut self.source,
+                Input::User => &mut self.user,
+            };
+            match key {
+                KeyCode::Enter | KeyCode::Esc => self.input = None,
+                KeyCode::Backspace => {
+                    field.pop();
+                }
+                KeyCode::Char(c) => field.push(c),
+                _ => return Ok(true),
+            }
+            // Incremental: results follow every keystroke
+            self.page = 0;
+            self.reload(db, now)?;
+            return Ok(true);
+        }
+
+        match key {
+            KeyCode::Esc | KeyCode::Char('l') => return Ok(false),
+            KeyCode::Up => self.select_offset(-1),
+            KeyCode::Down => self.select_offset(1),
+            KeyCode::PageUp if self.page > 0 => {
+                self.page -= 1;
+                self.reload(db, now)?;
+            }
+            KeyCode::PageDown if (self.page + 1) * PAGE_SIZE < self.total => {
+                self.page += 1;
+                self.reload(db, now)?;
+            }
+            KeyCode::Home => {
+                self.page = 0;
+                self.reload(db, now)?;
+            }
+            KeyCode::Char(c) => {
+                match c {
+                    '/' => self.input = Some(Input::Search),
+                    'i' => self.input = Some(Input::Source),
+                    'u' => self.input = Some(Input::User),
+                    's' => self.status = (self.status + 1) % STATUSES.len(),
+                    'k' => self.category = (self.category + 1) % CATEGORIES.len(),
+                    'r' => self.time_range = (self.time_range + 1) % TIME_RANGES.len(),
+                    'o' => self.sort = self.sort.next(),
+                    'd' => self.descending = !self.descending,
+                    'c' => {
+                        self.status = 0;
+                        self.category = 0;
+                        self.time_range = 0;
+                        self.source.clear();
+                        self.user.clear();
+                        self.search.clear();
+                    }
+                    _ => return Ok(true),
+                }
+                self.page = 0;
+                self.reload(db, now)?;
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn select_offset(&mut self, offset: i64) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let selected = self.state.selected().unwrap_or(0) as i64 + offset;
+        self.state
+            .select(Some(selected.clamp(0, self.rows.len() as i64 - 1) as usize));
+    }
+
+    // Reloads the current page with the current filters and sorting
+    pub fn reload(&mut self, db: &Connection, now: u64) -> Result<()> {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+
+        if let Some(status) = STATUSES[self.status] {
+            params.push(Value::Text(status.to_string()));
+            conditions.push(format!("status = ?{}", params.len()));
+        }
+        if let Some(category) = CATEGORIES[self.category] {
+            params.push(Value::Text(category.to_string()));
+            conditions.push(format!("category = ?{}", params.len()));
+        }
+        if let Some(length) = TIME_RANGES[self.time_range].1 {
+            params.push(Value::Integer(now.saturating_sub(length) as i64));
+            conditions.push(format!("timestamp >= ?{}", params.len()));
+        }
+        if !self.source.is_empty() {
+            params.push(Value::Text(format!("{}%", escape_like(&self.source))));
+            conditions.push(format!("source LIKE ?{} ESCAPE '\\'", params.len()));
+        }
+        if !self.user.is_empty() {
+            params.push(Value::Text(format!("{}%", escape_like(&self.user))));
+            conditions.push(format!("actor LIKE ?{} ESCAPE '\\'", params.len()));
+        }
+        if !self.search.is_empty() {
+            params.push(Value::Text(format!("%{}%", escape_like(&self.search))));
+            let n = params.len();
+            conditions.push(
+                [
+                    "actor", "target", "source", "event", "details", "method", "fingerprint", "country", "city", "org",
+                ]
+                    .iter()
+                    .map(|column| format!("{} LIKE ?{} ESCAPE '\\'", column, n))
+                    .collect::<Vec<_>>()
+                    .join(" OR "),
+            );
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "WHERE {}",
+                conditions.iter().map(|c| format!("({})", c)).collect::<Vec<_>>().join(" AND ")
+            )
+        };
+
+        self.total = db.query_row(
+            &format!("SELECT COUNT(*) FROM security_events {}", where_clause),
+            rusqlite::params_from_iter(params.iter()),
+            |row| row.get::<_, i64>(0),
+        )? as usize;
+
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        params.push(Value::Integer(PAGE_SIZE as i64));
+        params.push(Value::Integer((self.page * PAGE_SIZE) as i64));
+        let sql = format!(
+            "SELECT timestamp, severity, status, category, event, source, country, actor, target,
+                COALESCE(details, method)
+             FROM security_events {}
+             ORDER BY {} {}, timestamp_us DESC
+             LIMIT ?{} OFFSET ?{}",
+            where_clause,
+            self.sort.sql(),
+            direction,
+            params.len() - 1,
+            params.len()
+        );
+        self.rows = db
+            .prepare(&sql)?
+            .query_map(rusqlite::params_from_iter(params.iter()), |row| {
+                Ok(LogRow {
+                    timestamp: row.get::<_, i64>(0)? as u64,
+                    severity: row.get::<_, Option<String>>(1)?.as_deref().and_then(Severity::parse),
+                    status: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    category: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                    event: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    source: row.get(5)?,
+                    country: row.get(6)?,
+                    actor: row.get(7)?,
+                    target: row.get(8)?,
+                    details: row.get(9)?,
+                })
+            })?
+            .collect::<Result<_>>()?;
+
+        self.state.select(if self.rows.is_empty() { None } else { Some(0) });
+        Ok(())
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0), Constraint::Length(3)].as_ref())
+            .split(area);
+
+        // Active filters, with the field being typed into highlighted
+        let field = |label: &str, value: &str, input: Input| {
+            let typing = self.input == Some(input);
+            let text = format!(
+                "{}: {}{}  ",
+                label,
+                if value.is_empty() && !typing { "-" } else { value },
+                if typing { "▏" } else { "" }
+            );
+            if typing {
+                Span::styled(text, Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            } else {
+                Span::raw(text)
+            }
+        };
+        let filters = Paragraph::new(Spans::from(vec![
+            Span::raw(format!("status: {}  ", STATUSES[self.status].unwrap_or("all"))),
+            Span::raw(format!("category: {}  ", CATEGORIES[self.category].unwrap_or("all"))),
+            Span::raw(format!("range: {}  ", TIME_RANGES[self.time_range].0)),
+            field("source", &self.source, Input::Source),
+            field("user", &self.user, Input::User),
+            field("search", &self.search, Input::Search),
+            Span::raw(format!(
+                "sort: {} {}",
+                self.sort.label(),
+                if self.descending { "↓" } else { "↑" }
+            )),
+        ]))
+        .block(Block::default().title("Security log - filters").borders(Borders::ALL));
+        f.render_widget(filters, chunks[0]);
+
+        let rows: Vec<Row> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let color = row.severity.map(severity_color).unwrap_or(Color::Gray);
+                Row::new(vec![
+                    format_local_time(row.timestamp),
+                    row.status.clone(),
+                    row.category.clone(),
+                    row.event.clone(),
+                    row.source.clone().unwrap_or_default(),
+                    row.country.clone().unwrap_or_default(),
+                    row.actor.clone().unwrap_or_default(),
+                    row.target.clone().unwrap_or_default(),
+                    row.details.clone().unwrap_or_default(),
+                ])
+                .style(Style::default().fg(color))
+            })
+            .collect();
+
+        let first = self.page * PAGE_SIZE;
+        let title = if self.total == 0 {
+            "security_events - no matching events".to_string()
+        } else {
+            format!(
+                "security_events - {}-{} of {} (page {}/{})",
+                first + 1,
+                first + self.rows.len(),
+                self.total,
+                self.page + 1,
+                self.total.div_ceil(PAGE_SIZE)
+            )
+        };
+        let table = Table::new(rows)
+            .header(
+                Row::new(vec!["Time", "Status", "Category", "Event", "Source", "Country", "User", "Target", "Details"])
+                    .style(Style::default().fg(Color::Gray).add_modifier(Modifier::BOLD)),
+            )
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .widths(&[
+                Constraint::Length(19),
+                Constraint::Length(7),
+                Constraint::Length(15),
+                Constraint::Length(23),
+                Constraint::Length(28),
+                Constraint::Length(7),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Min(10),
+            ]);
+        f.render_stateful_widget(table, chunks[1], &mut self.state);
+
+        let help = Paragraph::new(
+            "↑↓ select  PgUp/PgDn page  Home first page  s status  k category  r range  i source  u user  / search  o sort  d direction  c clear  Esc/l close",
+        )
+        .style(Style::default().fg(Color::Gray))
+        .block(Block::default().borders(Borders::ALL));
+        f.render_widget(help, chunks[2]);
+    }
+}
+
+// Escapes the LIKE wildcards of user input (used with ESCAPE '\')
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
@@ -0,0 +1,213 @@
+// SSH brute-force detection: aggregates failed attempts of ssh_attempts per IP and per
+// username over sliding windows and flags IPs failing faster than the configured rate.
+// Only `failed` and `max_auth_exceeded` events are counted: an unknown username also logs an
+// `invalid_user` event before the failure, which would count one attempt twice
+
+use rusqlite::{Connection, Result};
+
+#[derive(Debug, Clone)]
+pub struct BruteForceConfig {
+    // Window used for the rate check, in seconds
+    pub rate_window: u64,
+    // Failures within rate_window at or above which an IP is flagged as a brute-forcer
+    pub max_failures: u64,
+    // Window of the "top attackers" report, in seconds
+    pub report_window: u64,
+    // Rows kept in the report
+    pub top: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttackerStats {
+    pub ip: String,
+    pub failures: u64,
+    // Failures within the rate window
+    pub recent_failures: u64,
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub usernames: Vec<String>,
+    pub brute_forcer: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct UserStats {
+    pub username: String,
+    pub failures: u64,
+    pub distinct_ips: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CountryStats {
+    // ISO code, None for IPs without GeoIP data
+    pub country: Option<String>,
+    pub failures: u64,
+    pub distinct_ips: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    // Top IPs by failures over the report window
+    pub attackers: Vec<AttackerStats>,
+    // Top targeted usernames over the report window
+    pub users: Vec<UserStats>,
+    // Failed attempts per country over the report window
+    pub countries: Vec<CountryStats>,
+    // Every IP over the rate limit, including those beyond the top of the report
+    pub brute_forcers: Vec<String>,
+}
+
+pub fn analyze(db: &Connection, config: &BruteForceConfig, now: u64) -> Result<Analysis> {
+    let report_start = now.saturating_sub(config.report_window) as i64;
+    let rate_start = now.saturating_sub(config.rate_window) as i64;
+    let max_failures = config.max_failures as i64;
+
+    let attackers = db
+        .prepare_cached(
+            "SELECT ip,
+                    COUNT(*),
+                    SUM(CASE WHEN timestamp >= ?2 THEN 1 ELSE 0 END),
+                    MIN(timestamp),
+                    MAX(timestamp),
+                    GROUP_CONCAT(DISTINCT username)
+             FROM ssh_attempts
+             WHERE event IN ('failed', 'max_auth_exceeded') AND timestamp >= ?1 AND ip != 'unknown'
+             GROUP BY ip
+             ORDER BY COUNT(*) DESC
+             LIMIT ?3",
+        )?
+        .query_map(
+            rusqlite::params![report_start, rate_start, config.top as i64],
+            |row| {
+                let recent_failures = row.get::<_, i64>(2)? as u64;
+                Ok(AttackerStats {
+                    ip: row.get(0)?,
+                    failures: row.get::<_, i64>(1)? as u64,
+                    recent_failures,
+                    first_seen: row.get::<_, i64>(3)? as u64,
+                    last_seen: row.get::<_, i64>(4)? as u64,
+                    usernames: row
+                        .get::<_, Option<String>>(5)?
+                        .map(|u| u.split(',').map(str::to_string).collect())
+                        .unwrap_or_default(),
+                    brute_forcer: recent_failures >= config.max_failures,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>>>()?;
+
+    let users = db
+        .prepare_cached(
+            "SELECT username, COUNT(*), COUNT(DISTINCT ip)
+             FROM ssh_attempts
+             WHERE event IN ('failed', 'max_auth_exceeded') AND timestamp >= ?1 AND username IS NOT NULL
+             GROUP BY username
+             ORDER BY COUNT(*) DESC
+             LIMIT ?2",
+        )?
+        .query_map(rusqlite::params![report_start, config.top as i64], |row| {
+            Ok(UserStats {
+                username: row.get(0)?,
+                failures: row.get::<_, i64>(1)? as u64,
+                distinct_ips: row.get::<_, i64>(2)? as u64,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let countries = db
+        .prepare_cached(
+            "SELECT country, COUNT(*), COUNT(DISTINCT ip)
+             FROM ssh_attempts
+             WHERE event IN ('failed', 'max_auth_exceeded') AND timestamp >= ?1
+             GROUP BY country
+             ORDER BY COUNT(*) DESC
+             LIMIT ?2",
+        )?
+        .query_map(rusqlite::params![report_start, config.top as i64], |row| {
+            Ok(CountryStats {
+                country: row.get(0)?,
+                failures: row.get::<_, i64>(1)? as u64,
+                distinct_ips: row.get::<_, i64>(2)? as u64,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let brute_forcers = db
+        .prepare_cached(
+            "SELECT ip
+             FROM ssh_attempts
+             WHERE event IN ('failed', 'max_auth_exceeded') AND timestamp >= ?1 AND ip != 'unknown'
+             GROUP BY ip
+             HAVING COUNT(*) >= ?2
+             ORDER BY COUNT(*) DESC",
+        )?
+        .query_map(rusqlite::params![rate_start, max_failures], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+
+    Ok(Analysis {
+        attackers,
+        users,
+        countries,
+        brute_forcers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use crate::security;
+
+    const NOW: u64 = 1_792_310_400;
+
+    fn store(db: &Connection, secs_ago: u64, line: &str) {
+        let event = security::parse_line(line).unwrap();
+        security::insert(db, (NOW - secs_ago) * 1_000_000, None, &event, None).unwrap();
+    }
+
+    fn config(max_failures: u64) -> BruteForceConfig {
+        BruteForceConfig {
+            rate_window: 600,
+            max_failures,
+            report_window: 86_400,
+            top: 10,
+        }
+    }
+
+    fn database() -> Connection {
+        let db = migrations::test_database();
+        // An invalid user logs two lines per attempt
+        for i in 0..4 {
+            store(&db, 60 + i, "sshd[1]: Invalid user oracle from 192.0.2.1 port 40000");
+            store(&db, 60 + i, "sshd[1]: Failed password for invalid user oracle from 192.0.2.1 port 40000 ssh2");
+        }
+        store(&db, 30, "sshd[2]: Failed password for root from 192.0.2.2 port 40001 ssh2");
+        store(&db, 20, "sshd[2]: error: maximum authentication attempts exceeded for root from 192.0.2.2 port 40001 ssh2 [preauth]");
+        // Older than the rate window
+        store(&db, 3600, "sshd[3]: Failed password for root from 192.0.2.2 port 40002 ssh2");
+        db
+    }
+
+    #[test]
+    fn counts_each_attempt_once() {
+        let analysis = analyze(&database(), &config(10), NOW).unwrap();
+        let counts: Vec<(&str, u64, u64)> = analysis
+            .attackers
+            .iter()
+            .map(|a| (a.ip.as_str(), a.failures, a.recent_failures))
+            .collect();
+        assert_eq!(counts, [("192.0.2.1", 4, 4), ("192.0.2.2", 3, 2)]);
+        let oracle = analysis.users.iter().find(|u| u.username == "oracle").unwrap();
+        assert_eq!(oracle.failures, 4);
+    }
+
+    #[test]
+    fn flags_ips_at_the_threshold() {
+        let analysis = analyze(&database(), &config(4), NOW).unwrap();
+        assert_eq!(analysis.brute_forcers, ["192.0.2.1"]);
+        assert!(analysis.attackers[0].brute_forcer);
+        assert!(!analysis.attackers[1].brute_forcer);
+
+        let analysis = analyze(&database(), &config(2), NOW).unwrap();
+        assert_eq!(analysis.brute_forcers.len(), 2);
+    }
+}
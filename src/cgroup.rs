@@ -0,0 +1,344 @@
+// cgroup (v1 and v2) detection and resource limit readers for the kaspad process
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// cgroup v1 reports "no limit" as a page-aligned i64::MAX, anything above this is unlimited
+const V1_UNLIMITED_THRESHOLD: u64 = 1 << 62;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgroupVersion {
+    V1,
+    V2,
+}
+
+impl CgroupVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CgroupVersion::V1 => "v1",
+            CgroupVersion::V2 => "v2",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cgroup {
+    pub version: CgroupVersion,
+    // Relative path of the cgroup as shown in /proc/<pid>/cgroup
+    pub path: String,
+    memory_dir: Option<PathBuf>,
+    cpu_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CgroupLimits {
+    pub memory_limit: Option<u64>,
+    pub memory_usage: Option<u64>,
+    // CPU quota expressed in cores (quota / period), None when unlimited
+    pub cpu_quota_cores: Option<f64>,
+    pub nr_periods: u64,
+    pub nr_throttled: u64,
+    pub throttled_usec: u64,
+}
+
+impl Cgroup {
+    // Finds the cgroup of `pid` and the controller directories under `cgroup_root`
+    // (usually /sys/fs/cgroup). Returns None for processes in the root cgroup.
+    pub fn detect(proc_root: &Path, cgroup_root: &Path, pid: u32) -> Option<Self> {
+        let content = fs::read_to_string(proc_root.join(pid.to_string()).join("cgroup")).ok()?;
+
+        let mut v2_path = None;
+        let mut memory_path = None;
+        let mut cpu_path = None;
+        let mut cpu_controller = "cpu";
+
+        // Lines look like "0::/system.slice/kaspad.service" (v2)
+        // or "4:cpu,cpuacct:/docker/<id>" (v1)
+        for line in content.lines() {
+            let mut parts = line.splitn(3, ':');
+            let (_, controllers, path) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(id), Some(controllers), Some(path)) => (id, controllers, path),
+                _ => continue,
+            };
+            if controllers.is_empty() {
+                v2_path = Some(path.to_string());
+                continue;
+            }
+            for controller in controllers.split(',') {
+                match controller {
+                    "memory" => memory_path = Some(path.to_string()),
+                    "cpu" => {
+                        cpu_path = Some(path.to_string());
+                        cpu_controller = controllers;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Prefer v1 controllers when present (hybrid hierarchy), they hold the limits
+        if memory_path.is_some() || cpu_path.is_some() {
+            let memory_dir = memory_path
+                .as_ref()
+                .map(|p| join_relative(&cgroup_root.join("memory"), p))
+                .filter(|d| d.exists());
+            let cpu_dir = cpu_path
+                .as_ref()
+                .map(|p| join_relative(&cgroup_root.join(cpu_controller), p))
+                .filter(|d| d.exists())
+                .or_else(|| {
+                    cpu_path
+                        .as_ref()
+                        .map(|p| join_relative(&cgroup_root.join("cpu"), p))
+                        .filter(|d| d.exists())
+                });
+            if memory_dir.is_none() && cpu_dir.is_none() {
+                return None;
+            }
+            return Some(Cgroup {
+                version: CgroupVersion::V1,
+                path: memory_path.or(cpu_path).unwrap_or_default(),
+                memory_dir,
+                cpu_dir,
+            });
+        }
+
+        let path = v2_path?;
+        if path == "/" {
+            return None;
+        }
+        let dir = join_relative(cgroup_root, &path);
+        if !dir.exists() {
+            return None;
+        }
+        Some(Cgroup {
+            version: CgroupVersion::V2,
+            path,
+            memory_dir: Some(dir.clone()),
+            cpu_dir: Some(dir),
+        })
+    }
+
+    // Directory holding the *.pressure files (cgroup v2 only)
+    pub fn pressure_dir(&self) -> Option<&Path> {
+        match self.version {
+            CgroupVersion::V2 => self
+                .cpu_dir
+                .as_deref()
+                .filter(|d| d.join("cpu.pressure").exists()),
+            CgroupVersion::V1 => None,
+        }
+    }
+
+    pub fn read_limits(&self) -> CgroupLimits {
+        match self.version {
+            CgroupVersion::V2 => self.read_limits_v2(),
+            CgroupVersion::V1 => self.read_limits_v1(),
+        }
+    }
+
+    fn read_limits_v2(&self) -> CgroupLimits {
+        let mut limits = CgroupLimits::default();
+
+        if let Some(dir) = &self.memory_dir {
+            limits.memory_limit = read_trimmed(&dir.join("memory.max"))
+                .and_then(|v| v.parse::<u64>().ok());
+            limits.memory_usage = read_trimmed(&dir.join("memory.current"))
+                .and_then(|v| v.parse::<u64>().ok());
+        }
+
+        if let Some(dir) = &self.cpu_dir {
+            // "max 100000" or "<quota> <period>"
+            if let Some(cpu_max) = read_trimmed(&dir.join("cpu.max")) {
+                let mut fields = cpu_max.split_whitespace();
+                let quota = fields.next().and_then(|q| q.parse::<f64>().ok());
+                let period = fields.next().and_then(|p| p.parse::<f64>().ok());
+                limits.cpu_quota_cores = match (quota, period) {
+                    (Some(q), Some(p)) if p > 0.0 => Some(q / p),
+                    _ => None,
+                };
+            }
+            for (key, value) in read_key_values(&dir.join("cpu.stat")) {
+                match key.as_str() {
+                    "nr_periods" => limits.nr_periods = value,
+                    "nr_throttled" => limits.nr_throttled = value,
+                    "throttled_usec" => limits.throttled_usec = value,
+                    _ => {}
+                }
+            }
+        }
+
+        limits
+    }
+
+    fn read_limits_v1(&self) -> CgroupLimits {
+        let mut limits = CgroupLimits::default();
+
+        if let Some(dir) = &self.memory_dir {
+            limits.memory_limit = read_trimmed(&dir.join("memory.limit_in_bytes"))
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|&v| v < V1_UNLIMITED_THRESHOLD);
+            limits.memory_usage = read_trimmed(&dir.join("memory.usage_in_bytes"))
+                .and_then(|v| v.parse::<u64>().ok());
+        }
+
+        if let Some(dir) = &self.cpu_dir {
+            let quota = read_trimmed(&dir.join("cpu.cfs_quota_us")).and_then(|v| v.parse::<i64>().ok());
+            let period = read_trimmed(&dir.join("cpu.cfs_period_us")).and_then(|v| v.parse::<i64>().ok());
+            limits.cpu_quota_cores = match (quota, period) {
+                (Some(q), Some(p)) if q > 0 && p > 0 => Some(q as f64 / p as f64),
+                _ => None,
+            };
+            for (key, value) in read_key_values(&dir.join("cpu.stat")) {
+                match key.as_str() {
+                    "nr_periods" => limits.nr_periods = value,
+                    "nr_throttled" => limits.nr_throttled = value,
+                    // v1 reports nanoseconds
+                    "throttled_time" => limits.throttled_usec = value / 1000,
+                    _ => {}
+                }
+            }
+        }
+
+        limits
+    }
+}
+
+fn join_relative(root: &Path, path: &str) -> PathBuf {
+    root.join(path.trim_start_matches('/'))
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_key_values(path: &Path) -> Vec<(String, u64)> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let key = fields.next()?.to_string();
+            let value = fields.next()?.parse::<u64>().ok()?;
+            Some((key, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    // /proc and /sys/fs/cgroup of a v2 host with an unlimited kaspad service (pid 100) and a
+    // limited container (pid 101), plus v1 containers without (pid 200) and with (pid 201)
+    // limits, and init in the root cgroup
+    fn fixture() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write(root, "proc/1/cgroup", "0::/\n");
+
+        write(root, "proc/100/cgroup", "0::/system.slice/kaspad.service\n");
+        write(root, "cgroup/system.slice/kaspad.service/memory.max", "max\n");
+        write(root, "cgroup/system.slice/kaspad.service/memory.current", "3221225472\n");
+        write(root, "cgroup/system.slice/kaspad.service/cpu.max", "max 100000\n");
+        write(
+            root,
+            "cgroup/system.slice/kaspad.service/cpu.stat",
+            "usage_usec 987654\nuser_usec 800000\nsystem_usec 187654\nnr_periods 0\nnr_throttled 0\nthrottled_usec 0\n",
+        );
+        write(root, "cgroup/system.slice/kaspad.service/cpu.pressure", "some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n");
+
+        write(root, "proc/101/cgroup", "0::/system.slice/docker-abc.scope\n");
+        write(root, "cgroup/system.slice/docker-abc.scope/memory.max", "4294967296\n");
+        write(root, "cgroup/system.slice/docker-abc.scope/memory.current", "1073741824\n");
+        write(root, "cgroup/system.slice/docker-abc.scope/cpu.max", "200000 100000\n");
+        write(
+            root,
+            "cgroup/system.slice/docker-abc.scope/cpu.stat",
+            "usage_usec 987654\nnr_periods 500\nnr_throttled 25\nthrottled_usec 1250000\n",
+        );
+
+        write(root, "proc/200/cgroup", "12:memory:/docker/def\n4:cpu,cpuacct:/docker/def\n1:name=systemd:/docker/def\n0::/\n");
+        write(root, "cgroup/memory/docker/def/memory.limit_in_bytes", "9223372036854771712\n");
+        write(root, "cgroup/memory/docker/def/memory.usage_in_bytes", "524288000\n");
+        write(root, "cgroup/cpu,cpuacct/docker/def/cpu.cfs_quota_us", "-1\n");
+        write(root, "cgroup/cpu,cpuacct/docker/def/cpu.cfs_period_us", "100000\n");
+
+        // The cpu controller only mounted as cpu/
+        write(root, "proc/201/cgroup", "12:memory:/docker/ghi\n4:cpu,cpuacct:/docker/ghi\n");
+        write(root, "cgroup/memory/docker/ghi/memory.limit_in_bytes", "2147483648\n");
+        write(root, "cgroup/memory/docker/ghi/memory.usage_in_bytes", "104857600\n");
+        write(root, "cgroup/cpu/docker/ghi/cpu.cfs_quota_us", "150000\n");
+        write(root, "cgroup/cpu/docker/ghi/cpu.cfs_period_us", "100000\n");
+        write(root, "cgroup/cpu/docker/ghi/cpu.stat", "nr_periods 1000\nnr_throttled 40\nthrottled_time 5000000000\n");
+        dir
+    }
+
+    fn detect(dir: &TempDir, pid: u32) -> Option<Cgroup> {
+        Cgroup::detect(&dir.path().join("proc"), &dir.path().join("cgroup"), pid)
+    }
+
+    #[test]
+    fn detects_v2_cgroups() {
+        let dir = fixture();
+        let service = detect(&dir, 100).unwrap();
+        assert_eq!((service.version, service.path.as_str()), (CgroupVersion::V2, "/system.slice/kaspad.service"));
+        assert_eq!(service.pressure_dir(), Some(dir.path().join("cgroup/system.slice/kaspad.service").as_path()));
+        assert_eq!(detect(&dir, 101).unwrap().pressure_dir(), None);
+
+        // Root cgroup, unknown process, cgroup directory gone
+        assert!(detect(&dir, 1).is_none());
+        assert!(detect(&dir, 9999).is_none());
+        fs::remove_dir_all(dir.path().join("cgroup/system.slice/docker-abc.scope")).unwrap();
+        assert!(detect(&dir, 101).is_none());
+    }
+
+    #[test]
+    fn reads_v2_limits() {
+        let dir = fixture();
+        let unlimited = detect(&dir, 100).unwrap().read_limits();
+        assert_eq!((unlimited.memory_limit, unlimited.memory_usage), (None, Some(3_221_225_472)));
+        assert_eq!(unlimited.cpu_quota_cores, None);
+        assert_eq!(unlimited.nr_periods, 0);
+
+        let limited = detect(&dir, 101).unwrap().read_limits();
+        assert_eq!((limited.memory_limit, limited.memory_usage), (Some(4_294_967_296), Some(1_073_741_824)));
+        assert_eq!(limited.cpu_quota_cores, Some(2.0));
+        assert_eq!((limited.nr_periods, limited.nr_throttled, limited.throttled_usec), (500, 25, 1_250_000));
+    }
+
+    #[test]
+    fn detects_v1_controllers() {
+        let dir = fixture();
+        let container = detect(&dir, 200).unwrap();
+        assert_eq!((container.version, container.path.as_str()), (CgroupVersion::V1, "/docker/def"));
+        assert_eq!(container.cpu_dir, Some(dir.path().join("cgroup/cpu,cpuacct/docker/def")));
+        assert_eq!(container.pressure_dir(), None);
+        assert_eq!(detect(&dir, 201).unwrap().cpu_dir, Some(dir.path().join("cgroup/cpu/docker/ghi")));
+
+        // No controller directory
+        write(dir.path(), "proc/202/cgroup", "12:memory:/docker/jkl\n4:cpu,cpuacct:/docker/jkl\n");
+        assert!(detect(&dir, 202).is_none());
+    }
+
+    #[test]
+    fn reads_v1_limits() {
+        let dir = fixture();
+        let unlimited = detect(&dir, 200).unwrap().read_limits();
+        assert_eq!((unlimited.memory_limit, unlimited.memory_usage), (None, Some(524_288_000)));
+        assert_eq!(unlimited.cpu_quota_cores, None);
+
+        let limited = detect(&dir, 201).unwrap().read_limits();
+        assert_eq!((limited.memory_limit, limited.memory_usage), (Some(2_147_483_648), Some(104_857_600)));
+        assert_eq!(limited.cpu_quota_cores, Some(1.5));
+        // throttled_time is in nanoseconds
+        assert_eq!((limited.nr_periods, limited.nr_throttled, limited.throttled_usec), (1000, 40, 5_000_000));
+    }
+}
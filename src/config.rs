@@ -0,0 +1,186 @@
+// Runtime configuration, read from KASPA_MONITOR_* environment variables
+
+use std::env;
+use std::path::PathBuf;
+
+use crate::bruteforce::BruteForceConfig;
+use crate::firewall::{Backend, BanConfig, IpNet};
+use crate::retention::RetentionConfig;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // Root of the /proc tree, overridable to point at a fixture tree
+    pub proc_root: PathBuf,
+    pub cgroup_root: PathBuf,
+    pub sys_root: PathBuf,
+    pub process_name: String,
+    // Overrides the kaspad data directory (default: discovered, then $HOME/.kaspa)
+    pub data_dir: Option<PathBuf>,
+    // Name or ID of the container running kaspad
+    pub container: Option<String>,
+    // CLI used to resolve container names (docker or podman)
+    pub container_runtime: String,
+    // Where SSH events are read from: auto, journald, file or replay
+    pub auth_source: String,
+    // systemd units of sshd ("ssh" on Debian/Ubuntu, "sshd" on RHEL/Arch)
+    pub ssh_units: Vec<String>,
+    // Syslog file tailed by the file source (default: /var/log/auth.log or /var/log/secure)
+    pub auth_log_file: Option<PathBuf>,
+    // File read once by the replay source
+    pub auth_replay_file: Option<PathBuf>,
+    pub brute_force: BruteForceConfig,
+    // Automatic banning of brute-force IPs, None when disabled
+    pub ban: Option<BanConfig>,
+    // MaxMind databases used to enrich SSH source IPs (no network lookups)
+    pub geoip_city_db: Option<PathBuf>,
+    pub geoip_asn_db: Option<PathBuf>,
+    // authorized_keys files to watch (default: those of every home directory)
+    pub authorized_keys: Option<Vec<PathBuf>>,
+    // Extra files watched by the integrity monitor
+    pub integrity_paths: Vec<PathBuf>,
+    // Seconds between two integrity checks
+    pub integrity_interval: u64,
+    pub retention: RetentionConfig,
+    // Seconds between two commits of the buffered time-series writes
+    pub commit_interval: u64,
+    // Shell command run when a login from a new IP, subnet or user is detected
+    pub login_alert_hook: Option<String>,
+}
+
+impl Config {
+    // Fails on values that would silently change what the monitor does
+    pub fn from_env() -> Result<Self, String> {
+        Ok(Config {
+            proc_root: var("PROC_ROOT").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("/proc")),
+            cgroup_root: var("CGROUP_ROOT")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from("/sys/fs/cgroup")),
+            sys_root: var("SYS_ROOT").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("/sys")),
+            process_name: var("PROCESS").unwrap_or_else(|| "kaspad".to_string()),
+            data_dir: var("DATA_DIR").map(PathBuf::from),
+            container: var("CONTAINER"),
+            container_runtime: var("CONTAINER_RUNTIME").unwrap_or_else(|| "docker".to_string()),
+            auth_source: var("AUTH_SOURCE").unwrap_or_else(|| "auto".to_string()),
+            ssh_units: list_var("SSH_UNITS").unwrap_or_else(|| vec!["ssh".to_string(), "sshd".to_string()]),
+            auth_log_file: var("AUTH_LOG").map(PathBuf::from),
+            auth_replay_file: var("AUTH_REPLAY").map(PathBuf::from),
+            brute_force: BruteForceConfig {
+                rate_window: number_var("BRUTEFORCE_WINDOW").unwrap_or(600),
+                max_failures: number_var("BRUTEFORCE_MAX_FAILURES").unwrap_or(10),
+                report_window: number_var("ATTACKERS_WINDOW").unwrap_or(86_400),
+                top: 10,
+            },
+            ban: ban_config()?,
+            geoip_city_db: var("GEOIP_CITY_DB").map(PathBuf::from),
+            geoip_asn_db: var("GEOIP_ASN_DB").map(PathBuf::from),
+            authorized_keys: list_var("AUTHORIZED_KEYS").map(|paths| paths.into_iter().map(PathBuf::from).collect()),
+            integrity_paths: list_var("INTEGRITY_PATHS")
+                .unwrap_or_default()
+                .into_iter()
+                .map(PathBuf::from)
+                .collect(),
+            integrity_interval: number_var("INTEGRITY_INTERVAL").unwrap_or(10),
+            retention: RetentionConfig {
+                // Hourly rollups are computed from raw samples, which must outlive an hour
+                raw_days: number_var("RAW_RETENTION_DAYS").unwrap_or(7).max(1),
+                minute_days: number_var("MINUTE_RETENTION_DAYS").unwrap_or(90),
+                hour_days: number_var("HOUR_RETENTION_DAYS").unwrap_or(730),
+            },
+            commit_interval: number_var("COMMIT_INTERVAL").unwrap_or(2),
+            login_alert_hook: var("LOGIN_ALERT_HOOK"),
+        })
+    }
+
+    // Data directory used when neither the configuration nor container discovery gives one
+    pub fn default_data_dir(&self) -> PathBuf {
+        let home = env::var("HOME").unwrap_or_else(|_| String::from("/root"));
+        PathBuf::from(format!("{}/.kaspa", home))
+    }
+}
+
+// Reads KASPA_MONITOR_<name>, ignoring empty values
+fn var(name: &str) -> Option<String> {
+    env::var(format!("KASPA_MONITOR_{}", name))
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+}
+
+// Reads a comma-separated KASPA_MONITOR_<name>
+fn list_var(name: &str) -> Option<Vec<String>> {
+    var(name).map(|v| {
+        v.split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+}
+
+fn number_var<T: std::str::FromStr>(name: &str) -> Option<T> {
+    var(name).and_then(|v| v.trim().parse::<T>().ok())
+}
+
+// Refuses unknown backends and allowlist entries rather than banning without them
+fn ban_config() -> Result<Option<BanConfig>, String> {
+    let backend = match var("BAN") {
+        Some(name) => backend(&name)?,
+        None => return Ok(None),
+    };
+    Ok(Some(BanConfig {
+        backend,
+        duration: number_var("BAN_DURATION").unwrap_or(3600),
+        allowlist: allowlist(&list_var("BAN_ALLOWLIST").unwrap_or_default())?,
+        dry_run: var("BAN_DRY_RUN")
+            .map(|v| matches!(v.as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false),
+    }))
+}
+
+fn backend(name: &str) -> Result<Backend, String> {
+    match name.trim() {
+        "nftables" => Ok(Backend::Nftables {
+            table: var("NFT_TABLE").unwrap_or_else(|| "kaspa_monitor".to_string()),
+            set: var("NFT_SET").unwrap_or_else(|| "bans".to_string()),
+        }),
+        "iptables" => Ok(Backend::Iptables {
+            chain: var("IPTABLES_CHAIN").unwrap_or_else(|| "KASPA_MONITOR".to_string()),
+        }),
+        other => Err(format!("KASPA_MONITOR_BAN: unknown firewall '{}' (nftables or iptables)", other)),
+    }
+}
+
+fn allowlist(entries: &[String]) -> Result<Vec<IpNet>, String> {
+    entries
+        .iter()
+        .map(|entry| {
+            IpNet::parse(entry).ok_or_else(|| format!("KASPA_MONITOR_BAN_ALLOWLIST: invalid IP or CIDR '{}'", entry))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ban_backends() {
+        assert!(matches!(backend("nftables"), Ok(Backend::Nftables { .. })));
+        assert!(matches!(backend("iptables"), Ok(Backend::Iptables { .. })));
+        assert_eq!(
+            backend("nft").err().as_deref(),
+            Some("KASPA_MONITOR_BAN: unknown firewall 'nft' (nftables or iptables)")
+        );
+    }
+
+    #[test]
+    fn ban_allowlist() {
+        let entries = |list: &[&str]| list.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(allowlist(&entries(&["10.0.0.0/8", "203.0.113.7", "2001:db8::/32"])).unwrap().len(), 3);
+        // A typo must not leave the operator's address bannable
+        for typo in ["203.0.113.7/33", "203.0.113", "10.0.0.0/8x"] {
+            assert_eq!(
+                allowlist(&entries(&["10.0.0.0/8", typo])).err(),
+                Some(format!("KASPA_MONITOR_BAN_ALLOWLIST: invalid IP or CIDR '{}'", typo))
+            );
+        }
+    }
+}
@@ -0,0 +1,302 @@
+// Discovery of a kaspad process running inside a Docker/Podman container:
+// PID on the host, bind-mounted data directory and network namespace counters.
+// Everything except the name -> ID resolution works on a /proc tree, so it can
+// be pointed at a fixture directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Data directory kaspad uses inside the official images when no --appdir is given
+const DEFAULT_CONTAINER_APPDIR: &str = "/root/.kaspa";
+
+#[derive(Debug, Clone)]
+pub struct KaspadContainer {
+    pub id: String,
+    pub pid: u32,
+    // Host path of the kaspad data directory, when it is bind-mounted
+    pub data_dir: Option<PathBuf>,
+}
+
+impl KaspadContainer {
+    // Resolves `container` (name or ID) and locates its kaspad process under `proc_root`
+    pub fn discover(
+        proc_root: &Path,
+        runtime: &str,
+        container: &str,
+        process_name: &str,
+    ) -> Option<Self> {
+        let id = resolve_container_id(runtime, container).unwrap_or_else(|| container.to_string());
+        let pid = find_container_pid(proc_root, &id, process_name)?;
+        let data_dir = container_appdir(proc_root, pid)
+            .and_then(|appdir| host_path_of(proc_root, pid, &appdir));
+        Some(KaspadContainer { id, pid, data_dir })
+    }
+
+    // Total received and transmitted bytes in the container's network namespace
+    pub fn network_totals(&self, proc_root: &Path) -> Option<(u64, u64)> {
+        read_net_dev(proc_root, self.pid)
+    }
+}
+
+// Asks the container runtime for the full ID of `container`
+pub fn resolve_container_id(runtime: &str, container: &str) -> Option<String> {
+    let output = Command::new(runtime)
+        .args(["inspect", "--format", "{{.Id}}", container])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if id.is_empty() {
+        None
+    } else {
+        Some(id)
+    }
+}
+
+// Finds a process named `process_name` whose cgroup path contains `container_id`
+// (e.g. "0::/system.slice/docker-<id>.scope" or "12:memory:/docker/<id>")
+pub fn find_container_pid(proc_root: &Path, container_id: &str, process_name: &str) -> Option<u32> {
+    let mut pids: Vec<u32> = fs::read_dir(proc_root)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .collect();
+    pids.sort_unstable();
+
+    pids.into_iter().find(|pid| {
+        let dir = proc_root.join(pid.to_string());
+        let comm = fs::read_to_string(dir.join("comm")).unwrap_or_default();
+        if comm.trim() != process_name {
+            return false;
+        }
+        fs::read_to_string(dir.join("cgroup"))
+            .map(|cgroup| cgroup.lines().any(|line| line.contains(container_id)))
+            .unwrap_or(false)
+    })
+}
+
+// kaspad's data directory as seen inside the container, from --appdir/-b in its cmdline
+pub fn container_appdir(proc_root: &Path, pid: u32) -> Option<String> {
+    let cmdline = fs::read(proc_root.join(pid.to_string()).join("cmdline")).ok()?;
+    let args: Vec<String> = cmdline
+        .split(|&b| b == 0)
+        .filter(|a| !a.is_empty())
+        .map(|a| String::from_utf8_lossy(a).into_owned())
+        .collect();
+
+    for (i, arg) in args.iter().enumerate() {
+        if let Some(value) = arg.strip_prefix("--appdir=") {
+            return Some(value.to_string());
+        }
+        if arg == "--appdir" || arg == "-b" {
+            return args.get(i + 1).cloned();
+        }
+    }
+    Some(DEFAULT_CONTAINER_APPDIR.to_string())
+}
+
+#[derive(Debug, Clone)]
+struct MountInfo {
+    device: String,
+    root: String,
+    mount_point: String,
+}
+
+fn parse_mountinfo(content: &str) -> Vec<MountInfo> {
+    // 36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 5 {
+                return None;
+            }
+            Some(MountInfo {
+                device: fields[2].to_string(),
+                root: unescape_mount_path(fields[3]),
+                mount_point: unescape_mount_path(fields[4]),
+            })
+        })
+        .collect()
+}
+
+// mountinfo escapes spaces, tabs, newlines and backslashes as octal sequences
+fn unescape_mount_path(path: &str) -> String {
+    path.replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\")
+}
+
+// Translates `container_path` (inside the mount namespace of `pid`) to a host path,
+// using the mount covering it and the host mount of the same device (from PID 1)
+pub fn host_path_of(proc_root: &Path, pid: u32, container_path: &str) -> Option<PathBuf> {
+    let container_mounts =
+        parse_mountinfo(&fs::read_to_string(proc_root.join(pid.to_string()).join("mountinfo")).ok()?);
+    let host_mounts = parse_mountinfo(&fs::read_to_string(proc_root.join("1").join("mountinfo")).ok()?);
+
+    // Longest mount point that is a prefix of the path
+    let mount = container_mounts
+        .iter()
+        .filter(|m| path_starts_with(container_path, &m.mount_point))
+        .max_by_key(|m| m.mount_point.len())?;
+
+    // Overlay root filesystems have no host equivalent, only bind mounts do
+    if mount.mount_point == "/" {
+        return None;
+    }
+
+    let host_mount = host_mounts
+        .iter()
+        .filter(|h| h.device == mount.device && path_starts_with(&mount.root, &h.root))
+        .max_by_key(|h| h.root.len())?;
+
+    let source = format!(
+        "{}/{}",
+        host_mount.mount_point.trim_end_matches('/'),
+        mount.root[host_mount.root.len()..].trim_start_matches('/')
+    );
+    let rest = container_path[mount.mount_point.len()..].trim_start_matches('/');
+    let mut host_path = PathBuf::from(source);
+    if !rest.is_empty() {
+        host_path.push(rest);
+    }
+    Some(host_path)
+}
+
+fn path_starts_with(path: &str, prefix: &str) -> bool {
+    prefix == "/"
+        || path == prefix
+        || (path.starts_with(prefix) && path[prefix.len()..].starts_with('/'))
+}
+
+// Sums the byte counters of /proc/<pid>/net/dev, skipping the loopback interface
+pub fn read_net_dev(proc_root: &Path, pid: u32) -> Option<(u64, u64)> {
+    let content = fs::read_to_string(proc_root.join(pid.to_string()).join("net").join("dev")).ok()?;
+    let mut received = 0;
+    let mut transmitted = 0;
+
+    // Inter-|   Receive                            |  Transmit
+    //  face |bytes    packets errs drop ...         |bytes    packets ...
+    //   eth0: 1234 10 0 0 0 0 0 0 5678 20 0 0 0 0 0 0
+    for line in content.lines().skip(2) {
+        let (iface, counters) = match line.split_once(':') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        if iface.trim() == "lo" {
+            continue;
+        }
+        let fields: Vec<u64> = counters
+            .split_whitespace()
+            .filter_map(|f| f.parse::<u64>().ok())
+            .collect();
+        if fields.len() >= 9 {
+            received += fields[0];
+            transmitted += fields[8];
+        }
+    }
+    Some((received, transmitted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const CONTAINER_ID: &str = "4f3c2b1a0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b";
+
+    fn write(root: &Path, path: &str, content: &[u8]) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    // Host with a data disk on /data, kaspad (pid 4242) in a container with /data/kaspa
+    // bind-mounted on /app, a kaspad outside the container and a shell inside it
+    fn fixture() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write(
+            root,
+            "1/mountinfo",
+            b"22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+              30 22 8:17 / /data rw,relatime shared:2 - ext4 /dev/sdb1 rw\n\
+              31 22 8:33 / /mnt/my\\040disk rw,relatime shared:3 - ext4 /dev/sdc1 rw\n",
+        );
+
+        write(root, "100/comm", b"kaspad\n");
+        write(root, "100/cgroup", b"0::/system.slice/kaspad.service\n");
+
+        write(root, "200/comm", b"bash\n");
+        write(root, "200/cgroup", format!("0::/system.slice/docker-{}.scope\n", CONTAINER_ID).as_bytes());
+
+        write(root, "4242/comm", b"kaspad\n");
+        write(root, "4242/cgroup", format!("0::/system.slice/docker-{}.scope\n", CONTAINER_ID).as_bytes());
+        write(root, "4242/cmdline", b"/app/kaspad\0--utxoindex\0--appdir=/app/data\0");
+        write(
+            root,
+            "4242/mountinfo",
+            b"500 400 0:52 / / rw,relatime - overlay overlay rw,lowerdir=/var/lib/docker/overlay2/l/A\n\
+              501 500 8:17 /kaspa /app rw,relatime - ext4 /dev/sdb1 rw\n\
+              502 500 8:33 /backups /backup\\040dir rw,relatime - ext4 /dev/sdc1 rw\n",
+        );
+        write(
+            root,
+            "4242/net/dev",
+            b"Inter-|   Receive                                                |  Transmit\n \
+              face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n    \
+              lo:  999999     100    0    0    0     0          0         0   999999     100    0    0    0     0       0          0\n  \
+              eth0: 1000 10 0 0 0 0 0 0 2000 20 0 0 0 0 0 0\n  \
+              eth1: 300 3 0 0 0 0 0 0 400 4 0 0 0 0 0 0\n",
+        );
+        dir
+    }
+
+    #[test]
+    fn finds_the_kaspad_process_of_the_container() {
+        let proc = fixture();
+        assert_eq!(find_container_pid(proc.path(), CONTAINER_ID, "kaspad"), Some(4242));
+        // A short ID, as given on the command line, matches too
+        assert_eq!(find_container_pid(proc.path(), &CONTAINER_ID[..12], "kaspad"), Some(4242));
+        assert_eq!(find_container_pid(proc.path(), CONTAINER_ID, "kaspad-rpc"), None);
+        assert_eq!(find_container_pid(proc.path(), "0123456789ab", "kaspad"), None);
+    }
+
+    #[test]
+    fn reads_the_appdir_from_the_cmdline() {
+        let proc = fixture();
+        assert_eq!(container_appdir(proc.path(), 4242).as_deref(), Some("/app/data"));
+
+        write(proc.path(), "4242/cmdline", b"kaspad\0-b\0/srv/kaspa\0");
+        assert_eq!(container_appdir(proc.path(), 4242).as_deref(), Some("/srv/kaspa"));
+        write(proc.path(), "4242/cmdline", b"kaspad\0--appdir\0/srv/other\0");
+        assert_eq!(container_appdir(proc.path(), 4242).as_deref(), Some("/srv/other"));
+        write(proc.path(), "4242/cmdline", b"kaspad\0--utxoindex\0");
+        assert_eq!(container_appdir(proc.path(), 4242).as_deref(), Some(DEFAULT_CONTAINER_APPDIR));
+
+        assert_eq!(container_appdir(proc.path(), 9999), None);
+    }
+
+    #[test]
+    fn translates_bind_mounts_to_host_paths() {
+        let proc = fixture();
+        let root = proc.path();
+        assert_eq!(host_path_of(root, 4242, "/app/data"), Some(PathBuf::from("/data/kaspa/data")));
+        assert_eq!(host_path_of(root, 4242, "/app"), Some(PathBuf::from("/data/kaspa")));
+        // Escaped spaces in mount points
+        assert_eq!(host_path_of(root, 4242, "/backup dir/daily"), Some(PathBuf::from("/mnt/my disk/backups/daily")));
+        // On the overlay root, or a sibling of a mount point
+        assert_eq!(host_path_of(root, 4242, "/root/.kaspa"), None);
+        assert_eq!(host_path_of(root, 4242, "/application"), None);
+    }
+
+    #[test]
+    fn sums_network_counters_without_loopback() {
+        let proc = fixture();
+        assert_eq!(read_net_dev(proc.path(), 4242), Some((1300, 2400)));
+        assert_eq!(read_net_dev(proc.path(), 100), None);
+    }
+}
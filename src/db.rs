@@ -0,0 +1,105 @@
+// Helpers for evolving the schema of metrics.db
+
+use std::time::Duration;
+
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Result};
+
+// How long a write waits for a lock held by another connection
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn table_columns(db: &Connection, table: &str) -> Result<Vec<String>> {
+    db.prepare(&format!("PRAGMA table_info({})", table))?
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect()
+}
+
+// "table", "view", "index", ... or None when `name` doesn't exist
+pub fn object_type(db: &Connection, name: &str) -> Result<Option<String>> {
+    db.query_row("SELECT type FROM sqlite_master WHERE name = ?1", [name], |row| row.get(0))
+        .optional()
+}
+
+pub fn has_column(db: &Connection, table: &str, column: &str) -> Result<bool> {
+    Ok(table_columns(db, table)?.iter().any(|c| c == column))
+}
+
+// Adds the columns of `columns` (name, type) that `table` doesn't have yet, so databases
+// created by older versions keep working after a column was added to a CREATE TABLE
+pub fn add_missing_columns(db: &Connection, table: &str, columns: &[(&str, &str)]) -> Result<()> {
+    let existing = table_columns(db, table)?;
+    for (name, column_type) in columns {
+        if !existing.iter().any(|c| c == name) {
+            db.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, name, column_type),
+                [],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Recreates `table` with the column definitions `columns_sql`, copying the columns the old
+// and new definitions have in common. Used when a key or constraint changes, which
+// ALTER TABLE can't do in SQLite.
+pub fn rebuild_table(db: &Connection, table: &str, columns_sql: &str) -> Result<()> {
+    let old_columns = table_columns(db, table)?;
+    let tmp = format!("{}_rebuild", table);
+
+    // A savepoint rather than BEGIN, so it also works inside a migration's transaction
+    db.execute_batch("SAVEPOINT rebuild_table")?;
+    let result = (|| {
+        db.execute(&format!("CREATE TABLE {} ({})", tmp, columns_sql), [])?;
+        let new_columns = table_columns(db, &tmp)?;
+        let common: Vec<&String> = new_columns.iter().filter(|c| old_columns.contains(c)).collect();
+        let list = common.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", ");
+        db.execute(
+            &format!("INSERT OR IGNORE INTO {} ({}) SELECT {} FROM {}", tmp, list, list, table),
+            [],
+        )?;
+        db.execute(&format!("DROP TABLE {}", table), [])?;
+        db.execute(&format!("ALTER TABLE {} RENAME TO {}", tmp, table), [])?;
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => db.execute_batch("RELEASE rebuild_table"),
+        Err(e) => {
+            db.execute_batch("ROLLBACK TO rebuild_table; RELEASE rebuild_table")?;
+            Err(e)
+        }
+    }
+}
+
+// Opens the database in WAL mode: readers (export, the log browser) don't block writes,
+// and commits only fsync at checkpoints. Writes wait for a lock held by another process
+// (sqlite3 shell, backup) instead of failing right away.
+pub fn open(path: &str) -> Result<Connection> {
+    let db = Connection::open(path)?;
+    db.busy_timeout(BUSY_TIMEOUT)?;
+    // Only applies to a new database, and must come before WAL mode writes its first page;
+    // older ones are converted by `server_monitor vacuum`
+    db.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+    db.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+    db.pragma_update(None, "synchronous", "NORMAL")?;
+    Ok(db)
+}
+
+// Opens the database for reading only, without creating it or changing its journal mode
+pub fn open_read_only(path: &str) -> Result<Connection> {
+    let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    db.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(db)
+}
+
+// Small key/value store for state that must survive restarts (journal cursors, ...)
+pub fn get_state(db: &Connection, key: &str) -> Result<Option<String>> {
+    db.prepare_cached("SELECT value FROM state WHERE key = ?1")?
+        .query_row([key], |row| row.get(0))
+        .optional()
+}
+
+pub fn set_state(db: &Connection, key: &str, value: &str) -> Result<()> {
+    db.prepare_cached("INSERT OR REPLACE INTO state (key, value) VALUES (?1, ?2)")?
+        .execute([key, value])?;
+    Ok(())
+}
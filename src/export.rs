@@ -0,0 +1,646 @@
+// Export of the `metrics` and `ssh_attempts` views to CSV, JSON Lines or Parquet, for a time
+// range and a choice of columns. Rows are streamed from SQLite to the output (in batches of
+// BATCH_ROWS for Parquet), so large databases are never loaded into memory. Times are
+// ISO-8601 in UTC, and headers carry the unit of their column from metric_units, e.g.
+// "kaspad_memory_bytes [bytes]".
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+
+use arrow_array::builder::{Float64Builder, Int64Builder, StringBuilder, TimestampMicrosecondBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use rusqlite::types::Value;
+use rusqlite::{params, Connection};
+use serde_json::{Number, Value as JsonValue};
+
+use crate::authlog;
+use crate::history;
+
+// Rows per record batch handed to the Parquet writer
+const BATCH_ROWS: usize = 8192;
+
+// Rows per Parquet row group, which the writer keeps in memory until it is complete
+const ROW_GROUP_ROWS: usize = 16 * BATCH_ROWS;
+
+// An exported view: its time columns are replaced by a single "time" column
+struct Source {
+    name: &'static str,
+    // Time of a row in microseconds, as an SQL expression
+    time_us: &'static str,
+    // Indexed column the time range applies to, and its units per second
+    range_column: &'static str,
+    range_scale: u64,
+    time_columns: &'static [&'static str],
+    // Fractional digits of the exported times
+    precision: usize,
+}
+
+const SOURCES: [Source; 2] = [
+    Source {
+        name: "metrics",
+        time_us: "timestamp_ms * 1000",
+        range_column: "timestamp_ms",
+        range_scale: 1000,
+        time_columns: &["timestamp_ms"],
+        precision: 3,
+    },
+    Source {
+        name: "ssh_attempts",
+        time_us: "COALESCE(timestamp_us, timestamp * 1000000)",
+        range_column: "timestamp",
+        range_scale: 1,
+        time_columns: &["timestamp", "timestamp_us"],
+        precision: 6,
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    JsonLines,
+    Parquet,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Format::Csv),
+            "jsonl" => Some(Format::JsonLines),
+            "parquet" => Some(Format::Parquet),
+            _ => None,
+        }
+    }
+}
+
+pub struct Options {
+    pub table: String,
+    // [from, to) in seconds
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    // All the columns when None
+    pub columns: Option<Vec<String>>,
+    pub format: Format,
+    // Standard output when None
+    pub output: Option<String>,
+}
+
+impl Options {
+    // `<table> [--from <time>] [--to <time>] [--columns a,b,...] [--format csv|jsonl|parquet]
+    // [--output <file>]`, where a time is an ISO-8601 date or date and time (local time, or
+    // UTC with a trailing Z) or a length ago such as 30s, 24h or 7d
+    pub fn parse(args: &[String], now: u64) -> Result<Self, String> {
+        let (table, mut rest) = match args.split_first() {
+            Some((table, rest)) => (table.clone(), rest),
+            None => return Err("missing table (metrics or ssh_attempts)".to_string()),
+        };
+        let mut options = Options {
+            table,
+            from: None,
+            to: None,
+            columns: None,
+            format: Format::Csv,
+            output: None,
+        };
+        while let [flag, value, tail @ ..] = rest {
+            match flag.as_str() {
+                "--from" => options.from = Some(parse_time(value, now).ok_or_else(|| invalid_time(value))?),
+                "--to" => options.to = Some(parse_time(value, now).ok_or_else(|| invalid_time(value))?),
+                "--columns" => options.columns = Some(value.split(',').map(|c| c.trim().to_string()).collect()),
+                "--format" => options.format = Format::parse(value).ok_or(format!("unknown format '{}'", value))?,
+                "--output" => options.output = Some(value.clone()),
+                _ => return Err(format!("unknown option '{}'", flag)),
+            }
+            rest = tail;
+        }
+        if let [flag] = rest {
+            return Err(format!("missing value for '{}'", flag));
+        }
+        Ok(options)
+    }
+}
+
+fn invalid_time(text: &str) -> String {
+    format!(
+        "invalid time '{}': expected a date (2026-10-17), a date and time (2026-10-17T08:00, Z for UTC) or a length ago (30s, 45m, 24h, 7d, 2w)",
+        text
+    )
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Db(rusqlite::Error),
+    Io(io::Error),
+    Parquet(ParquetError),
+    Usage(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::Db(e) => write!(f, "database error: {}", e),
+            ExportError::Io(e) => write!(f, "write error: {}", e),
+            ExportError::Parquet(e) => write!(f, "parquet error: {}", e),
+            ExportError::Usage(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ExportError {
+    fn from(e: rusqlite::Error) -> Self {
+        ExportError::Db(e)
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<ParquetError> for ExportError {
+    fn from(e: ParquetError) -> Self {
+        ExportError::Parquet(e)
+    }
+}
+
+// How a column is typed in Parquet, from its declared SQLite type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Integer,
+    Real,
+    Text,
+}
+
+struct Column {
+    name: String,
+    // Name with its unit, e.g. "kaspad_cpu_percent [%]"
+    header: String,
+    kind: Kind,
+}
+
+// Writes the rows of `options.table` and returns their number
+pub fn export(db: &Connection, options: &Options) -> Result<u64, ExportError> {
+    let source = SOURCES.iter().find(|s| s.name == options.table).ok_or_else(|| {
+        ExportError::Usage(format!("unknown table '{}' (metrics or ssh_attempts)", options.table))
+    })?;
+    let columns = select_columns(db, source, options.columns.as_deref())?;
+
+    let output: Box<dyn Write + Send> = match &options.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let output = BufWriter::new(output);
+    let mut writer: Box<dyn RowWriter> = match options.format {
+        Format::Csv => Box::new(CsvWriter::new(output, &columns)?),
+        Format::JsonLines => Box::new(JsonWriter { output, headers: columns.iter().map(|c| c.header.clone()).collect() }),
+        Format::Parquet => Box::new(ParquetWriter::new(output, &columns)?),
+    };
+
+    let names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+    let mut stmt = db.prepare(&format!(
+        "SELECT {time_us}, {columns} FROM {table}
+         WHERE {range} >= ?1 AND {range} < ?2
+         ORDER BY {range}",
+        time_us = source.time_us,
+        columns = names.join(", "),
+        table = source.name,
+        range = source.range_column
+    ))?;
+    let from = options.from.map_or(0, |from| (from * source.range_scale) as i64);
+    let to = options.to.map_or(i64::MAX, |to| (to * source.range_scale) as i64);
+    let mut rows = stmt.query(params![from, to])?;
+    let mut count = 0;
+    let mut values = Vec::with_capacity(columns.len());
+    while let Some(row) = rows.next()? {
+        let time_us: i64 = row.get(0)?;
+        values.clear();
+        for i in 0..columns.len() {
+            values.push(row.get::<_, Value>(i + 1)?);
+        }
+        writer.write_row(time_us, source.precision, &values)?;
+        count += 1;
+    }
+    writer.finish()?;
+    Ok(count)
+}
+
+// The requested columns (all but the time columns by default), with their unit and type
+fn select_columns(db: &Connection, source: &Source, requested: Option<&[String]>) -> Result<Vec<Column>, ExportError> {
+    let mut available = Vec::new();
+    let mut stmt = db.prepare(&format!(
+        "SELECT info.name, info.type, units.unit
+         FROM pragma_table_info('{}') AS info
+         LEFT JOIN metric_units AS units ON units.table_name = ?1 AND units.column_name = info.name
+         ORDER BY info.cid",
+        source.name
+    ))?;
+    let mut rows = stmt.query([source.name])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        if source.time_columns.contains(&name.as_str()) {
+            continue;
+        }
+        let declared: String = row.get(1)?;
+        let kind = match declared.to_uppercase() {
+            t if t.starts_with("INT") => Kind::Integer,
+            t if t == "TEXT" => Kind::Text,
+            _ => Kind::Real,
+        };
+        let header = match row.get::<_, Option<String>>(2)? {
+            Some(unit) => format!("{} [{}]", name, unit),
+            None => name.clone(),
+        };
+        available.push(Column { name, header, kind });
+    }
+
+    let Some(requested) = requested else {
+        return Ok(available);
+    };
+    let mut columns = Vec::new();
+    for name in requested {
+        match available.iter().position(|c| &c.name == name) {
+            Some(i) => columns.push(available.remove(i)),
+            None => {
+                return Err(ExportError::Usage(format!(
+                    "unknown or repeated column '{}' of {} (available: {})",
+                    name,
+                    source.name,
+                    column_names(&available)
+                )))
+            }
+        }
+    }
+    Ok(columns)
+}
+
+fn column_names(columns: &[Column]) -> String {
+    columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+trait RowWriter {
+    fn write_row(&mut self, time_us: i64, precision: usize, values: &[Value]) -> Result<(), ExportError>;
+    fn finish(self: Box<Self>) -> Result<(), ExportError>;
+}
+
+struct CsvWriter<W: Write> {
+    output: W,
+}
+
+impl<W: Write> CsvWriter<W> {
+    fn new(mut output: W, columns: &[Column]) -> io::Result<Self> {
+        let mut header = vec!["time".to_string()];
+        header.extend(columns.iter().map(|c| csv_field(&c.header)));
+        writeln!(output, "{}", header.join(","))?;
+        Ok(CsvWriter { output })
+    }
+}
+
+impl<W: Write> RowWriter for CsvWriter<W> {
+    fn write_row(&mut self, time_us: i64, precision: usize, values: &[Value]) -> Result<(), ExportError> {
+        let mut fields = vec![iso8601(time_us, precision)];
+        fields.extend(values.iter().map(|value| match value {
+            Value::Null => String::new(),
+            Value::Integer(i) => i.to_string(),
+            Value::Real(r) => r.to_string(),
+            Value::Text(t) => csv_field(t),
+            Value::Blob(_) => String::new(),
+        }));
+        writeln!(self.output, "{}", fields.join(","))?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), ExportError> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+// Quotes a field containing a separator, a quote or a line break (RFC 4180)
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+struct JsonWriter<W: Write> {
+    output: W,
+    headers: Vec<String>,
+}
+
+impl<W: Write> RowWriter for JsonWriter<W> {
+    fn write_row(&mut self, time_us: i64, precision: usize, values: &[Value]) -> Result<(), ExportError> {
+        // Written by hand to keep the keys in column order
+        let mut line = format!("{{\"time\":\"{}\"", iso8601(time_us, precision));
+        for (header, value) in self.headers.iter().zip(values) {
+            let value = match value {
+                Value::Integer(i) => JsonValue::from(*i),
+                Value::Real(r) => Number::from_f64(*r).map_or(JsonValue::Null, JsonValue::Number),
+                Value::Text(t) => JsonValue::from(t.as_str()),
+                Value::Null | Value::Blob(_) => JsonValue::Null,
+            };
+            line.push_str(&format!(",{}:{}", JsonValue::from(header.as_str()), value));
+        }
+        line.push('}');
+        writeln!(self.output, "{}", line)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), ExportError> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+enum Builder {
+    Integer(Int64Builder),
+    Real(Float64Builder),
+    Text(StringBuilder),
+}
+
+struct ParquetWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: Arc<Schema>,
+    times: TimestampMicrosecondBuilder,
+    builders: Vec<Builder>,
+    rows: usize,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    fn new(output: W, columns: &[Column]) -> Result<Self, ExportError> {
+        let mut fields = vec![Field::new("time", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), false)];
+        fields.extend(columns.iter().map(|c| {
+            let data_type = match c.kind {
+                Kind::Integer => DataType::Int64,
+                Kind::Real => DataType::Float64,
+                Kind::Text => DataType::Utf8,
+            };
+            Field::new(c.header.as_str(), data_type, true)
+        }));
+        let schema = Arc::new(Schema::new(fields));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROW_GROUP_ROWS)
+            .build();
+        let writer = ArrowWriter::try_new(output, schema.clone(), Some(properties))?;
+        Ok(ParquetWriter {
+            writer,
+            schema,
+            times: new_time_builder(),
+            builders: columns.iter().map(|c| new_builder(c.kind)).collect(),
+            rows: 0,
+        })
+    }
+
+    // Writes the buffered rows as one record batch
+    fn flush_batch(&mut self) -> Result<(), ExportError> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let mut arrays: Vec<ArrayRef> = vec![Arc::new(self.times.finish())];
+        for builder in &mut self.builders {
+            arrays.push(match builder {
+                Builder::Integer(b) => Arc::new(b.finish()),
+                Builder::Real(b) => Arc::new(b.finish()),
+                Builder::Text(b) => Arc::new(b.finish()),
+            });
+        }
+        self.times = new_time_builder();
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays).map_err(ParquetError::from)?;
+        self.writer.write(&batch)?;
+        self.rows = 0;
+        Ok(())
+    }
+}
+
+fn new_time_builder() -> TimestampMicrosecondBuilder {
+    TimestampMicrosecondBuilder::with_capacity(BATCH_ROWS).with_timezone("UTC")
+}
+
+fn new_builder(kind: Kind) -> Builder {
+    match kind {
+        Kind::Integer => Builder::Integer(Int64Builder::with_capacity(BATCH_ROWS)),
+        Kind::Real => Builder::Real(Float64Builder::with_capacity(BATCH_ROWS)),
+        Kind::Text => Builder::Text(StringBuilder::new()),
+    }
+}
+
+impl<W: Write + Send> RowWriter for ParquetWriter<W> {
+    fn write_row(&mut self, time_us: i64, _precision: usize, values: &[Value]) -> Result<(), ExportError> {
+        self.times.append_value(time_us);
+        for (builder, value) in self.builders.iter_mut().zip(values) {
+            // SQLite values are loosely typed: anything not matching the column is null
+            match (builder, value) {
+                (Builder::Integer(b), Value::Integer(i)) => b.append_value(*i),
+                (Builder::Integer(b), Value::Real(r)) => b.append_value(*r as i64),
+                (Builder::Integer(b), _) => b.append_null(),
+                (Builder::Real(b), Value::Real(r)) => b.append_value(*r),
+                (Builder::Real(b), Value::Integer(i)) => b.append_value(*i as f64),
+                (Builder::Real(b), _) => b.append_null(),
+                (Builder::Text(b), Value::Text(t)) => b.append_value(t),
+                (Builder::Text(b), Value::Integer(i)) => b.append_value(i.to_string()),
+                (Builder::Text(b), Value::Real(r)) => b.append_value(r.to_string()),
+                (Builder::Text(b), _) => b.append_null(),
+            }
+        }
+        self.rows += 1;
+        if self.rows >= BATCH_ROWS {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), ExportError> {
+        self.flush_batch()?;
+        let mut output = self.writer.into_inner()?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+// "2026-10-18T08:00:00.123Z" for microseconds since the epoch, with `precision` fractional
+// digits
+fn iso8601(time_us: i64, precision: usize) -> String {
+    let secs = time_us.div_euclid(1_000_000);
+    let micros = time_us.rem_euclid(1_000_000);
+    let (year, month, day) = authlog::civil_from_days(secs.div_euclid(86_400));
+    let day_secs = secs.rem_euclid(86_400);
+    let mut time = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs % 3600 / 60,
+        day_secs % 60
+    );
+    if precision > 0 {
+        let fraction = format!("{:06}", micros);
+        time.push('.');
+        time.push_str(&fraction[..precision.min(6)]);
+    }
+    time.push('Z');
+    time
+}
+
+// "2026-10-17", "2026-10-17T08:00", "2026-10-17 08:00:30" in local time, the same with a
+// trailing Z in UTC, or a length ago ("30s", "24h", "7d")
+fn parse_time(text: &str, now: u64) -> Option<u64> {
+    if let Some(length) = history::parse_duration(text) {
+        return Some(now.saturating_sub(length));
+    }
+    let (text, utc) = match text.strip_suffix('Z') {
+        Some(text) => (text, true),
+        None => (text, false),
+    };
+    let (date, time) = text.split_once(['T', ' ']).unwrap_or((text, "00:00"));
+    let date: Vec<&str> = date.split('-').collect();
+    let time: Vec<&str> = time.split(':').collect();
+    if date.len() != 3 || !(2..=3).contains(&time.len()) {
+        return None;
+    }
+    let year: i64 = date[0].parse().ok()?;
+    let month: u32 = date[1].parse().ok()?;
+    let day: u32 = date[2].parse().ok()?;
+    let hour: u32 = time[0].parse().ok()?;
+    let minute: u32 = time[1].parse().ok()?;
+    let second: u32 = time.get(2).map_or(Some(0), |s| s.parse().ok())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let secs = if utc {
+        authlog::days_from_civil(year, month, day) * 86_400 + (hour * 3600 + minute * 60 + second) as i64
+    } else {
+        authlog::local_to_epoch(year, month as i32, day as i32, hour as i32, minute as i32, second as i32)?
+    };
+    u64::try_from(secs).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use tempfile::TempDir;
+
+    fn utc(year: i64, month: u32, day: u32, secs: i64) -> i64 {
+        authlog::days_from_civil(year, month, day) * 86_400 + secs
+    }
+
+    #[test]
+    fn iso8601_times() {
+        assert_eq!(iso8601(0, 3), "1970-01-01T00:00:00.000Z");
+        assert_eq!(iso8601(1_792_310_400_123_456, 3), "2026-10-18T08:00:00.123Z");
+        assert_eq!(iso8601(1_792_310_400_123_456, 6), "2026-10-18T08:00:00.123456Z");
+        assert_eq!(iso8601(1_792_310_400_123_456, 0), "2026-10-18T08:00:00Z");
+        // Before 1970 the fraction still counts forward from the second
+        assert_eq!(iso8601(-1, 6), "1969-12-31T23:59:59.999999Z");
+        assert_eq!(iso8601(-86_400_000_000 * 365 - 500_000, 3), "1968-12-31T23:59:59.500Z");
+        // Leap days
+        assert_eq!(iso8601(utc(2024, 2, 29, 43_200) * 1_000_000, 0), "2024-02-29T12:00:00Z");
+        assert_eq!(iso8601(utc(2000, 2, 29, 0) * 1_000_000, 0), "2000-02-29T00:00:00Z");
+        assert_eq!(iso8601(utc(2100, 2, 28, 86_400) * 1_000_000, 0), "2100-03-01T00:00:00Z");
+        assert_eq!(iso8601(utc(1968, 2, 29, 3661) * 1_000_000, 0), "1968-02-29T01:01:01Z");
+    }
+
+    #[test]
+    fn utc_and_local_times() {
+        let now = 1_792_310_400;
+        assert_eq!(parse_time("2026-10-17T08:00Z", now), Some(utc(2026, 10, 17, 8 * 3600) as u64));
+        assert_eq!(parse_time("2026-10-17 08:00:30Z", now), Some(utc(2026, 10, 17, 8 * 3600 + 30) as u64));
+        assert_eq!(parse_time("2026-10-17Z", now), Some(utc(2026, 10, 17, 0) as u64));
+        assert_eq!(
+            parse_time("2026-10-17T08:00", now),
+            authlog::local_to_epoch(2026, 10, 17, 8, 0, 0).map(|secs| secs as u64)
+        );
+        assert_eq!(
+            parse_time("2026-10-17", now),
+            authlog::local_to_epoch(2026, 10, 17, 0, 0, 0).map(|secs| secs as u64)
+        );
+        assert_eq!(parse_time("2024-02-29T00:00Z", now), Some(utc(2024, 2, 29, 0) as u64));
+        for invalid in ["2026-13-01", "2026-10-17T24:00Z", "2026-10-17T08", "17/10/2026", "1969-12-31Z", "soon"] {
+            assert_eq!(parse_time(invalid, now), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn lengths_ago() {
+        let now = 1_792_310_400;
+        assert_eq!(parse_time("30s", now), Some(now - 30));
+        assert_eq!(parse_time("24h", now), Some(now - 86_400));
+        assert_eq!(parse_time("7d", now), Some(now - 7 * 86_400));
+        let error = Options::parse(&["metrics".to_string(), "--from".to_string(), "30x".to_string()], now)
+            .err()
+            .unwrap();
+        assert!(error.starts_with("invalid time '30x'"), "{}", error);
+    }
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(csv_field("plain text"), "plain text");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn headers_carry_units() {
+        let db = migrations::test_database();
+        let metrics = &SOURCES[0];
+        let columns = select_columns(&db, metrics, None).unwrap();
+        let headers: Vec<&str> = columns.iter().map(|c| c.header.as_str()).collect();
+        assert!(!headers.iter().any(|h| h.starts_with("timestamp_ms")));
+        assert!(headers.contains(&"kaspad_memory_bytes [bytes]"));
+        assert!(headers.contains(&"kaspad_cpu_percent [%]"));
+
+        // No units for ssh_attempts
+        let requested = ["username".to_string(), "ip".to_string()];
+        let columns = select_columns(&db, &SOURCES[1], Some(&requested)).unwrap();
+        let headers: Vec<&str> = columns.iter().map(|c| c.header.as_str()).collect();
+        assert_eq!(headers, ["username", "ip"]);
+
+        let unknown = ["ip".to_string(), "ip".to_string()];
+        assert!(select_columns(&db, &SOURCES[1], Some(&unknown)).is_err());
+    }
+
+    #[test]
+    fn exports_csv() {
+        let db = migrations::test_database();
+        db.execute_batch(
+            "INSERT INTO samples (series_id, ts, value)
+            SELECT series.id, ts, value FROM series JOIN (
+                SELECT 'kaspad_cpu_percent' AS name, 1792310400000 AS ts, 12.5 AS value
+                UNION ALL SELECT 'kaspad_memory_bytes', 1792310400000, 1048576
+                UNION ALL SELECT 'kaspad_cpu_percent', 1792310402500, 13.0
+                UNION ALL SELECT 'kaspad_cpu_percent', 1792310460000, 14.0
+            ) AS rows ON rows.name = series.name;",
+        )
+        .unwrap();
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("metrics.csv");
+        let options = Options {
+            table: "metrics".to_string(),
+            from: Some(1_792_310_400),
+            to: Some(1_792_310_460),
+            columns: Some(vec!["kaspad_memory_bytes".to_string(), "kaspad_cpu_percent".to_string()]),
+            format: Format::Csv,
+            output: Some(path.display().to_string()),
+        };
+        assert_eq!(export(&db, &options).unwrap(), 2);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "time,kaspad_memory_bytes [bytes],kaspad_cpu_percent [%]\n\
+             2026-10-18T08:00:00.000Z,1048576,12.5\n\
+             2026-10-18T08:00:02.500Z,,13\n"
+        );
+    }
+}
@@ -0,0 +1,491 @@
+// Optional action engine banning brute-force IPs through nftables sets or iptables chains.
+// Bans are recorded in the `bans` table and lifted once they expire. Firewall commands go
+// through a CommandRunner, so dry-run mode and tests never touch the firewall.
+
+use std::io;
+use std::net::IpAddr;
+use std::process::Command;
+
+use rusqlite::{Connection, Result};
+
+pub trait CommandRunner: Send {
+    // Runs `program` with `args` and returns whether it exited successfully
+    fn run(&mut self, program: &str, args: &[String]) -> io::Result<bool>;
+}
+
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&mut self, program: &str, args: &[String]) -> io::Result<bool> {
+        Ok(Command::new(program).args(args).output()?.status.success())
+    }
+}
+
+// Commands kept by DryRunRunner
+const DRY_RUN_HISTORY: usize = 100;
+
+// Records the last commands instead of running them
+#[derive(Default)]
+pub struct DryRunRunner {
+    pub commands: Vec<String>,
+}
+
+impl CommandRunner for DryRunRunner {
+    fn run(&mut self, program: &str, args: &[String]) -> io::Result<bool> {
+        self.commands.push(format!("{} {}", program, args.join(" ")));
+        if self.commands.len() > DRY_RUN_HISTORY {
+            self.commands.remove(0);
+        }
+        Ok(true)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend {
+    // Elements of the `set` (IPv4) and `<set>6` (IPv6) sets of table `inet <table>`
+    Nftables { table: String, set: String },
+    // DROP rules in `chain`, jumped to from INPUT
+    Iptables { chain: String },
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Nftables { .. } => "nftables",
+            Backend::Iptables { .. } => "iptables",
+        }
+    }
+
+    // Commands creating the table/sets/chain the bans go into; each is idempotent
+    fn setup_commands(&self) -> Vec<(&'static str, Vec<String>)> {
+        match self {
+            Backend::Nftables { table, set } => vec![
+                ("nft", args(&["add", "table", "inet", table])),
+                ("nft", args(&["add", "set", "inet", table, set, "{ type ipv4_addr; }"])),
+                ("nft", args(&["add", "set", "inet", table, &format!("{}6", set), "{ type ipv6_addr; }"])),
+                (
+                    "nft",
+                    args(&[
+                        "add", "chain", "inet", table, "input",
+                        "{ type filter hook input priority -10; policy accept; }",
+                    ]),
+                ),
+                ("nft", args(&["flush", "chain", "inet", table, "input"])),
+                ("nft", args(&["add", "rule", "inet", table, "input", "ip", "saddr", &format!("@{}", set), "drop"])),
+                ("nft", args(&["add", "rule", "inet", table, "input", "ip6", "saddr", &format!("@{}6", set), "drop"])),
+            ],
+            Backend::Iptables { chain } => ["iptables", "ip6tables"]
+                .iter()
+                .flat_map(|program| {
+                    vec![
+                        (*program, args(&["-N", chain])),
+                        (*program, args(&["-F", chain])),
+                        (*program, args(&["-D", "INPUT", "-j", chain])),
+                        (*program, args(&["-I", "INPUT", "-j", chain])),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn ban_command(&self, ip: &IpAddr) -> (&'static str, Vec<String>) {
+        let address = ip.to_string();
+        match self {
+            Backend::Nftables { table, set } => (
+                "nft",
+                args(&["add", "element", "inet", table, &set_for(set, ip), &format!("{{ {} }}", address)]),
+            ),
+            Backend::Iptables { chain } => (
+                iptables_for(ip),
+                args(&["-I", chain, "-s", &address, "-j", "DROP"]),
+            ),
+        }
+    }
+
+    fn unban_command(&self, ip: &IpAddr) -> (&'static str, Vec<String>) {
+        let address = ip.to_string();
+        match self {
+            Backend::Nftables { table, set } => (
+                "nft",
+                args(&["delete", "element", "inet", table, &set_for(set, ip), &format!("{{ {} }}", address)]),
+            ),
+            Backend::Iptables { chain } => (
+                iptables_for(ip),
+                args(&["-D", chain, "-s", &address, "-j", "DROP"]),
+            ),
+        }
+    }
+}
+
+fn args(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+fn set_for(set: &str, ip: &IpAddr) -> String {
+    match ip {
+        IpAddr::V4(_) => set.to_string(),
+        IpAddr::V6(_) => format!("{}6", set),
+    }
+}
+
+fn iptables_for(ip: &IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "iptables",
+        IpAddr::V6(_) => "ip6tables",
+    }
+}
+
+// An address or network ("10.0.0.0/8", "2001:db8::/32", "192.168.1.10")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpNet {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u8>().ok()?)),
+            None => (value, None),
+        };
+        let address = address.trim().parse::<IpAddr>().ok()?;
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        if prefix > max {
+            return None;
+        }
+        Some(IpNet { address, prefix })
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.address, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BanConfig {
+    pub backend: Backend,
+    // Ban duration in seconds
+    pub duration: u64,
+    pub allowlist: Vec<IpNet>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BanAction {
+    Banned(String),
+    Unbanned(String),
+}
+
+pub struct BanEngine {
+    config: BanConfig,
+    runner: Box<dyn CommandRunner>,
+    set_up: bool,
+    // Last firewall command failure, shown in the TUI
+    pub last_error: Option<String>,
+}
+
+impl BanEngine {
+    pub fn new(config: BanConfig, runner: Box<dyn CommandRunner>) -> Self {
+        BanEngine {
+            config,
+            runner,
+            set_up: false,
+            last_error: None,
+        }
+    }
+
+    // Engine using the real firewall, or recording commands in dry-run mode
+    pub fn from_config(config: BanConfig) -> Self {
+        let runner: Box<dyn CommandRunner> = if config.dry_run {
+            Box::new(DryRunRunner::default())
+        } else {
+            Box::new(SystemRunner)
+        };
+        Self::new(config, runner)
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.config.dry_run
+    }
+
+    pub fn is_allowlisted(&self, ip: &IpAddr) -> bool {
+        self.config.allowlist.iter().any(|net| net.contains(ip))
+    }
+
+    // IPs currently banned according to the bans table, in dry-run mode or on the firewall:
+    // bans recorded in the other mode are not in effect
+    pub fn active_bans(db: &Connection, dry_run: bool) -> Result<Vec<String>> {
+        db.prepare_cached("SELECT DISTINCT ip FROM bans WHERE unbanned_at IS NULL AND dry_run = ?1")?
+            .query_map([dry_run], |row| row.get(0))?
+            .collect()
+    }
+
+    // Bans the new `offenders`, lifts expired bans, and the first time re-applies the
+    // bans still active in the database (the firewall may have been reset by a reboot)
+    pub fn apply(&mut self, db: &Connection, offenders: &[String], reason: &str, now: u64) -> Result<Vec<BanAction>> {
+        let mut actions = Vec::new();
+
+        if !self.set_up {
+            self.set_up = true;
+            for (program, args) in self.config.backend.setup_commands() {
+                // -N/-D fail when the chain/jump already exists/doesn't exist yet
+                self.run(program, &args, false);
+            }
+            for ip in Self::active_bans(db, self.config.dry_run)? {
+                if let Ok(address) = ip.parse::<IpAddr>() {
+                    let (program, args) = self.config.backend.ban_command(&address);
+                    self.run(program, &args, true);
+                }
+            }
+        }
+
+        let expired: Vec<(i64, String, bool)> = db
+            .prepare_cached("SELECT id, ip, dry_run FROM bans WHERE unbanned_at IS NULL AND expires_at <= ?1")?
+            .query_map([now as i64], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_>>()?;
+        for (id, ip, dry_run) in expired {
+            // A dry-run ban never reached the firewall
+            let on_firewall = !dry_run || self.config.dry_run;
+            if let (true, Ok(address)) = (on_firewall, ip.parse::<IpAddr>()) {
+                let (program, args) = self.config.backend.unban_command(&address);
+                self.run(program, &args, true);
+            }
+            db.execute(
+                "UPDATE bans SET unbanned_at = ?1 WHERE id = ?2",
+                rusqlite::params![now as i64, id],
+            )?;
+            actions.push(BanAction::Unbanned(ip));
+        }
+
+        let active = Self::active_bans(db, self.config.dry_run)?;
+        for ip in offenders {
+            // Only act on well-formed addresses coming from the logs
+            let address = match ip.parse::<IpAddr>() {
+                Ok(address) => address,
+                Err(_) => continue,
+            };
+            if active.contains(ip) || self.is_allowlisted(&address) {
+                continue;
+            }
+
+            let (program, args) = self.config.backend.ban_command(&address);
+            if !self.run(program, &args, true) {
+                continue;
+            }
+            db.execute(
+                "INSERT INTO bans (ip, banned_at, expires_at, reason, backend, dry_run)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                rusqlite::params![
+                    ip,
+                    now as i64,
+                    (now + self.config.duration) as i64,
+                    reason,
+                    self.config.backend.name(),
+                    self.config.dry_run,
+                ],
+            )?;
+            actions.push(BanAction::Banned(ip.clone()));
+        }
+
+        Ok(actions)
+    }
+
+    fn run(&mut self, program: &str, args: &[String], report_failure: bool) -> bool {
+        match self.runner.run(program, args) {
+            Ok(true) => true,
+            Ok(false) => {
+                if report_failure {
+                    self.last_error = Some(format!("{} {} failed", program, args.join(" ")));
+                }
+                false
+            }
+            Err(e) => {
+                self.last_error = Some(format!("{}: {}", program, e));
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // Records the commands where the test can see them
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl CommandRunner for Recorder {
+        fn run(&mut self, program: &str, args: &[String]) -> io::Result<bool> {
+            self.0.lock().unwrap().push(format!("{} {}", program, args.join(" ")));
+            Ok(true)
+        }
+    }
+
+    fn config(dry_run: bool) -> BanConfig {
+        BanConfig {
+            backend: Backend::Iptables {
+                chain: "MONITOR".to_string(),
+            },
+            duration: 3600,
+            allowlist: vec![IpNet::parse("10.0.0.0/8").unwrap(), IpNet::parse("2001:db8::1").unwrap()],
+            dry_run,
+        }
+    }
+
+    fn database() -> Connection {
+        crate::migrations::test_database()
+    }
+
+    fn engine(recorder: &Recorder) -> BanEngine {
+        let mut engine = BanEngine::new(config(false), Box::new(recorder.clone()));
+        // Setup commands are covered by the startup test
+        engine.set_up = true;
+        engine
+    }
+
+    fn offenders(ips: &[&str]) -> Vec<String> {
+        ips.iter().map(|ip| ip.to_string()).collect()
+    }
+
+    #[test]
+    fn skips_allowlisted_and_malformed_addresses() {
+        let (db, recorder) = (database(), Recorder::default());
+        let mut engine = engine(&recorder);
+        let ips = offenders(&["10.1.2.3", "2001:db8::1", "unknown", "192.0.2.7", "2001:db8::2"]);
+        let actions = engine.apply(&db, &ips, "brute force", 1000).unwrap();
+        assert_eq!(
+            actions,
+            [
+                BanAction::Banned("192.0.2.7".to_string()),
+                BanAction::Banned("2001:db8::2".to_string())
+            ]
+        );
+        assert_eq!(
+            recorder.take(),
+            [
+                "iptables -I MONITOR -s 192.0.2.7 -j DROP",
+                "ip6tables -I MONITOR -s 2001:db8::2 -j DROP"
+            ]
+        );
+        // Already banned
+        assert!(engine.apply(&db, &ips, "brute force", 1010).unwrap().is_empty());
+        assert!(recorder.take().is_empty());
+    }
+
+    #[test]
+    fn lifts_expired_bans() {
+        let (db, recorder) = (database(), Recorder::default());
+        let mut engine = engine(&recorder);
+        engine.apply(&db, &offenders(&["192.0.2.7"]), "brute force", 1000).unwrap();
+        recorder.take();
+
+        assert!(engine.apply(&db, &[], "", 4599).unwrap().is_empty());
+        let actions = engine.apply(&db, &[], "", 4600).unwrap();
+        assert_eq!(actions, [BanAction::Unbanned("192.0.2.7".to_string())]);
+        assert_eq!(recorder.take(), ["iptables -D MONITOR -s 192.0.2.7 -j DROP"]);
+        assert!(BanEngine::active_bans(&db, false).unwrap().is_empty());
+        let unbanned_at: i64 = db.query_row("SELECT unbanned_at FROM bans", [], |row| row.get(0)).unwrap();
+        assert_eq!(unbanned_at, 4600);
+
+        // Can be banned again
+        let actions = engine.apply(&db, &offenders(&["192.0.2.7"]), "brute force", 4700).unwrap();
+        assert_eq!(actions, [BanAction::Banned("192.0.2.7".to_string())]);
+    }
+
+    #[test]
+    fn reapplies_active_bans_on_startup() {
+        let db = database();
+        engine(&Recorder::default())
+            .apply(&db, &offenders(&["192.0.2.7", "192.0.2.8"]), "brute force", 1000)
+            .unwrap();
+
+        // A restarted monitor, after a reboot flushed the firewall
+        let recorder = Recorder::default();
+        let mut engine = BanEngine::new(config(false), Box::new(recorder.clone()));
+        assert!(engine.apply(&db, &[], "", 2000).unwrap().is_empty());
+        let commands = recorder.take();
+        let setup = config(false).backend.setup_commands().len();
+        assert_eq!(
+            commands[setup..],
+            [
+                "iptables -I MONITOR -s 192.0.2.7 -j DROP",
+                "iptables -I MONITOR -s 192.0.2.8 -j DROP"
+            ]
+        );
+        assert!(commands[..setup].iter().any(|c| c == "iptables -N MONITOR"));
+
+        // Only once
+        engine.apply(&db, &[], "", 2010).unwrap();
+        assert!(recorder.take().is_empty());
+    }
+
+    #[test]
+    fn dry_run_records_commands_instead_of_running_them() {
+        let db = database();
+        let mut engine = BanEngine::from_config(config(true));
+        let actions = engine.apply(&db, &offenders(&["192.0.2.7"]), "brute force", 1000).unwrap();
+        assert_eq!(actions, [BanAction::Banned("192.0.2.7".to_string())]);
+        assert_eq!(engine.last_error, None);
+        let (backend, dry_run): (String, bool) = db
+            .query_row("SELECT backend, dry_run FROM bans", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((backend.as_str(), dry_run), ("iptables", true));
+
+        assert_eq!(BanEngine::active_bans(&db, true).unwrap(), ["192.0.2.7"]);
+        assert!(BanEngine::active_bans(&db, false).unwrap().is_empty());
+
+        let mut runner = DryRunRunner::default();
+        for i in 0..DRY_RUN_HISTORY + 5 {
+            runner.run("iptables", &args(&["-I", "MONITOR", "-s", &format!("192.0.2.{}", i)])).unwrap();
+        }
+        assert_eq!(runner.commands.len(), DRY_RUN_HISTORY);
+        assert_eq!(runner.commands[0], "iptables -I MONITOR -s 192.0.2.5");
+    }
+
+    #[test]
+    fn dry_run_bans_are_not_applied_after_switching_to_the_firewall() {
+        let db = database();
+        BanEngine::from_config(config(true))
+            .apply(&db, &offenders(&["192.0.2.7", "192.0.2.8"]), "brute force", 1000)
+            .unwrap();
+
+        // Restarted without dry run: nothing to re-apply, and the offender is banned for real
+        let recorder = Recorder::default();
+        let mut engine = BanEngine::new(config(false), Box::new(recorder.clone()));
+        let actions = engine.apply(&db, &offenders(&["192.0.2.7"]), "brute force", 2000).unwrap();
+        assert_eq!(actions, [BanAction::Banned("192.0.2.7".to_string())]);
+        let setup = config(false).backend.setup_commands().len();
+        assert_eq!(recorder.take()[setup..], ["iptables -I MONITOR -s 192.0.2.7 -j DROP"]);
+        assert_eq!(BanEngine::active_bans(&db, false).unwrap(), ["192.0.2.7"]);
+
+        // The dry-run bans expire without touching the firewall
+        let actions = engine.apply(&db, &[], "", 4600).unwrap();
+        assert_eq!(
+            actions,
+            [
+                BanAction::Unbanned("192.0.2.7".to_string()),
+                BanAction::Unbanned("192.0.2.8".to_string())
+            ]
+        );
+        assert!(recorder.take().is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+// Offline GeoIP/ASN enrichment of SSH source IPs from local MaxMind (mmdb) databases
+
+use std::net::IpAddr;
+use std::path::Path;
+
+use maxminddb::{geoip2, Reader};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeoInfo {
+    // ISO 3166-1 alpha-2 code, e.g. "DE"
+    pub country: Option<String>,
+    pub city: Option<String>,
+    pub asn: Option<u32>,
+    pub org: Option<String>,
+}
+
+impl GeoInfo {
+    // Short form for the log panel: "DE Berlin, AS3320 Deutsche Telekom AG"
+    pub fn summary(&self) -> String {
+        let location = [self.country.as_deref(), self.city.as_deref()]
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let network = match (self.asn, &self.org) {
+            (Some(asn), Some(org)) => format!("AS{} {}", asn, org),
+            (Some(asn), None) => format!("AS{}", asn),
+            (None, Some(org)) => org.clone(),
+            (None, None) => String::new(),
+        };
+        [location, network]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// City/Country and ASN databases (e.g. GeoLite2-City.mmdb and GeoLite2-ASN.mmdb);
+// either may be missing
+pub struct GeoIp {
+    city: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    pub fn open(city_db: Option<&Path>, asn_db: Option<&Path>) -> Result<Self, String> {
+        let open = |path: &Path| {
+            Reader::open_readfile(path).map_err(|e| format!("{}: {}", path.display(), e))
+        };
+        Ok(GeoIp {
+            city: city_db.map(open).transpose()?,
+            asn: asn_db.map(open).transpose()?,
+        })
+    }
+
+    pub fn lookup(&self, ip: &str) -> Option<GeoInfo> {
+        let address = ip.parse::<IpAddr>().ok()?;
+        let mut info = GeoInfo::default();
+
+        if let Some(reader) = &self.city {
+            if let Ok(city) = reader.lookup::<geoip2::City>(address) {
+                info.country = city
+                    .country
+                    .and_then(|c| c.iso_code)
+                    .map(str::to_string);
+                info.city = city
+                    .city
+                    .and_then(|c| c.names)
+                    .and_then(|names| names.get("en").map(|n| n.to_string()));
+            }
+        }
+
+        if let Some(reader) = &self.asn {
+            if let Ok(asn) = reader.lookup::<geoip2::Asn>(address) {
+                info.asn = asn.autonomous_system_number;
+                info.org = asn.autonomous_system_organization.map(str::to_string);
+            }
+        }
+
+        if info == GeoInfo::default() {
+            None
+        } else {
+            Some(info)
+        }
+    }
+}
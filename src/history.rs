@@ -0,0 +1,389 @@
+// Historical view of the charts: a time range (5m to 30d, or custom) that either follows
+// now ("live") or ends at a point reached by panning. Short ranges read the raw samples,
+// longer ones the `metrics_1m`/`metrics_1h` rollups, completed from the raw samples for
+// the hour not rolled up yet.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use rusqlite::{params, Connection, Result};
+
+use crate::authlog;
+use crate::retention;
+use crate::sensors::SensorKind;
+use crate::series::{Labels, SeriesStore};
+
+// Ranges selected with the keys 1 to 6: label, length in seconds
+pub const RANGES: [(&str, u64); 6] = [
+    ("5m", 300),
+    ("1h", 3600),
+    ("6h", 6 * 3600),
+    ("24h", 86_400),
+    ("7d", 7 * 86_400),
+    ("30d", 30 * 86_400),
+];
+
+// Points of every chart, as (seconds, value)
+#[derive(Debug, Clone, Default)]
+pub struct ChartData {
+    pub cpu: Vec<(f64, f64)>,
+    pub mem: Vec<(f64, f64)>,
+    pub disk: Vec<(f64, f64)>,
+    pub received: Vec<(f64, f64)>,
+    pub transmitted: Vec<(f64, f64)>,
+    pub cpu_temp: Vec<(f64, f64)>,
+    pub nvme_temp: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resolution {
+    Raw,
+    Minute,
+    Hour,
+}
+
+impl Resolution {
+    // Raw samples up to 1h, minutes up to 24h, hours beyond
+    fn for_length(length: u64) -> Self {
+        if length <= 3600 {
+            Resolution::Raw
+        } else if length <= 86_400 {
+            Resolution::Minute
+        } else {
+            Resolution::Hour
+        }
+    }
+
+    // Seconds per point, 0 for every sample
+    fn step(&self) -> u64 {
+        match self {
+            Resolution::Raw => 0,
+            Resolution::Minute => 60,
+            Resolution::Hour => 3600,
+        }
+    }
+
+    fn table(&self) -> &'static str {
+        match self {
+            Resolution::Raw | Resolution::Minute => "metrics_1m",
+            Resolution::Hour => "metrics_1h",
+        }
+    }
+}
+
+const CPU_SENSORS: [SensorKind; 2] = [SensorKind::CpuPackage, SensorKind::CpuCore];
+const NVME_SENSORS: [SensorKind; 1] = [SensorKind::Nvme];
+
+pub struct HistoryView {
+    pub label: String,
+    length: u64,
+    // End of the window in seconds, None to follow now
+    end: Option<u64>,
+    resolution: Resolution,
+    pub data: ChartData,
+    loaded_at: Option<Instant>,
+}
+
+impl HistoryView {
+    pub fn new(label: &str, length: u64, end: Option<u64>) -> Self {
+        HistoryView {
+            label: label.to_string(),
+            length,
+            end,
+            resolution: Resolution::for_length(length),
+            data: ChartData::default(),
+            loaded_at: None,
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.end.is_none()
+    }
+
+    // [from, to) in seconds
+    pub fn window(&self, now: u64) -> (u64, u64) {
+        let to = self.end.unwrap_or(now);
+        (to.saturating_sub(self.length), to)
+    }
+
+    // Moves the window by half its length; reaching now switches back to live
+    pub fn pan(&mut self, forward: bool, now: u64) {
+        let (_, to) = self.window(now);
+        let step = (self.length / 2).max(1);
+        self.end = if forward {
+            Some(to + step).filter(|&end| end < now)
+        } else {
+            Some(to.saturating_sub(step))
+        };
+    }
+
+    pub fn go_live(&mut self) {
+        self.end = None;
+    }
+
+    // Whether a live view should be reloaded for the latest samples: every sample for raw
+    // samples, every minute otherwise
+    pub fn is_stale(&self) -> bool {
+        self.is_live()
+            && self
+                .loaded_at
+                .is_none_or(|loaded| loaded.elapsed().as_secs() >= self.resolution.step().min(60))
+    }
+
+    // "6h live" or "6h to 2026-10-17 12:00:00"
+    pub fn title(&self, now: u64) -> String {
+        match self.end {
+            None => format!("{} live", self.label),
+            Some(_) => format!("{} to {}", self.label, authlog::format_local_time(self.window(now).1)),
+        }
+    }
+
+    pub fn load(&mut self, db: &Connection, now: u64, raw_days: u64) -> Result<()> {
+        let (from, to) = self.window(now);
+        // Raw samples are only kept for `raw_days`
+        let resolution = if self.resolution == Resolution::Raw && from < now.saturating_sub(raw_days * 86_400) {
+            Resolution::Minute
+        } else {
+            self.resolution
+        };
+        let boundary = retention::rolled_up_until(db)?.unwrap_or(0);
+        let window = Window {
+            from,
+            to,
+            resolution,
+            boundary,
+        };
+        self.data = ChartData {
+            cpu: window.gauge(db, "kaspad_cpu_percent")?,
+            mem: window.gauge(db, "kaspad_memory_percent")?,
+            disk: window.gauge(db, "disk_usage_percent")?,
+            received: rates(&window.gauge(db, "network_received_bytes")?),
+            transmitted: rates(&window.gauge(db, "network_transmitted_bytes")?),
+            cpu_temp: window.hottest(db, &CPU_SENSORS)?,
+            nvme_temp: window.hottest(db, &NVME_SENSORS)?,
+        };
+        self.loaded_at = Some(Instant::now());
+        Ok(())
+    }
+}
+
+struct Window {
+    from: u64,
+    to: u64,
+    resolution: Resolution,
+    // Start of the hours not rolled up yet
+    boundary: u64,
+}
+
+impl Window {
+    // Parts of the window read from the rollups and from the raw tables
+    fn split(&self) -> ((u64, u64), (u64, u64)) {
+        if self.resolution == Resolution::Raw {
+            ((self.from, self.from), (self.from, self.to))
+        } else {
+            let boundary = self.boundary.clamp(self.from, self.to);
+            ((self.from, boundary), (boundary, self.to))
+        }
+    }
+
+    // Average of an unlabeled series per point
+    fn gauge(&self, db: &Connection, name: &str) -> Result<Vec<(f64, f64)>> {
+        let ((rollup_from, rollup_to), (raw_from, raw_to)) = self.split();
+        let mut points: Vec<(f64, f64)> = db
+            .prepare_cached(&format!(
+                "SELECT bucket, avg FROM {} WHERE metric = ?1 AND bucket >= ?2 AND bucket < ?3 ORDER BY bucket",
+                self.resolution.table()
+            ))?
+            .query_map(
+                params![format!("metrics.{}", name), rollup_from as i64, rollup_to as i64],
+                |row| Ok((row.get::<_, i64>(0)? as f64, row.get(1)?)),
+            )?
+            .collect::<Result<_>>()?;
+
+        if raw_from < raw_to {
+            if let Some(series) = SeriesStore::find(db, name, &Labels::new())? {
+                let step_ms = (self.resolution.step() * 1000).max(1) as i64;
+                let mut stmt = db.prepare_cached(
+                    "SELECT ts / ?2 * ?2, AVG(value) FROM samples
+                     WHERE series_id = ?1 AND ts >= ?3 AND ts < ?4 AND value IS NOT NULL
+                     GROUP BY 1
+                     ORDER BY 1",
+                )?;
+                let raw = stmt.query_map(
+                    params![series.id, step_ms, (raw_from * 1000) as i64, (raw_to * 1000) as i64],
+                    |row| Ok((row.get::<_, i64>(0)? as f64 / 1000.0, row.get(1)?)),
+                )?;
+                for point in raw {
+                    points.push(point?);
+                }
+            }
+        }
+        Ok(points)
+    }
+
+    // Hottest sensor of `kinds` per point
+    fn hottest(&self, db: &Connection, kinds: &[SensorKind]) -> Result<Vec<(f64, f64)>> {
+        let ((rollup_from, rollup_to), (raw_from, raw_to)) = self.split();
+        // The rollups only know sensors by label, whose kind is guessed again
+        let mut hottest: BTreeMap<i64, f64> = BTreeMap::new();
+        let mut stmt = db.prepare_cached(&format!(
+            "SELECT metric, bucket, max FROM {} WHERE metric LIKE 'sensors.%' AND bucket >= ?1 AND bucket < ?2",
+            self.resolution.table()
+        ))?;
+        let mut rows = stmt.query(params![rollup_from as i64, rollup_to as i64])?;
+        while let Some(row) = rows.next()? {
+            let metric: String = row.get(0)?;
+            let label = metric.trim_start_matches("sensors.");
+            if kinds.contains(&SensorKind::from_label(label)) {
+                let value: f64 = row.get(2)?;
+                let max = hottest.entry(row.get(1)?).or_insert(value);
+                *max = max.max(value);
+            }
+        }
+        let mut points: Vec<(f64, f64)> = hottest.into_iter().map(|(bucket, value)| (bucket as f64, value)).collect();
+
+        if raw_from < raw_to {
+            let kinds: Vec<String> = kinds.iter().map(|k| format!("'{}'", k.as_str())).collect();
+            let step_ms = (self.resolution.step() * 1000).max(1) as i64;
+            let mut stmt = db.prepare_cached(&format!(
+                "SELECT timestamp_ms / ?1 * ?1, MAX(value) FROM sensors
+                 WHERE kind IN ({}) AND timestamp_ms >= ?2 AND timestamp_ms < ?3 AND value IS NOT NULL
+                 GROUP BY 1
+                 ORDER BY 1",
+                kinds.join(", ")
+            ))?;
+            let raw = stmt.query_map(
+                params![step_ms, (raw_from * 1000) as i64, (raw_to * 1000) as i64],
+                |row| Ok((row.get::<_, i64>(0)? as f64 / 1000.0, row.get(1)?)),
+            )?;
+            for point in raw {
+                points.push(point?);
+            }
+        }
+        Ok(points)
+    }
+}
+
+// MiB/s between consecutive points of a byte counter, like the live network charts; 0
+// across counter resets
+pub fn rates(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    points
+        .windows(2)
+        .map(|pair| {
+            let ((prev_time, prev), (time, value)) = (pair[0], pair[1]);
+            let rate = if time > prev_time && value >= prev {
+                (value - prev) / ((time - prev_time) * 1_048_576.0)
+            } else {
+                0.0
+            };
+            (time, rate)
+        })
+        .collect()
+}
+
+// Parses a custom range: a length ("45m", "12h", "3d", "2w") ending now, or a local start
+// time followed by a length ("2026-10-17 08:00 6h", "2026-10-01 7d"). Returns the view
+pub fn parse_custom(input: &str) -> Option<HistoryView> {
+    let parts: Vec<&str> = input.split_whitespace().collect();
+    let (length_text, start) = match parts.as_slice() {
+        [length] => (*length, None),
+        [date, length] => (*length, Some(parse_start(date, "00:00")?)),
+        [date, time, length] => (*length, Some(parse_start(date, time)?)),
+        _ => return None,
+    };
+    let length = parse_length(length_text)?;
+    let end = match start {
+        Some(start) => Some(start.checked_add(length)?),
+        None => None,
+    };
+    Some(HistoryView::new(length_text, length, end))
+}
+
+// Length of a time range, at least a minute
+pub fn parse_length(text: &str) -> Option<u64> {
+    parse_duration(text).filter(|&length| length >= 60)
+}
+
+// "30s", "45m", "12h", "3d" or "2w" in seconds
+pub fn parse_duration(text: &str) -> Option<u64> {
+    let unit = match text.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86_400,
+        'w' => 7 * 86_400,
+        _ => return None,
+    };
+    let count: u64 = text[..text.len() - 1].parse().ok()?;
+    count.checked_mul(unit)
+}
+
+// "YYYY-MM-DD" and "HH:MM" in local time, to seconds since the epoch
+fn parse_start(date: &str, time: &str) -> Option<u64> {
+    let date: Vec<&str> = date.split('-').collect();
+    let time: Vec<&str> = time.split(':').collect();
+    if date.len() != 3 || time.len() != 2 {
+        return None;
+    }
+    let secs = authlog::local_to_epoch(
+        date[0].parse().ok()?,
+        date[1].parse().ok()?,
+        date[2].parse().ok()?,
+        time[0].parse().ok()?,
+        time[1].parse().ok()?,
+        0,
+    )?;
+    u64::try_from(secs).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths() {
+        assert_eq!(parse_length("90s"), Some(90));
+        assert_eq!(parse_length("45m"), Some(2700));
+        assert_eq!(parse_length("2w"), Some(14 * 86_400));
+        assert_eq!(parse_length("30s"), None);
+        assert_eq!(parse_length("h"), None);
+        assert_eq!(parse_length("12x"), None);
+        assert_eq!(parse_length(""), None);
+        assert_eq!(parse_length("99999999999999999999w"), None);
+        assert_eq!(parse_length("9999999999999999w"), None);
+    }
+
+    #[test]
+    fn custom_ranges() {
+        let view = parse_custom("12h").unwrap();
+        assert!(view.is_live());
+        assert_eq!(view.window(100_000), (100_000 - 12 * 3600, 100_000));
+
+        let start = authlog::local_to_epoch(2026, 10, 17, 8, 0, 0).unwrap() as u64;
+        let view = parse_custom("2026-10-17 08:00 6h").unwrap();
+        assert_eq!(view.window(start + 86_400), (start, start + 6 * 3600));
+        assert!(parse_custom("2026-10-17 8 6h").is_none());
+        assert!(parse_custom("2026-10-17 18446744073709551615s").is_none());
+    }
+
+    #[test]
+    fn hottest_raw_sensors() {
+        let db = crate::migrations::test_database();
+        db.execute_batch(
+            "INSERT INTO sensors (timestamp_ms, label, kind, value, unit) VALUES
+                (1792310400000, 'Package id 0', 'cpu_package', 55.0, '°C'),
+                (1792310400000, 'Core 0', 'cpu_core', 61.0, '°C'),
+                (1792310400500, 'Package id 0', 'cpu_package', 57.0, '°C'),
+                (1792310400500, 'Composite', 'nvme', 70.0, '°C'),
+                (1792310460000, 'Package id 0', 'cpu_package', 50.0, '°C');",
+        )
+        .unwrap();
+        let window = Window { from: 1_792_310_400, to: 1_792_310_460, resolution: Resolution::Raw, boundary: 0 };
+        // Two readings within a second are both kept
+        assert_eq!(
+            window.hottest(&db, &CPU_SENSORS).unwrap(),
+            [(1_792_310_400.0, 61.0), (1_792_310_400.5, 57.0)]
+        );
+        assert_eq!(window.hottest(&db, &NVME_SENSORS).unwrap(), [(1_792_310_400.5, 70.0)]);
+    }
+}
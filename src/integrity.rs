@@ -0,0 +1,634 @@
+// File integrity monitoring: SHA-256, size, mtime, mode and owner of the kaspad binary, its
+// systemd unit, authorized_keys files and configured paths are kept in `file_integrity`.
+// Files are stat'ed at every check and rehashed when their metadata changed (and at least
+// every FULL_RECHECK); changes become security events with a summary of what changed.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, OptionalExtension, Result};
+use sha2::{Digest, Sha256};
+
+use crate::authkeys;
+use crate::authlog::format_local_time;
+use crate::security::{Category, SecurityEvent, Severity};
+
+// Files are rehashed at least this often even when their metadata didn't change
+const FULL_RECHECK: u64 = 3600;
+
+// Text files up to this size are stored so changes can be summarized line by line
+const MAX_CONTENT_SIZE: u64 = 64 * 1024;
+
+// Directories holding systemd unit files, by precedence
+const UNIT_DIRS: [&str; 3] = ["/etc/systemd/system", "/lib/systemd/system", "/usr/lib/systemd/system"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Binary,
+    Unit,
+    AuthorizedKeys,
+    Other,
+}
+
+impl FileKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileKind::Binary => "binary",
+            FileKind::Unit => "unit",
+            FileKind::AuthorizedKeys => "authorized_keys",
+            FileKind::Other => "file",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchedFile {
+    // Key in file_integrity, shown in events
+    pub path: PathBuf,
+    // Where the file is read from (differs for binaries inside containers)
+    pub read_path: PathBuf,
+    pub kind: FileKind,
+    // User owning an authorized_keys file
+    pub owner: Option<String>,
+}
+
+impl WatchedFile {
+    fn new(path: PathBuf, kind: FileKind) -> Self {
+        WatchedFile {
+            read_path: path.clone(),
+            path,
+            kind,
+            owner: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileState {
+    present: bool,
+    sha256: Option<String>,
+    size: u64,
+    mtime: i64,
+    ctime: i64,
+    inode: u64,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    content: Option<Vec<u8>>,
+    hashed_at: u64,
+}
+
+impl FileState {
+    fn absent(now: u64) -> Self {
+        FileState {
+            present: false,
+            sha256: None,
+            size: 0,
+            mtime: 0,
+            ctime: 0,
+            inode: 0,
+            mode: 0,
+            uid: 0,
+            gid: 0,
+            content: None,
+            hashed_at: now,
+        }
+    }
+
+    // Same metadata: the content is assumed unchanged until the next full recheck
+    fn same_metadata(&self, metadata: &fs::Metadata) -> bool {
+        self.present
+            && self.size == metadata.len()
+            && self.mtime == metadata.mtime()
+            && self.ctime == metadata.ctime()
+            && self.inode == metadata.ino()
+            && self.mode == metadata.mode()
+            && self.uid == metadata.uid()
+            && self.gid == metadata.gid()
+    }
+}
+
+// None when the file can't be read (e.g. /root without root privileges)
+fn read_state(path: &Path, now: u64) -> Option<FileState> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Some(FileState::absent(now)),
+        Err(_) => return None,
+    };
+    let (sha256, content) = hash_file(path, metadata.len()).ok()?;
+    Some(FileState {
+        present: true,
+        sha256: Some(sha256),
+        size: metadata.len(),
+        mtime: metadata.mtime(),
+        ctime: metadata.ctime(),
+        inode: metadata.ino(),
+        mode: metadata.mode(),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        content,
+        hashed_at: now,
+    })
+}
+
+// Hex SHA-256 of the file, and its content when it is a small text file
+fn hash_file(path: &Path, size: u64) -> io::Result<(String, Option<Vec<u8>>)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut content = Vec::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        if size <= MAX_CONTENT_SIZE {
+            content.extend_from_slice(&buffer[..n]);
+        }
+    }
+    let hash = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    let content = if size <= MAX_CONTENT_SIZE && std::str::from_utf8(&content).is_ok() {
+        Some(content)
+    } else {
+        None
+    };
+    Ok((hash, content))
+}
+
+pub struct IntegrityWatch {
+    // kaspad process name, used to find its unit file
+    process_name: String,
+    authorized_keys: Option<Vec<PathBuf>>,
+    extra_paths: Vec<PathBuf>,
+}
+
+impl IntegrityWatch {
+    pub fn new(process_name: &str, authorized_keys: Option<Vec<PathBuf>>, extra_paths: Vec<PathBuf>) -> Self {
+        IntegrityWatch {
+            process_name: process_name.to_string(),
+            authorized_keys,
+            extra_paths,
+        }
+    }
+
+    // Watched files: the running kaspad binary (`exe` is its path and the path it can be read
+    // from), the unit file and drop-in, authorized_keys files and the configured paths
+    pub fn files(&self, exe: Option<(PathBuf, PathBuf)>) -> Vec<WatchedFile> {
+        let mut files = Vec::new();
+        if let Some((path, read_path)) = exe {
+            files.push(WatchedFile {
+                path,
+                read_path,
+                kind: FileKind::Binary,
+                owner: None,
+            });
+        }
+        for dir in UNIT_DIRS {
+            let unit = Path::new(dir).join(format!("{}.service", self.process_name));
+            files.push(WatchedFile::new(unit, FileKind::Unit));
+        }
+        files.push(WatchedFile::new(
+            PathBuf::from(format!("/etc/systemd/system/{}.service.d/override.conf", self.process_name)),
+            FileKind::Unit,
+        ));
+        for (path, owner) in authkeys::files(self.authorized_keys.as_deref()) {
+            let mut file = WatchedFile::new(path, FileKind::AuthorizedKeys);
+            file.owner = owner;
+            files.push(file);
+        }
+        for path in &self.extra_paths {
+            files.push(WatchedFile::new(path.clone(), FileKind::Other));
+        }
+        files
+    }
+
+    // Checks `files` against file_integrity; the first check of a file only records it
+    pub fn check(&self, db: &Connection, files: &[WatchedFile], now: u64) -> Result<Vec<SecurityEvent>> {
+        let mut events = Vec::new();
+        let mut seen = HashMap::new();
+        for file in files {
+            // Unit candidates and drop-ins may list the same path twice
+            if seen.insert(file.path.clone(), ()).is_some() {
+                continue;
+            }
+            let key = file.path.display().to_string();
+            let previous = load(db, &key)?;
+
+            if let (Some(previous), Ok(metadata)) = (&previous, fs::metadata(&file.read_path)) {
+                if previous.same_metadata(&metadata) && now < previous.hashed_at + FULL_RECHECK {
+                    continue;
+                }
+            }
+            if let (Some(previous), Err(e)) = (&previous, fs::metadata(&file.read_path)) {
+                if e.kind() == io::ErrorKind::NotFound && !previous.present {
+                    continue;
+                }
+            }
+
+            let current = match read_state(&file.read_path, now) {
+                Some(state) => state,
+                None => continue,
+            };
+            let event = previous.as_ref().and_then(|previous| change_event(file, previous, &current));
+            save(db, &key, file.kind, &current, now, event.is_some())?;
+            events.extend(event);
+        }
+        Ok(events)
+    }
+}
+
+fn load(db: &Connection, path: &str) -> Result<Option<FileState>> {
+    db.prepare_cached(
+        "SELECT present, sha256, size, mtime, ctime, inode, mode, uid, gid, content, hashed_at
+         FROM file_integrity WHERE path = ?1",
+    )?
+    .query_row([path], |row| {
+        Ok(FileState {
+            present: row.get(0)?,
+            sha256: row.get(1)?,
+            size: row.get::<_, i64>(2)? as u64,
+            mtime: row.get(3)?,
+            ctime: row.get(4)?,
+            inode: row.get::<_, i64>(5)? as u64,
+            mode: row.get(6)?,
+            uid: row.get(7)?,
+            gid: row.get(8)?,
+            content: row.get(9)?,
+            hashed_at: row.get::<_, i64>(10)? as u64,
+        })
+    })
+    .optional()
+}
+
+fn save(db: &Connection, path: &str, kind: FileKind, state: &FileState, now: u64, changed: bool) -> Result<()> {
+    db.prepare_cached(
+        "INSERT INTO file_integrity (
+            path, kind, present, sha256, size, mtime, ctime, inode, mode, uid, gid, content,
+            hashed_at, checked_at, changed_at
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?13, ?14)
+        ON CONFLICT (path) DO UPDATE SET
+            kind = excluded.kind, present = excluded.present, sha256 = excluded.sha256,
+            size = excluded.size, mtime = excluded.mtime, ctime = excluded.ctime,
+            inode = excluded.inode, mode = excluded.mode, uid = excluded.uid, gid = excluded.gid,
+            content = excluded.content, hashed_at = excluded.hashed_at,
+            checked_at = excluded.checked_at,
+            changed_at = COALESCE(excluded.changed_at, changed_at)",
+    )?
+    .execute(rusqlite::params![
+        path,
+        kind.as_str(),
+        state.present,
+        state.sha256,
+        state.size as i64,
+        state.mtime,
+        state.ctime,
+        state.inode as i64,
+        state.mode,
+        state.uid,
+        state.gid,
+        state.content,
+        now as i64,
+        if changed { Some(now as i64) } else { None },
+    ])?;
+    Ok(())
+}
+
+// Security event describing the difference between two states, None when nothing that
+// matters changed (e.g. only the mtime or ctime)
+fn change_event(file: &WatchedFile, previous: &FileState, current: &FileState) -> Option<SecurityEvent> {
+    let authorized_keys = file.kind == FileKind::AuthorizedKeys;
+    let (category, prefix) = if authorized_keys {
+        (Category::AuthorizedKeys, "authorized_keys")
+    } else {
+        (Category::Integrity, "file")
+    };
+
+    let (name, severity, details) = match (previous.present, current.present) {
+        (false, false) => return None,
+        (false, true) => (
+            "created",
+            if authorized_keys { Severity::Critical } else { Severity::Warning },
+            format!("size {}, sha256 {}", format_size(current.size), short_hash(&current.sha256)),
+        ),
+        (true, false) => ("removed", Severity::Warning, format!("was sha256 {}", short_hash(&previous.sha256))),
+        (true, true) if previous.sha256 != current.sha256 => {
+            let (added, removed, summary) = line_diff(previous, current);
+            let mut parts = Vec::new();
+            match summary {
+                Some(summary) => parts.push(summary),
+                None => parts.push(format!(
+                    "sha256 {} → {}",
+                    short_hash(&previous.sha256),
+                    short_hash(&current.sha256)
+                )),
+            }
+            parts.extend(metadata_changes(previous, current));
+            let severity = match file.kind {
+                // Added keys are how an intruder keeps access
+                FileKind::AuthorizedKeys if added > 0 || removed == 0 => Severity::Critical,
+                FileKind::AuthorizedKeys => Severity::Warning,
+                FileKind::Binary | FileKind::Unit => Severity::Critical,
+                FileKind::Other => Severity::Warning,
+            };
+            ("changed", severity, parts.join(", "))
+        }
+        (true, true) => {
+            let changes = metadata_changes(previous, current);
+            let permission_changes: Vec<String> = changes
+                .into_iter()
+                .filter(|c| c.starts_with("mode") || c.starts_with("owner"))
+                .collect();
+            if permission_changes.is_empty() {
+                return None;
+            }
+            // A new setuid/setgid bit is a classic backdoor
+            let setuid_added = current.mode & !previous.mode & 0o6000 != 0;
+            let severity = if setuid_added { Severity::Critical } else { Severity::Warning };
+            ("permissions_changed", severity, permission_changes.join(", "))
+        }
+    };
+
+    let mut event = SecurityEvent::new(category, event_name(prefix, name), severity, "Info");
+    event.target = match &file.owner {
+        Some(owner) => Some(owner.clone()),
+        None if !authorized_keys => Some(file.kind.as_str().to_string()),
+        None => None,
+    };
+    event.source = Some(file.path.display().to_string());
+    event.details = Some(details);
+    Some(event)
+}
+
+fn event_name(prefix: &str, name: &str) -> &'static str {
+    match (prefix, name) {
+        ("authorized_keys", "created") => "authorized_keys_created",
+        ("authorized_keys", "removed") => "authorized_keys_removed",
+        ("authorized_keys", "changed") => "authorized_keys_changed",
+        ("authorized_keys", _) => "authorized_keys_permissions_changed",
+        (_, "created") => "file_created",
+        (_, "removed") => "file_removed",
+        (_, "changed") => "file_modified",
+        _ => "file_permissions_changed",
+    }
+}
+
+// "size 31.2 MB → 31.4 MB", "mtime ... → ...", "mode 755 → 4755", "owner 0:0 → 1000:1000"
+fn metadata_changes(previous: &FileState, current: &FileState) -> Vec<String> {
+    let mut changes = Vec::new();
+    if previous.size != current.size {
+        changes.push(format!("size {} → {}", format_size(previous.size), format_size(current.size)));
+    }
+    if previous.mtime != current.mtime {
+        changes.push(format!(
+            "mtime {} → {}",
+            format_local_time(previous.mtime.max(0) as u64),
+            format_local_time(current.mtime.max(0) as u64)
+        ));
+    }
+    if previous.mode & 0o7777 != current.mode & 0o7777 {
+        changes.push(format!("mode {:o} → {:o}", previous.mode & 0o7777, current.mode & 0o7777));
+    }
+    if (previous.uid, previous.gid) != (current.uid, current.gid) {
+        changes.push(format!(
+            "owner {}:{} → {}:{}",
+            previous.uid, previous.gid, current.uid, current.gid
+        ));
+    }
+    changes
+}
+
+// Lines added and removed between two small text files, with a summary such as
+// "+1 -0 lines: + ssh-ed25519 AAAAC3Nz… alice@laptop"
+fn line_diff(previous: &FileState, current: &FileState) -> (usize, usize, Option<String>) {
+    let (old, new) = match (&previous.content, &current.content) {
+        (Some(old), Some(new)) => (String::from_utf8_lossy(old), String::from_utf8_lossy(new)),
+        _ => return (0, 0, None),
+    };
+
+    let mut counts: HashMap<&str, i64> = HashMap::new();
+    for line in old.lines() {
+        *counts.entry(line).or_default() -= 1;
+    }
+    for line in new.lines() {
+        *counts.entry(line).or_default() += 1;
+    }
+    // Repeated lines count once per added or removed copy
+    let added: Vec<&str> = new.lines().filter(|l| take(&mut counts, l, 1)).collect();
+    let removed: Vec<&str> = old.lines().filter(|l| take(&mut counts, l, -1)).collect();
+
+    let mut summary = format!("+{} -{} lines", added.len(), removed.len());
+    if let Some(line) = added.first() {
+        summary.push_str(&format!(": + {}", abbreviate(line)));
+    } else if let Some(line) = removed.first() {
+        summary.push_str(&format!(": - {}", abbreviate(line)));
+    }
+    (added.len(), removed.len(), Some(summary))
+}
+
+// Takes one copy of `line` from its count when the count has the sign of `sign`
+fn take<'a>(counts: &mut HashMap<&'a str, i64>, line: &'a str, sign: i64) -> bool {
+    match counts.get_mut(line) {
+        Some(count) if *count * sign > 0 => {
+            *count -= sign;
+            true
+        }
+        _ => false,
+    }
+}
+
+// Shortens long tokens (key blobs, hashes) so a line fits in the log panel
+fn abbreviate(line: &str) -> String {
+    line.split_whitespace()
+        .map(|token| {
+            if token.chars().count() > 24 {
+                format!("{}…", token.chars().take(12).collect::<String>())
+            } else {
+                token.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn short_hash(hash: &Option<String>) -> String {
+    match hash {
+        Some(hash) => format!("{}…", &hash[..hash.len().min(12)]),
+        None => "-".to_string(),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    const NOW: u64 = 1_792_310_400;
+    const KEY_1: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIGJ0cmFjZWQgYnkgYWxpY2U alice@laptop";
+    const KEY_2: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHNvbWVvbmUgZWxzZSdzIGtleQ bob@desktop";
+
+    struct Fixture {
+        _dir: TempDir,
+        db: Connection,
+        watch: IntegrityWatch,
+        file: WatchedFile,
+    }
+
+    impl Fixture {
+        fn new(kind: FileKind, content: Option<&str>) -> Self {
+            let dir = TempDir::new().unwrap();
+            let file = WatchedFile::new(dir.path().join("watched"), kind);
+            if let Some(content) = content {
+                fs::write(&file.path, content).unwrap();
+            }
+            let fixture = Fixture {
+                _dir: dir,
+                db: migrations::test_database(),
+                watch: IntegrityWatch::new("kaspad", Some(Vec::new()), Vec::new()),
+                file,
+            };
+            // The first check only records the file
+            assert!(fixture.check(NOW).is_empty());
+            fixture
+        }
+
+        fn check(&self, now: u64) -> Vec<(String, Severity, String)> {
+            self.watch
+                .check(&self.db, std::slice::from_ref(&self.file), now)
+                .unwrap()
+                .into_iter()
+                .map(|event| (event.event, event.severity, event.details.unwrap_or_default()))
+                .collect()
+        }
+
+        fn write(&self, content: &str) {
+            fs::write(&self.file.path, content).unwrap();
+        }
+
+        fn chmod(&self, mode: u32) {
+            fs::set_permissions(&self.file.path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+    }
+
+    fn only(events: Vec<(String, Severity, String)>) -> (String, Severity, String) {
+        assert_eq!(events.len(), 1, "{:?}", events);
+        events.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn records_a_file_on_the_first_check() {
+        let fixture = Fixture::new(FileKind::Other, Some("a\n"));
+        let (present, size): (bool, i64) = fixture
+            .db
+            .query_row("SELECT present, size FROM file_integrity", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((present, size), (true, 2));
+        assert!(fixture.check(NOW + 60).is_empty());
+    }
+
+    #[test]
+    fn created_and_removed_files() {
+        let fixture = Fixture::new(FileKind::Other, None);
+        fixture.write("a\n");
+        let (event, severity, details) = only(fixture.check(NOW + 60));
+        assert_eq!((event.as_str(), severity), ("file_created", Severity::Warning));
+        assert!(details.starts_with("size 2 B, sha256 "), "{}", details);
+
+        fs::remove_file(&fixture.file.path).unwrap();
+        let (event, severity, _) = only(fixture.check(NOW + 120));
+        assert_eq!((event.as_str(), severity), ("file_removed", Severity::Warning));
+        // Still absent: nothing new
+        assert!(fixture.check(NOW + 180).is_empty());
+
+        let keys = Fixture::new(FileKind::AuthorizedKeys, None);
+        keys.write(&format!("{}\n", KEY_1));
+        let (event, severity, _) = only(keys.check(NOW + 60));
+        assert_eq!((event.as_str(), severity), ("authorized_keys_created", Severity::Critical));
+    }
+
+    #[test]
+    fn summarizes_modified_lines() {
+        let fixture = Fixture::new(FileKind::AuthorizedKeys, Some(&format!("{}\n", KEY_1)));
+        fixture.write(&format!("{}\n{}\n", KEY_1, KEY_2));
+        let (event, severity, details) = only(fixture.check(NOW + 60));
+        assert_eq!((event.as_str(), severity), ("authorized_keys_changed", Severity::Critical));
+        assert!(
+            details.starts_with("+1 -0 lines: + ssh-ed25519 AAAAC3NzaC1l… bob@desktop, size "),
+            "{}",
+            details
+        );
+
+        // Only removing a key is less alarming
+        fixture.write(&format!("{}\n", KEY_2));
+        let (_, severity, details) = only(fixture.check(NOW + 120));
+        assert_eq!(severity, Severity::Warning);
+        assert!(details.starts_with("+0 -1 lines: - ssh-ed25519 AAAAC3NzaC1l… alice@laptop"), "{}", details);
+
+        // Binaries have no line summary, and any change is critical
+        let binary = Fixture::new(FileKind::Binary, Some("\u{7f}ELF one"));
+        fs::write(&binary.file.path, [0x7f, b'E', b'L', b'F', 0xff, 0xfe]).unwrap();
+        let (event, severity, details) = only(binary.check(NOW + 60));
+        assert_eq!((event.as_str(), severity), ("file_modified", Severity::Critical));
+        assert!(details.starts_with("sha256 "), "{}", details);
+    }
+
+    #[test]
+    fn permission_changes() {
+        let fixture = Fixture::new(FileKind::Other, Some("a\n"));
+        fixture.chmod(0o644);
+        fixture.check(NOW + 60);
+        fixture.chmod(0o600);
+        let (event, severity, details) = only(fixture.check(NOW + 120));
+        assert_eq!((event.as_str(), severity), ("file_permissions_changed", Severity::Warning));
+        assert_eq!(details, "mode 644 → 600");
+
+        // A new setuid bit is a backdoor
+        fixture.chmod(0o4755);
+        let (_, severity, details) = only(fixture.check(NOW + 180));
+        assert_eq!((severity, details.as_str()), (Severity::Critical, "mode 600 → 4755"));
+    }
+
+    #[test]
+    fn rehashes_unchanged_metadata_only_after_the_full_recheck() {
+        let fixture = Fixture::new(FileKind::Other, Some("a\n"));
+        // Pretend the content was "b\n" and changed without touching the metadata
+        fixture.db.execute("UPDATE file_integrity SET sha256 = 'stale', content = X'620a'", []).unwrap();
+        assert!(fixture.check(NOW + 60).is_empty());
+
+        let (event, _, details) = only(fixture.check(NOW + FULL_RECHECK));
+        assert_eq!((event.as_str(), details.as_str()), ("file_modified", "+1 -1 lines: + a"));
+    }
+
+    #[test]
+    fn line_summaries() {
+        let state = |content: &str| FileState {
+            content: Some(content.as_bytes().to_vec()),
+            ..FileState::absent(NOW)
+        };
+        assert_eq!(
+            line_diff(&state("a\nb\nb\n"), &state("b\nc\nd\n")),
+            (2, 2, Some("+2 -2 lines: + c".to_string()))
+        );
+        assert_eq!(line_diff(&state("a\n"), &FileState::absent(NOW)), (0, 0, None));
+        assert_eq!(abbreviate("from=\"10.0.0.1\"  ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAABAQ"), "from=\"10.0.0.1\" ssh-rsa AAAAB3NzaC1y…");
+    }
+}
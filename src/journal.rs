@@ -0,0 +1,118 @@
+// Incremental reader for journald entries, using `journalctl -o json` and cursors
+
+use std::io;
+use std::process::Command;
+
+use serde_json::Value;
+
+// Entries read on the very first run, when no cursor was persisted yet
+const INITIAL_BACKLOG: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub cursor: String,
+    // Microseconds since the epoch, as recorded by journald
+    pub realtime_us: u64,
+    // SYSLOG_IDENTIFIER and _PID, e.g. "sudo" and "1234"
+    pub identifier: Option<String>,
+    pub pid: Option<String>,
+    pub message: String,
+}
+
+impl JournalEntry {
+    // The entry as a syslog line without date and host: "sudo[1234]: message"
+    pub fn syslog_message(&self) -> String {
+        match (&self.identifier, &self.pid) {
+            (Some(identifier), Some(pid)) => format!("{}[{}]: {}", identifier, pid, self.message),
+            (Some(identifier), None) => format!("{}: {}", identifier, self.message),
+            _ => self.message.clone(),
+        }
+    }
+}
+
+// Syslog facilities of authentication messages: auth (sshd, su, useradd) and authpriv
+// (sudo, login, PAM)
+const AUTH_FACILITIES: [&str; 2] = ["4", "10"];
+
+// Reads the entries of `units` and of the auth facilities written after `cursor` (or the last INITIAL_BACKLOG
+// entries when there is no cursor). A cursor journald no longer knows (rotated or
+// vacuumed journal) falls back to the initial backlog.
+pub fn read_entries(units: &[String], cursor: Option<&str>) -> io::Result<Vec<JournalEntry>> {
+    match run_journalctl(units, cursor) {
+        Ok(entries) => Ok(entries),
+        Err(_) if cursor.is_some() => run_journalctl(units, None),
+        Err(e) => Err(e),
+    }
+}
+
+fn run_journalctl(units: &[String], cursor: Option<&str>) -> io::Result<Vec<JournalEntry>> {
+    let mut command = Command::new("journalctl");
+    command.args(["-o", "json", "--no-pager"]);
+    // Matches on different fields are ANDed unless separated by "+"
+    let mut matches: Vec<String> = units
+        .iter()
+        .map(|unit| {
+            if unit.contains('.') {
+                format!("_SYSTEMD_UNIT={}", unit)
+            } else {
+                format!("_SYSTEMD_UNIT={}.service", unit)
+            }
+        })
+        .collect();
+    matches.extend(AUTH_FACILITIES.iter().map(|f| format!("SYSLOG_FACILITY={}", f)));
+    for (i, m) in matches.iter().enumerate() {
+        if i > 0 {
+            command.arg("+");
+        }
+        command.arg(m);
+    }
+    match cursor {
+        Some(cursor) => command.arg(format!("--after-cursor={}", cursor)),
+        None => command.args(["-n", &INITIAL_BACKLOG.to_string()]),
+    };
+
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_entry)
+        .collect())
+}
+
+// Parses one line of `journalctl -o json`
+pub fn parse_entry(line: &str) -> Option<JournalEntry> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let cursor = value.get("__CURSOR")?.as_str()?.to_string();
+    let realtime_us = value
+        .get("__REALTIME_TIMESTAMP")?
+        .as_str()?
+        .parse::<u64>()
+        .ok()?;
+    let message = match value.get("MESSAGE")? {
+        Value::String(s) => s.clone(),
+        // Messages that aren't valid UTF-8 are exported as byte arrays
+        Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .filter_map(|b| b.as_u64().map(|b| b as u8))
+                .collect();
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+        _ => return None,
+    };
+
+    let field = |name: &str| value.get(name).and_then(|v| v.as_str()).map(str::to_string);
+
+    Some(JournalEntry {
+        cursor,
+        realtime_us,
+        identifier: field("SYSLOG_IDENTIFIER"),
+        pid: field("_PID"),
+        message,
+    })
+}
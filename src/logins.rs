@@ -0,0 +1,437 @@
+// Alerts on successful SSH logins from an IP, subnet or user outside the known baseline.
+// The baseline is kept in `known_logins` and seeded from the logins already stored; alerts
+// stay in `login_alerts` until acknowledged, and trusting one adds its IP, subnet and user
+// to the baseline.
+
+use std::net::IpAddr;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use rusqlite::{Connection, OptionalExtension, Result};
+
+use crate::db;
+
+// Prefixes grouping addresses into "subnets" for the baseline
+const IPV4_SUBNET_PREFIX: u32 = 24;
+const IPV6_SUBNET_PREFIX: u32 = 64;
+
+// State key set once the baseline has been seeded from ssh_attempts
+const SEEDED_STATE_KEY: &str = "login_baseline_seeded";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Novelty {
+    Ip,
+    Subnet,
+    User,
+}
+
+impl Novelty {
+    // Kind column of known_logins
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Novelty::Ip => "ip",
+            Novelty::Subnet => "subnet",
+            Novelty::User => "user",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Novelty::Ip => "new IP",
+            Novelty::Subnet => "new subnet",
+            Novelty::User => "new user",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ip" => Some(Novelty::Ip),
+            "subnet" => Some(Novelty::Subnet),
+            "user" => Some(Novelty::User),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginAlert {
+    pub id: i64,
+    pub timestamp: u64,
+    pub ip: String,
+    pub subnet: Option<String>,
+    pub username: String,
+    pub country: Option<String>,
+    // What wasn't in the baseline
+    pub novelties: Vec<Novelty>,
+}
+
+impl LoginAlert {
+    // "new IP, new subnet"
+    pub fn reasons(&self) -> String {
+        self.novelties
+            .iter()
+            .map(|n| n.label())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// "203.0.113.0/24" or "2001:db8:1:2::/64"
+pub fn subnet_of(ip: &str) -> Option<String> {
+    match ip.parse::<IpAddr>().ok()? {
+        IpAddr::V4(address) => {
+            let mask = u32::MAX << (32 - IPV4_SUBNET_PREFIX);
+            let network = std::net::Ipv4Addr::from(u32::from(address) & mask);
+            Some(format!("{}/{}", network, IPV4_SUBNET_PREFIX))
+        }
+        IpAddr::V6(address) => {
+            let mask = u128::MAX << (128 - IPV6_SUBNET_PREFIX);
+            let network = std::net::Ipv6Addr::from(u128::from(address) & mask);
+            Some(format!("{}/{}", network, IPV6_SUBNET_PREFIX))
+        }
+    }
+}
+
+pub struct LoginWatch {
+    // Shell command run for each new alert
+    hook: Option<String>,
+    // Unacknowledged alerts, oldest first
+    pub pending: Vec<LoginAlert>,
+    // Last hook failure, shown in the TUI
+    pub last_error: Option<String>,
+}
+
+impl LoginWatch {
+    pub fn new(hook: Option<String>) -> Self {
+        LoginWatch {
+            hook,
+            pending: Vec::new(),
+            last_error: None,
+        }
+    }
+
+    // The first time, takes every successful login already in ssh_attempts as known, so
+    // upgrading doesn't raise an alert for each past login
+    pub fn seed(db: &Connection) -> Result<()> {
+        if db::get_state(db, SEEDED_STATE_KEY)?.is_some() {
+            return Ok(());
+        }
+
+        let logins: Vec<(String, Option<String>, i64, i64)> = db
+            .prepare(
+                "SELECT ip, username, MIN(timestamp), MAX(timestamp)
+                 FROM ssh_attempts
+                 WHERE event = 'accepted'
+                 GROUP BY ip, username",
+            )?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+            .collect::<Result<_>>()?;
+        for (ip, username, first_seen, last_seen) in logins {
+            for (kind, value) in baseline_entries(&ip, username.as_deref()) {
+                remember(db, kind, &value, first_seen, last_seen, "history")?;
+            }
+        }
+
+        db::set_state(db, SEEDED_STATE_KEY, "1")
+    }
+
+    pub fn load_pending(&mut self, db: &Connection) -> Result<()> {
+        self.pending = db
+            .prepare(
+                "SELECT id, timestamp, ip, subnet, username, country, reasons
+                 FROM login_alerts
+                 WHERE acknowledged_at IS NULL
+                 ORDER BY timestamp, id",
+            )?
+            .query_map([], |row| {
+                Ok(LoginAlert {
+                    id: row.get(0)?,
+                    timestamp: row.get::<_, i64>(1)? as u64,
+                    ip: row.get(2)?,
+                    subnet: row.get(3)?,
+                    username: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    country: row.get(5)?,
+                    novelties: row
+                        .get::<_, Option<String>>(6)?
+                        .unwrap_or_default()
+                        .split(',')
+                        .filter_map(Novelty::parse)
+                        .collect(),
+                })
+            })?
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    // Checks a successful login against the baseline, raising an alert when its IP, subnet
+    // or user is unknown
+    pub fn check(
+        &mut self,
+        db: &Connection,
+        ip: &str,
+        username: Option<&str>,
+        country: Option<&str>,
+        timestamp: u64,
+    ) -> Result<()> {
+        let mut novelties = Vec::new();
+        for (kind, value) in baseline_entries(ip, username) {
+            if is_known(db, kind, &value)? {
+                db.execute(
+                    "UPDATE known_logins SET last_seen = MAX(last_seen, ?3) WHERE kind = ?1 AND value = ?2",
+                    rusqlite::params![kind.as_str(), value, timestamp as i64],
+                )?;
+            } else {
+                novelties.push(kind);
+            }
+        }
+        if novelties.is_empty() {
+            return Ok(());
+        }
+
+        let subnet = subnet_of(ip);
+        let reasons = novelties.iter().map(|n| n.as_str()).collect::<Vec<_>>().join(",");
+        db.execute(
+            "INSERT INTO login_alerts (timestamp, ip, subnet, username, country, reasons)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![timestamp as i64, ip, subnet, username, country, reasons],
+        )?;
+        let alert = LoginAlert {
+            id: db.last_insert_rowid(),
+            timestamp,
+            ip: ip.to_string(),
+            subnet,
+            username: username.unwrap_or_default().to_string(),
+            country: country.map(str::to_string),
+            novelties,
+        };
+
+        self.notify(&alert);
+        self.pending.push(alert);
+        Ok(())
+    }
+
+    // Dismisses the oldest pending alert without changing the baseline
+    pub fn acknowledge(&mut self, db: &Connection, now: u64) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let alert = self.pending.remove(0);
+        db.execute(
+            "UPDATE login_alerts SET acknowledged_at = ?1, trusted = 0 WHERE id = ?2",
+            rusqlite::params![now as i64, alert.id],
+        )?;
+        Ok(())
+    }
+
+    // Adds the IP, subnet and user of the oldest pending alert to the baseline, then
+    // acknowledges every pending alert the baseline now covers
+    pub fn trust(&mut self, db: &Connection, now: u64) -> Result<()> {
+        let alert = match self.pending.first() {
+            Some(alert) => alert.clone(),
+            None => return Ok(()),
+        };
+        let username = Some(alert.username.as_str()).filter(|u| !u.is_empty());
+        for (kind, value) in baseline_entries(&alert.ip, username) {
+            remember(db, kind, &value, alert.timestamp as i64, alert.timestamp as i64, "trusted")?;
+        }
+
+        let mut remaining = Vec::new();
+        for pending in std::mem::take(&mut self.pending) {
+            let username = Some(pending.username.as_str()).filter(|u| !u.is_empty());
+            let mut covered = true;
+            for (kind, value) in baseline_entries(&pending.ip, username) {
+                covered &= is_known(db, kind, &value)?;
+            }
+            if covered {
+                db.execute(
+                    "UPDATE login_alerts SET acknowledged_at = ?1, trusted = 1 WHERE id = ?2",
+                    rusqlite::params![now as i64, pending.id],
+                )?;
+            } else {
+                remaining.push(pending);
+            }
+        }
+        self.pending = remaining;
+        Ok(())
+    }
+
+    // Runs the hook in the background with the alert in KASPA_ALERT_* variables
+    fn notify(&mut self, alert: &LoginAlert) {
+        let hook = match &self.hook {
+            Some(hook) => hook,
+            None => return,
+        };
+        let message = format!(
+            "New SSH login: {} from {} ({})",
+            alert.username,
+            alert.ip,
+            alert.reasons()
+        );
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(hook)
+            .env("KASPA_ALERT_MESSAGE", &message)
+            .env("KASPA_ALERT_IP", &alert.ip)
+            .env("KASPA_ALERT_SUBNET", alert.subnet.as_deref().unwrap_or(""))
+            .env("KASPA_ALERT_USER", &alert.username)
+            .env("KASPA_ALERT_COUNTRY", alert.country.as_deref().unwrap_or(""))
+            .env("KASPA_ALERT_REASONS", alert.reasons())
+            .env("KASPA_ALERT_TIMESTAMP", alert.timestamp.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match child {
+            // Reaped in the background so a slow hook doesn't block the TUI
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+                self.last_error = None;
+            }
+            Err(e) => self.last_error = Some(format!("login alert hook: {}", e)),
+        }
+    }
+}
+
+// Baseline entries of a login: its IP and subnet (for a parseable address) and its user
+fn baseline_entries(ip: &str, username: Option<&str>) -> Vec<(Novelty, String)> {
+    let mut entries = Vec::new();
+    if let Some(subnet) = subnet_of(ip) {
+        entries.push((Novelty::Ip, ip.to_string()));
+        entries.push((Novelty::Subnet, subnet));
+    }
+    if let Some(username) = username {
+        entries.push((Novelty::User, username.to_string()));
+    }
+    entries
+}
+
+fn is_known(db: &Connection, kind: Novelty, value: &str) -> Result<bool> {
+    Ok(db
+        .prepare_cached("SELECT 1 FROM known_logins WHERE kind = ?1 AND value = ?2")?
+        .query_row([kind.as_str(), value], |_| Ok(()))
+        .optional()?
+        .is_some())
+}
+
+fn remember(db: &Connection, kind: Novelty, value: &str, first_seen: i64, last_seen: i64, source: &str) -> Result<()> {
+    db.execute(
+        "INSERT INTO known_logins (kind, value, first_seen, last_seen, source)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT (kind, value) DO UPDATE SET
+            first_seen = MIN(first_seen, excluded.first_seen),
+            last_seen = MAX(last_seen, excluded.last_seen)",
+        rusqlite::params![kind.as_str(), value, first_seen, last_seen, source],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use crate::security;
+
+    const NOW: u64 = 1_792_310_400;
+
+    fn store(db: &Connection, line: &str) {
+        let event = security::parse_line(line).unwrap();
+        security::insert(db, (NOW - 3600) * 1_000_000, None, &event, None).unwrap();
+    }
+
+    // Baseline seeded from one past login of kaspa from 192.0.2.10
+    fn database() -> Connection {
+        let db = migrations::test_database();
+        store(&db, "sshd[1]: Accepted publickey for kaspa from 192.0.2.10 port 40000 ssh2");
+        store(&db, "sshd[2]: Failed password for root from 203.0.113.5 port 40001 ssh2");
+        LoginWatch::seed(&db).unwrap();
+        db
+    }
+
+    fn novelties(watch: &LoginWatch) -> Vec<Vec<Novelty>> {
+        watch.pending.iter().map(|alert| alert.novelties.clone()).collect()
+    }
+
+    fn alerts(db: &Connection, condition: &str) -> i64 {
+        db.query_row(&format!("SELECT COUNT(*) FROM login_alerts WHERE {}", condition), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn subnets() {
+        assert_eq!(subnet_of("203.0.113.77").as_deref(), Some("203.0.113.0/24"));
+        assert_eq!(subnet_of("2001:db8:1:2:3:4:5:6").as_deref(), Some("2001:db8:1:2::/64"));
+        assert_eq!(subnet_of("not an address"), None);
+    }
+
+    #[test]
+    fn seeds_the_baseline_from_past_logins_once() {
+        let db = database();
+        assert!(is_known(&db, Novelty::Ip, "192.0.2.10").unwrap());
+        assert!(is_known(&db, Novelty::Subnet, "192.0.2.0/24").unwrap());
+        assert!(is_known(&db, Novelty::User, "kaspa").unwrap());
+        // Failed logins are not known
+        assert!(!is_known(&db, Novelty::Ip, "203.0.113.5").unwrap());
+
+        store(&db, "sshd[3]: Accepted password for admin from 198.51.100.1 port 40002 ssh2");
+        LoginWatch::seed(&db).unwrap();
+        assert!(!is_known(&db, Novelty::User, "admin").unwrap());
+    }
+
+    #[test]
+    fn alerts_on_what_is_not_in_the_baseline() {
+        let db = database();
+        let mut watch = LoginWatch::new(None);
+        watch.check(&db, "192.0.2.10", Some("kaspa"), None, NOW).unwrap();
+        watch.check(&db, "192.0.2.11", Some("kaspa"), None, NOW).unwrap();
+        watch.check(&db, "198.51.100.1", Some("kaspa"), Some("NL"), NOW).unwrap();
+        watch.check(&db, "192.0.2.10", Some("admin"), None, NOW).unwrap();
+        assert_eq!(
+            novelties(&watch),
+            [vec![Novelty::Ip], vec![Novelty::Ip, Novelty::Subnet], vec![Novelty::User]]
+        );
+        assert_eq!(watch.pending[1].reasons(), "new IP, new subnet");
+        assert_eq!(watch.pending[1].subnet.as_deref(), Some("198.51.100.0/24"));
+
+        // Stored, and loaded back after a restart
+        let mut restarted = LoginWatch::new(None);
+        restarted.load_pending(&db).unwrap();
+        assert_eq!(novelties(&restarted), novelties(&watch));
+        assert_eq!(restarted.pending[1].country.as_deref(), Some("NL"));
+    }
+
+    #[test]
+    fn trusting_an_alert_covers_the_other_pending_ones() {
+        let db = database();
+        let mut watch = LoginWatch::new(None);
+        watch.check(&db, "198.51.100.1", Some("kaspa"), None, NOW).unwrap();
+        watch.check(&db, "198.51.100.1", Some("kaspa"), None, NOW + 60).unwrap();
+        // Same subnet, but another IP
+        watch.check(&db, "198.51.100.2", Some("kaspa"), None, NOW + 120).unwrap();
+
+        // Its IP is still unknown; the alert keeps the novelties it was raised with
+        watch.trust(&db, NOW + 300).unwrap();
+        assert_eq!(novelties(&watch), [vec![Novelty::Ip, Novelty::Subnet]]);
+        assert_eq!(watch.pending[0].ip, "198.51.100.2");
+        assert_eq!(alerts(&db, "trusted = 1 AND acknowledged_at IS NOT NULL"), 2);
+        assert!(is_known(&db, Novelty::Subnet, "198.51.100.0/24").unwrap());
+
+        // The next login from there is known
+        watch.check(&db, "198.51.100.1", Some("kaspa"), None, NOW + 600).unwrap();
+        assert_eq!(watch.pending.len(), 1);
+    }
+
+    #[test]
+    fn acknowledging_leaves_the_baseline_unchanged() {
+        let db = database();
+        let mut watch = LoginWatch::new(None);
+        watch.check(&db, "198.51.100.1", Some("kaspa"), None, NOW).unwrap();
+        watch.acknowledge(&db, NOW + 60).unwrap();
+        assert!(watch.pending.is_empty());
+        assert_eq!(alerts(&db, "trusted = 0 AND acknowledged_at IS NOT NULL"), 1);
+        assert!(!is_known(&db, Novelty::Ip, "198.51.100.1").unwrap());
+
+        // So the next login alerts again
+        watch.check(&db, "198.51.100.1", Some("kaspa"), None, NOW + 600).unwrap();
+        assert_eq!(watch.pending.len(), 1);
+    }
+}
@@ -4,24 +4,44 @@
  * License: MIT License
  */
 
- use std::{thread, time::Duration};
+ mod authkeys;
+ mod authlog;
+ mod backup;
+ mod browser;
+ mod bruteforce;
+ mod cgroup;
+ mod config;
+ mod container;
+ mod db;
+ mod export;
+ mod firewall;
+ mod geoip;
+ mod history;
+ mod integrity;
+ mod journal;
+ mod logins;
+ mod migrations;
+ mod procfs;
+ mod psi;
+ mod retention;
+ mod sampler;
+ mod security;
+ mod series;
+ mod sensors;
+ mod ssh;
+ mod storage;
+ mod writer;
+
+ use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+ use std::time::Duration;
  use rusqlite::{Connection, Result};
- use sysinfo::{
-     System,
-     SystemExt,
-     NetworksExt,
-     NetworkExt,
-     DiskExt,
-     ProcessExt,
- };
  use std::time::{SystemTime, UNIX_EPOCH};
- use std::process::Command;
  use tui::{
      backend::CrosstermBackend,
-     widgets::{Block, Borders, Chart, Dataset, GraphType, Paragraph, List, ListItem},
+     widgets::{Block, Borders, Cell, Chart, Dataset, GraphType, Paragraph, List, ListItem, Row, Table},
      layout::{Layout, Constraint, Direction, Alignment},
      text::{Span, Spans},
-     style::{Style, Color},
+     style::{Style, Color, Modifier},
      symbols,
      Terminal,
  };
@@ -31,97 +51,223 @@
      terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
  };
  use std::io::stdout;
+ use std::path::Path;
+ use browser::{severity_color, LogBrowser};
+ use cgroup::{Cgroup, CgroupLimits};
+ use config::Config;
+ use container::KaspadContainer;
+ use geoip::GeoInfo;
+ use history::{ChartData, HistoryView};
+ use procfs::ProcessStats;
+ use psi::PressureSnapshot;
+ use sampler::{Sample, Sampler};
+ use sensors::{CpuFrequency, SensorKind, SensorReading};
+ use security::SecurityEvent;
+ use series::{Labels, SeriesStore};
+ use storage::{Request, Status, Storage, Update};
  
  struct ServerMonitor {
-     sys: System,
-     cpu_history: Vec<(f64, f64)>,
-     mem_history: Vec<(f64, f64)>,
-     disk_history: Vec<(f64, f64)>,
-     received_history: Vec<(f64, f64)>,
-     transmitted_history: Vec<(f64, f64)>,
-     ssh_attempts: Vec<(String, String, String)>,
+     // Latest `window_size` samples, shown when no time range is selected
+     recent: ChartData,
+     // Selected time range, read from the database
+     history: Option<HistoryView>,
+     // Custom time range being typed
+     range_input: Option<String>,
+     security_events: Vec<(u64, SecurityEvent, Option<GeoInfo>)>,
+     // Read-only use: the storage thread does all the writes
      db: Connection,
      window_size: usize,
      log_scroll: usize,
      max_logs: usize,
-     last_received: u64,
-     last_transmitted: u64,
-     last_network_time: SystemTime,
+     process_stats: Option<(f64, ProcessStats)>,
+     ctxt_switch_rate: f64,
+     system_pressure: PressureSnapshot,
+     kaspad_pressure: Option<PressureSnapshot>,
+     kaspad_cgroup: Option<Cgroup>,
+     cgroup_limits: Option<(f64, CgroupLimits)>,
+     throttled_percent: f64,
+     config: Config,
+     container: Option<KaspadContainer>,
+     host_cores: usize,
+     host_memory: u64,
+     disk_space: Option<(u64, u64)>,
+     sensor_readings: Vec<SensorReading>,
+     cpu_frequency: Option<CpuFrequency>,
+     thermal_throttled: bool,
+     // Auth log, brute-force, bans, login alerts and writes, from the storage thread
+     status: Status,
+     storage: Sender<Request>,
+     attackers_view: AttackersView,
+     // Full-screen security log browser, when open
+     log_browser: Option<LogBrowser>,
+     // Last failed database read of the interface, shown until the next key
+     error: Option<String>,
  }
  
- fn get_dir_size(path: &str) -> std::io::Result<u64> {
-     let mut total_size = 0;
-     for entry in std::fs::read_dir(path)? {
-         let entry = entry?;
-         let metadata = entry.metadata()?;
-         if metadata.is_file() {
-             total_size += metadata.len();
-         } else if metadata.is_dir() {
-             total_size += get_dir_size(entry.path().to_str().unwrap_or(""))?;
-         }
+ // What the table next to the logs shows
+ #[derive(Clone, Copy, PartialEq, Eq)]
+ enum AttackersView {
+     Ips,
+     Countries,
+ }
+ 
+ // Database, in the working directory
+ const DB_PATH: &str = "metrics.db";
+
+ // Delay between two samples, and between two ticks of the storage thread
+ const UPDATE_INTERVAL: Duration = Duration::from_secs(2);
+ 
+ // Latest `limit` samples of an unlabeled series, as (seconds, value)
+ fn stored_series(db: &Connection, name: &str, limit: usize) -> Result<Vec<(f64, f64)>> {
+     let samples = match SeriesStore::find(db, name, &Labels::new())? {
+         Some(series) => SeriesStore::latest(db, &series, limit)?,
+         None => Vec::new(),
+     };
+     Ok(samples.into_iter().map(|(ts, value)| (ts as f64 / 1000.0, value)).collect())
+ }
+ 
+ // MiB/s between the latest `limit` + 1 samples of a byte counter series, like the live
+ // network charts
+ fn stored_rates(db: &Connection, name: &str, limit: usize) -> Result<Vec<(f64, f64)>> {
+     Ok(history::rates(&stored_series(db, name, limit + 1)?))
+ }
+ 
+ // Hottest sensor of `kinds` at each of the latest `limit` timestamps of the sensors table
+ fn stored_temperatures(db: &Connection, kinds: &[SensorKind], limit: usize) -> Result<Vec<(f64, f64)>> {
+     let kinds: Vec<String> = kinds.iter().map(|k| format!("'{}'", k.as_str())).collect();
+     let mut temperatures = db
+         .prepare(&format!(
+             "SELECT timestamp_ms, MAX(value) FROM sensors
+              WHERE kind IN ({}) AND value IS NOT NULL
+              GROUP BY timestamp_ms
+              ORDER BY timestamp_ms DESC
+              LIMIT ?1",
+             kinds.join(", ")
+         ))?
+         .query_map([limit as i64], |row| Ok((row.get::<_, i64>(0)? as f64 / 1000.0, row.get(1)?)))?
+         .collect::<Result<Vec<(f64, f64)>>>()?;
+     temperatures.reverse();
+     Ok(temperatures)
+ }
+ 
+ // "12s ago", "5m ago", "3h ago" for a timestamp in seconds
+ fn format_ago(timestamp: u64) -> String {
+     let time = SystemTime::now()
+         .duration_since(UNIX_EPOCH)
+         .unwrap()
+         .as_secs() as i64
+         - timestamp as i64;
+ 
+     if time < 60 {
+         format!("{}s ago", time)
+     } else if time < 3600 {
+         format!("{}m ago", time / 60)
+     } else {
+         format!("{}h ago", time / 3600)
      }
-     Ok(total_size)
  }
  
  impl ServerMonitor {
-    fn new() -> Result<Self> {
-        let db = Connection::open("metrics.db")?;
-    
-        // System metrics table avec les nouvelles colonnes
-        db.execute(
-            "CREATE TABLE IF NOT EXISTS metrics (
-                timestamp INTEGER PRIMARY KEY,
-                cpu_usage REAL,
-                memory_usage REAL,
-                memory_total INTEGER,
-                memory_used INTEGER,
-                disk_usage REAL,
-                network_received INTEGER,
-                network_transmitted INTEGER,
-                kaspad_memory INTEGER,
-                kaspad_disk_read INTEGER,
-                kaspad_disk_write INTEGER
-            )",
-            rusqlite::params![],
-        )?;
- 
-         // SSH attempts table
-         db.execute(
-             "CREATE TABLE IF NOT EXISTS ssh_attempts (
-                 timestamp INTEGER,
-                 ip TEXT,
-                 status TEXT,
-                 PRIMARY KEY (timestamp, ip)
-             )",
-             rusqlite::params![],
-         )?;
- 
-         // Indexes
-         db.execute(
-             "CREATE INDEX IF NOT EXISTS idx_timestamp ON metrics(timestamp)",
-             rusqlite::params![],
-         )?;
-         db.execute(
-             "CREATE INDEX IF NOT EXISTS idx_ssh_timestamp ON ssh_attempts(timestamp)",
-             rusqlite::params![],
-         )?;
- 
-         Ok(ServerMonitor {
-             sys: System::new_all(),
-             cpu_history: Vec::new(),
-             mem_history: Vec::new(),
-             disk_history: Vec::new(),
-             received_history: Vec::new(),
-             transmitted_history: Vec::new(),
-             ssh_attempts: Vec::new(),
+     fn new(config: Config, db: Connection, storage: Sender<Request>) -> Self {
+         ServerMonitor {
+             recent: ChartData::default(),
+             history: None,
+             range_input: None,
+             security_events: Vec::new(),
              db,
              window_size: 100,
              log_scroll: 0,
              max_logs: 1000, // Limit the number of logs kept in memory
-             last_received: 0,
-             last_transmitted: 0,
-             last_network_time: SystemTime::now(),
-         })
+             process_stats: None,
+             ctxt_switch_rate: 0.0,
+             system_pressure: PressureSnapshot::default(),
+             kaspad_pressure: None,
+             kaspad_cgroup: None,
+             cgroup_limits: None,
+             throttled_percent: 0.0,
+             config,
+             container: None,
+             host_cores: 0,
+             host_memory: 0,
+             disk_space: None,
+             sensor_readings: Vec::new(),
+             cpu_frequency: None,
+             thermal_throttled: false,
+             status: Status::default(),
+             storage,
+             attackers_view: AttackersView::Ips,
+             log_browser: None,
+             error: None,
+         }
+     }
+
+     // Every key but q, which quits
+     fn key(&mut self, code: KeyCode) -> Result<()> {
+         match code {
+             code if self.log_browser.is_some() => self.browser_key(code)?,
+             code if self.range_input.is_some() => self.range_input_key(code)?,
+             KeyCode::Up => self.scroll_logs_up(),
+             KeyCode::Down => self.scroll_logs_down(),
+             KeyCode::Char('g') => self.toggle_attackers_view(),
+             KeyCode::Char('l') => self.open_log_browser()?,
+             KeyCode::Char('a') => self.acknowledge_login_alert(),
+             KeyCode::Char('t') => self.trust_login_alert(),
+             KeyCode::Char(c @ '1'..='6') => self.select_range(c as usize - '1' as usize)?,
+             KeyCode::Char('c') => self.range_input = Some(String::new()),
+             KeyCode::Char('0') => self.history = None,
+             KeyCode::Char('n') => self.go_live()?,
+             KeyCode::Left => self.pan_range(false)?,
+             KeyCode::Right => self.pan_range(true)?,
+             _ => {}
+         }
+         Ok(())
+     }
+ 
+     // Number of cores kaspad may use: the cgroup CPU quota when lower than the host core count
+     fn effective_cores(&self) -> f64 {
+         sampler::effective_cores(self.host_cores, self.cgroup_limits.as_ref().map(|(_, l)| l))
+     }
+ 
+     // Memory available to kaspad in bytes: the cgroup limit when lower than the host total
+     fn effective_memory(&self) -> u64 {
+         sampler::effective_memory(self.host_memory, self.cgroup_limits.as_ref().map(|(_, l)| l))
+     }
+ 
+     fn toggle_attackers_view(&mut self) {
+         self.attackers_view = match self.attackers_view {
+             AttackersView::Ips => AttackersView::Countries,
+             AttackersView::Countries => AttackersView::Ips,
+         };
+     }
+ 
+     // Handled by the storage thread, which sends the remaining alerts back
+     fn acknowledge_login_alert(&mut self) {
+         let _ = self.storage.send(Request::AcknowledgeAlert);
+     }
+ 
+     fn trust_login_alert(&mut self) {
+         let _ = self.storage.send(Request::TrustAlert);
+     }
+ 
+     fn open_log_browser(&mut self) -> Result<()> {
+         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+         self.log_browser = Some(LogBrowser::open(&self.db, now)?);
+         Ok(())
+     }
+ 
+     fn browser_key(&mut self, key: KeyCode) -> Result<()> {
+         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+         if let Some(browser) = &mut self.log_browser {
+             if !browser.handle_key(&self.db, key, now)? {
+                 self.log_browser = None;
+             }
+         }
+         Ok(())
+     }
+ 
+     // Whether keys are typed into a text field rather than being commands
+     fn is_typing(&self) -> bool {
+         self.range_input.is_some() || self.log_browser.as_ref().is_some_and(|b| b.is_typing())
      }
  
      // Methods for scrolling logs
@@ -132,272 +278,364 @@
      }
  
      fn scroll_logs_down(&mut self) {
-         if self.log_scroll < self.ssh_attempts.len().saturating_sub(3) {
+         if self.log_scroll < self.security_events.len().saturating_sub(3) {
              self.log_scroll += 1;
          }
      }
  
-     fn check_ssh_attempts(&mut self) -> Result<()> {
-         let output = Command::new("journalctl")
-             .args(["-u", "ssh", "--since", "1m", "-n", "50", "--no-pager"])
-             .output()
-             .expect("Failed to execute journalctl");
- 
-         let log = String::from_utf8_lossy(&output.stdout);
-         for line in log.lines() {
-             if line.contains("Failed password") || line.contains("Accepted password") {
-                 let timestamp = SystemTime::now()
-                     .duration_since(UNIX_EPOCH)
-                     .unwrap()
-                     .as_secs();
- 
-                 let ip = if let Some(ip) = line
-                     .split("from ")
-                     .nth(1)
-                     .and_then(|s| s.split(' ').next())
-                 {
-                     ip.to_string()
-                 } else {
-                     "unknown".to_string()
-                 };
+     // Prefills the charts with the latest samples of previous runs and the logs panel with the
+     // latest stored events, instead of starting blank
+     fn load_history(&mut self) -> Result<()> {
+         let limit = self.window_size;
+         self.recent = ChartData {
+             cpu: stored_series(&self.db, "kaspad_cpu_percent", limit)?,
+             mem: stored_series(&self.db, "kaspad_memory_percent", limit)?,
+             disk: stored_series(&self.db, "disk_usage_percent", limit)?,
+             received: stored_rates(&self.db, "network_received_bytes", limit)?,
+             transmitted: stored_rates(&self.db, "network_transmitted_bytes", limit)?,
+             cpu_temp: stored_temperatures(&self.db, &[SensorKind::CpuPackage, SensorKind::CpuCore], limit)?,
+             nvme_temp: stored_temperatures(&self.db, &[SensorKind::Nvme], limit)?,
+         };
+         self.security_events = security::recent(&self.db, self.max_logs)?;
+         Ok(())
+     }
  
-                 let status = if line.contains("Failed") {
-                     "Failed"
-                 } else {
-                     "Success"
-                 };
- 
-                 self.db.execute(
-                     "INSERT OR IGNORE INTO ssh_attempts (timestamp, ip, status)
-                     VALUES (?1, ?2, ?3)",
-                     (timestamp, &ip, status),
-                 )?;
- 
-                 self.ssh_attempts.push((
-                     timestamp.to_string(),
-                     ip,
-                     status.to_string(),
-                 ));
-             }
+     fn apply_sample(&mut self, sample: Sample) -> Result<()> {
+         let timestamp = sample.timestamp;
+         let recent = &mut self.recent;
+         recent.cpu.push((timestamp, sample.kaspad_cpu_percent));
+         recent.mem.push((timestamp, sample.kaspad_memory_percent));
+         recent.disk.push((timestamp, sample.disk_usage_percent));
+         recent.received.push((timestamp, sample.received_speed));
+         recent.transmitted.push((timestamp, sample.transmitted_speed));
+         if let Some(temp) = sample.cpu_temp {
+             recent.cpu_temp.push((timestamp, temp));
+         }
+         if let Some(temp) = sample.nvme_temp {
+             recent.nvme_temp.push((timestamp, temp));
          }
  
-         // Limit the number of logs in memory
-         while self.ssh_attempts.len() > self.max_logs {
-             self.ssh_attempts.remove(0);
+         // Maintain window size for all histories
+         for history in [
+             &mut recent.cpu,
+             &mut recent.mem,
+             &mut recent.disk,
+             &mut recent.received,
+             &mut recent.transmitted,
+             &mut recent.cpu_temp,
+             &mut recent.nvme_temp,
+         ] {
+             if history.len() > self.window_size {
+                 history.remove(0);
+             }
          }
  
+         self.sensor_readings = sample.sensor_readings;
+         self.cpu_frequency = sample.cpu_frequency;
+         self.thermal_throttled = sample.thermal_throttled;
+         self.process_stats = sample.process_stats.map(|stats| (timestamp, stats));
+         self.ctxt_switch_rate = sample.ctxt_switch_rate;
+         self.system_pressure = sample.system_pressure;
+         self.kaspad_pressure = sample.kaspad_pressure;
+         self.kaspad_cgroup = sample.kaspad_cgroup;
+         self.cgroup_limits = sample.cgroup_limits.map(|limits| (timestamp, limits));
+         self.throttled_percent = sample.throttled_percent;
+         self.container = sample.container;
+         self.host_cores = sample.host_cores;
+         self.host_memory = sample.host_memory;
+         self.disk_space = sample.disk_space;
+ 
+         // A live time range follows the samples as the storage thread writes them
+         if self.history.as_ref().is_some_and(|view| view.is_stale()) {
+             self.load_range()?;
+         }
          Ok(())
      }
  
-     fn log_to_db(&mut self) -> Result<()> {
-        let now = SystemTime::now();
-        let timestamp = now
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs_f64();
-            
-        // Refresh all system information
-        self.sys.refresh_all();
-        
-        // Attendre un peu pour des mesures plus précises
-        thread::sleep(Duration::from_millis(200));
-    
-        // Rafraîchir à nouveau pour la mesure
-        self.sys.refresh_cpu();
-        self.sys.refresh_processes();
-    
-        // Get kaspad metrics with proper refresh
-        let num_cores = self.sys.cpus().len() as f64;
-        let (kaspad_cpu_usage, kaspad_memory, kaspad_disk_read, kaspad_disk_write) = 
-            if let Some(process) = self.sys.processes_by_name("kaspad").next() {
-                (
-                    (process.cpu_usage() as f64) / num_cores,
-                    process.memory() as f64 / 1_024_000.0, // Convert to GB
-                    process.disk_usage().read_bytes as f64 / 1_024_000.0,
-                    process.disk_usage().written_bytes as f64 / 1_024_000.0
-                )
-            } else {
-                (0.0, 0.0, 0.0, 0.0)
-            };
-    
-        // Update CPU history
-        self.cpu_history.push((timestamp, kaspad_cpu_usage));
-    
-        // Calculate memory percentage
-        let total_memory_gb = self.sys.total_memory() as f64 / 1_024_000.0;
-        let memory_usage = (kaspad_memory / total_memory_gb) * 100.0;
-        self.mem_history.push((timestamp as f64, memory_usage));
-    
-        // Network calculations
-        let total_received = self
-            .sys
-            .networks()
-            .iter()
-            .map(|(_, data)| data.received())
-            .sum::<u64>();
-        let total_transmitted = self
-            .sys
-            .networks()
-            .iter()
-            .map(|(_, data)| data.transmitted())
-            .sum::<u64>();
-    
-        let time_diff = now
-            .duration_since(self.last_network_time)
-            .unwrap_or(Duration::from_secs(1))
-            .as_secs_f64();
-    
-        if time_diff > 0.0 {
-            let received_speed = if self.last_received > 0 && total_received >= self.last_received {
-                (total_received - self.last_received) as f64 / (time_diff * 1_048_576.0)
-            } else {
-                0.0
-            };
-    
-            let transmitted_speed =
-                if self.last_transmitted > 0 && total_transmitted >= self.last_transmitted {
-                    (total_transmitted - self.last_transmitted) as f64 / (time_diff * 1_048_576.0)
-                } else {
-                    0.0
-                };
-    
-            self.received_history.push((timestamp, received_speed));
-            self.transmitted_history.push((timestamp, transmitted_speed));
-    
-            self.last_received = total_received;
-            self.last_transmitted = total_transmitted;
-            self.last_network_time = now;
-        } else {
-            self.received_history.push((timestamp, 0.0));
-            self.transmitted_history.push((timestamp, 0.0));
-        }
-    
-        // Disk usage including kaspad directory
-        let disk_usage = if let Some(disk) = self.sys.disks().into_iter().next() {
-            let home = std::env::var("HOME").unwrap_or_else(|_| String::from("/root"));
-            let kaspa_dir = format!("{}/.kaspa", home);
-    
-            if let Ok(size) = get_dir_size(&kaspa_dir) {
-                let total_space = disk.total_space();
-                let free_space = disk.available_space();
-                let used_space = total_space - free_space + size;
-                (used_space as f64 / total_space as f64) * 100.0
-            } else {
-                0.0
-            }
-        } else {
-            0.0
-        };
-    
-        self.disk_history.push((timestamp as f64, disk_usage));
-    
-        // Maintain window size for all histories
-        if self.cpu_history.len() > self.window_size {
-            self.cpu_history.remove(0);
-        }
-        if self.mem_history.len() > self.window_size {
-            self.mem_history.remove(0);
-        }
-        if self.disk_history.len() > self.window_size {
-            self.disk_history.remove(0);
-        }
-        if self.received_history.len() > self.window_size {
-            self.received_history.remove(0);
-        }
-        if self.transmitted_history.len() > self.window_size {
-            self.transmitted_history.remove(0);
-        }
-    
-        // Save all metrics to database
-        self.db.execute(
-            "INSERT INTO metrics (
-                timestamp, cpu_usage, memory_usage, memory_total,
-                memory_used, disk_usage, network_received, network_transmitted,
-                kaspad_memory, kaspad_disk_read, kaspad_disk_write
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
-            rusqlite::params![
-                timestamp as i64,
-                kaspad_cpu_usage,
-                memory_usage as f64,
-                self.sys.total_memory() as i64,
-                (kaspad_memory * 1_024_000.0) as i64,
-                disk_usage,
-                total_received as i64,
-                total_transmitted as i64,
-                (kaspad_memory * 1_024_000.0) as i64,
-                (kaspad_disk_read * 1_024_000.0) as i64,
-                (kaspad_disk_write * 1_024_000.0) as i64,
-            ],
-        )?;
-    
-        Ok(())
-    }
- 
-     fn update(&mut self) -> Result<()> {
-         self.log_to_db()?;
-         self.check_ssh_attempts()?;
+     fn load_range(&mut self) -> Result<()> {
+         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+         if let Some(view) = &mut self.history {
+             view.load(&self.db, now, self.config.retention.raw_days)?;
+         }
          Ok(())
      }
  
-     fn cleanup(&self) -> Result<()> {
-         self.db.execute("VACUUM", rusqlite::params![])?;
-         self.db.execute("ANALYZE", rusqlite::params![])?;
+     // Shows one of history::RANGES ending now
+     fn select_range(&mut self, index: usize) -> Result<()> {
+         let (label, length) = history::RANGES[index];
+         self.history = Some(HistoryView::new(label, length, None));
+         self.load_range()
+     }
+ 
+     fn pan_range(&mut self, forward: bool) -> Result<()> {
+         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+         if let Some(view) = &mut self.history {
+             view.pan(forward, now);
+         }
+         self.load_range()
+     }
+ 
+     fn go_live(&mut self) -> Result<()> {
+         if let Some(view) = &mut self.history {
+             view.go_live();
+         }
+         self.load_range()
+     }
+ 
+     // Keys typed into the custom time range prompt; an invalid range keeps the prompt open
+     fn range_input_key(&mut self, key: KeyCode) -> Result<()> {
+         let Some(input) = &mut self.range_input else {
+             return Ok(());
+         };
+         match key {
+             KeyCode::Esc => self.range_input = None,
+             KeyCode::Backspace => {
+                 input.pop();
+             }
+             KeyCode::Char(c) => input.push(c),
+             KeyCode::Enter => {
+                 if let Some(view) = history::parse_custom(input) {
+                     self.range_input = None;
+                     self.history = Some(view);
+                     self.load_range()?;
+                 }
+             }
+             _ => {}
+         }
          Ok(())
      }
  
+     fn apply_update(&mut self, update: Update) {
+         self.security_events.extend(update.events);
+         self.status = update.status;
+ 
+         // Limit the number of logs in memory
+         if self.security_events.len() > self.max_logs {
+             let excess = self.security_events.len() - self.max_logs;
+             self.security_events.drain(..excess);
+         }
+     }
+ 
      fn draw<B: tui::backend::Backend>(&mut self, terminal: &mut Terminal<B>) -> std::io::Result<()> {
          terminal.draw(|f| {
+             // Banner above everything while a login alert is pending
+             let mut area = f.size();
+             if let Some(alert) = self.status.login_alerts.first() {
+                 let rows = Layout::default()
+                     .direction(Direction::Vertical)
+                     .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+                     .split(area);
+                 area = rows[1];
+ 
+                 let location = alert
+                     .country
+                     .as_ref()
+                     .map(|c| format!(" ({})", c))
+                     .unwrap_or_default();
+                 let mut text = format!(
+                     "⚠ NEW SSH LOGIN: {} from {}{} {} - {} - a: acknowledge, t: trust",
+                     alert.username,
+                     alert.ip,
+                     location,
+                     format_ago(alert.timestamp),
+                     alert.reasons()
+                 );
+                 if self.status.login_alerts.len() > 1 {
+                     text.push_str(&format!(" ({} pending)", self.status.login_alerts.len()));
+                 }
+                 if let Some(error) = &self.status.login_error {
+                     text.push_str(&format!(" - {}", error));
+                 }
+                 let banner = Paragraph::new(text)
+                     .style(Style::default().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD))
+                     .block(Block::default().borders(Borders::ALL))
+                     .alignment(Alignment::Center);
+                 f.render_widget(banner, rows[0]);
+             }
+ 
+             if let Some(browser) = &mut self.log_browser {
+                 browser.render(f, area);
+                 return;
+             }
+ 
+             // Charts show the selected time range over its whole window, or the latest samples
+             let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+             let charts = self.history.as_ref().map_or(&self.recent, |view| &view.data);
+             let range_window = self.history.as_ref().map(|view| view.window(now));
+             let x_bounds = |line: &[(f64, f64)]| match range_window {
+                 Some((from, to)) => [from as f64, to as f64],
+                 None => [
+                     line.first().map(|p| p.0).unwrap_or(0.0),
+                     line.last().map(|p| p.0).unwrap_or(100.0),
+                 ],
+             };
+             let range_title = match &self.history {
+                 Some(view) => format!(" - {} (←→ pan, n: live, 0: recent)", view.title(now)),
+                 None => " - 1-6/c: time range".to_string(),
+             };
+ 
              let chunks = Layout::default()
                  .direction(Direction::Vertical)
                  .constraints(
                      [
-                         Constraint::Percentage(20), // CPU
-                         Constraint::Percentage(20), // Memory
-                         Constraint::Percentage(20), // Disk
-                         Constraint::Percentage(20), // Network
-                         Constraint::Percentage(15), // Logs
+                         Constraint::Percentage(18), // CPU
+                         Constraint::Percentage(18), // Memory
+                         Constraint::Percentage(18), // Disk
+                         Constraint::Percentage(18), // Network
+                         Constraint::Percentage(23), // Logs and top attackers
                          Constraint::Percentage(5),  // Author info
                      ]
                      .as_ref(),
                  )
-                 .split(f.size());
+                 .split(area);
+ 
+             // CPU row: chart on the left, kaspad process stats on the right
+             let cpu_row = Layout::default()
+                 .direction(Direction::Horizontal)
+                 .constraints([Constraint::Percentage(75), Constraint::Percentage(25)].as_ref())
+                 .split(chunks[0]);
  
             // CPU Graph
-            let current_cpu = self.cpu_history.last().map(|&(_, v)| v).unwrap_or(0.0);
-            let num_cores = self.sys.cpus().len();
+            let current_cpu = self.recent.cpu.last().map(|&(_, v)| v).unwrap_or(0.0);
+            let num_cores = self.host_cores;
+            let cpu_limited = (self.effective_cores() - num_cores as f64).abs() > f64::EPSILON;
+            let cores_label = if cpu_limited {
+                format!("{:.2} of {} Cores (cgroup)", self.effective_cores(), num_cores)
+            } else {
+                format!("{} Cores", num_cores)
+            };
 
             // New format for the CPU title that includes real-time usage and core count
-            let cpu_title = format!("kaspad CPU Usage ({:.1}%) - {} Cores", current_cpu, num_cores);
-            let cpu_label = format!("CPU: {:.1}% of {} Cores", current_cpu, num_cores);
+            let cpu_title = if cpu_limited {
+                format!(
+                    "kaspad CPU Usage ({:.1}%) - {} - throttled {:.1}%",
+                    current_cpu, cores_label, self.throttled_percent
+                )
+            } else {
+                format!("kaspad CPU Usage ({:.1}%) - {}", current_cpu, cores_label)
+            } + &range_title;
+            let cpu_label = format!("CPU: {:.1}% of {}", current_cpu, cores_label);
 
             let cpu_dataset = Dataset::default()
                 .name(cpu_label.as_str())
                  .marker(symbols::Marker::Dot)
                  .graph_type(GraphType::Line)
                  .style(Style::default().fg(Color::Cyan))
-                 .data(&self.cpu_history);
+                 .data(&charts.cpu);
  
              let cpu_chart = Chart::new(vec![cpu_dataset])
                  .block(Block::default().title(cpu_title).borders(Borders::ALL))
-                 .x_axis(tui::widgets::Axis::default().bounds([
-                     self.cpu_history.first().map(|p| p.0).unwrap_or(0.0),
-                     self.cpu_history.last().map(|p| p.0).unwrap_or(100.0),
-                 ]))
+                 .x_axis(tui::widgets::Axis::default().bounds(x_bounds(&charts.cpu)))
                  .y_axis(tui::widgets::Axis::default().bounds([0.0, 100.0]));
  
-             f.render_widget(cpu_chart, chunks[0]);
+             f.render_widget(cpu_chart, cpu_row[0]);
+ 
+             // kaspad process stats panel
+             let mut stats_lines = match &self.process_stats {
+                 Some((_, stats)) => {
+                     let limit = |l: Option<u64>| {
+                         l.map(|v| v.to_string()).unwrap_or_else(|| "unlimited".to_string())
+                     };
+                     let fd_usage = stats
+                         .fd_usage_percent()
+                         .map(|p| format!(" ({:.1}%)", p))
+                         .unwrap_or_default();
+                     vec![
+                         Spans::from(Span::styled(
+                             format!("FDs: {} / {}{}", stats.open_fds, limit(stats.fd_soft_limit), fd_usage),
+                             Style::default().fg(if stats.fd_alert() { Color::Red } else { Color::White }),
+                         )),
+                         Spans::from(format!("FD hard limit: {}", limit(stats.fd_hard_limit))),
+                         Spans::from(format!("Threads: {}", stats.threads)),
+                         Spans::from(format!("Ctx switches: {:.0}/s", self.ctxt_switch_rate)),
+                         Spans::from(format!(
+                             "  vol {} / invol {}",
+                             stats.voluntary_ctxt_switches, stats.nonvoluntary_ctxt_switches
+                         )),
+                     ]
+                 }
+                 None => vec![Spans::from(Span::styled(
+                     "kaspad not found",
+                     Style::default().fg(Color::Gray),
+                 ))],
+             };
+ 
+             if let Some(container) = &self.container {
+                 stats_lines.push(Spans::from(format!(
+                     "Container {} (pid {})",
+                     container.id.chars().take(12).collect::<String>(),
+                     container.pid
+                 )));
+             } else if let Some(name) = &self.config.container {
+                 stats_lines.push(Spans::from(Span::styled(
+                     format!("Container {} not running", name),
+                     Style::default().fg(Color::Red),
+                 )));
+             }
+ 
+             if let (Some(cgroup), Some((_, limits))) = (&self.kaspad_cgroup, &self.cgroup_limits) {
+                 let quota = limits
+                     .cpu_quota_cores
+                     .map(|q| format!("{:.2} cores", q))
+                     .unwrap_or_else(|| "no CPU quota".to_string());
+                 stats_lines.push(Spans::from(format!("cgroup {}: {}", cgroup.version.as_str(), quota)));
+                 stats_lines.push(Spans::from(Span::styled(
+                     format!(
+                         "Throttled: {:.1}% ({}/{} periods)",
+                         self.throttled_percent, limits.nr_throttled, limits.nr_periods
+                     ),
+                     Style::default().fg(if self.throttled_percent > 0.0 { Color::Yellow } else { Color::White }),
+                 )));
+             }
+ 
+             if let Some(error) = &self.error {
+                 stats_lines.push(Spans::from(Span::styled(
+                     format!("DB read failed: {}", error),
+                     Style::default().fg(Color::Red),
+                 )));
+             }
+             if let Some(error) = &self.status.db_error {
+                 stats_lines.push(Spans::from(Span::styled(
+                     format!("DB error: {}", error),
+                     Style::default().fg(Color::Red),
+                 )));
+             }
+             if let Some(error) = &self.status.write_error {
+                 stats_lines.push(Spans::from(Span::styled(
+                     format!(
+                         "DB write failed: {} ({} queued, {} dropped)",
+                         error,
+                         self.status.queued_rows,
+                         self.status.dropped_rows
+                     ),
+                     Style::default().fg(Color::Red),
+                 )));
+             }
+
+             let stats_panel = Paragraph::new(stats_lines)
+                 .block(Block::default().title("kaspad Process").borders(Borders::ALL));
+ 
+             f.render_widget(stats_panel, cpu_row[1]);
+ 
+             // Memory row: chart on the left, PSI on the right
+             let mem_row = Layout::default()
+                 .direction(Direction::Horizontal)
+                 .constraints([Constraint::Percentage(75), Constraint::Percentage(25)].as_ref())
+                 .split(chunks[1]);
  
              // Memory Graph
-             let current_mem = self.mem_history.last().map(|&(_, v)| v).unwrap_or(0.0);
-             let total_mem = self.sys.total_memory() as f64 / 1_024_000.0; // Convert to GB
+             let current_mem = self.recent.mem.last().map(|&(_, v)| v).unwrap_or(0.0);
+             let total_mem = self.effective_memory() as f64 / 1_073_741_824.0; // Convert to GB
              let used_mem = total_mem * current_mem / 100.0;
              let mem_label = format!(
                  "MEM: {:.1}GB / {:.1}GB ({:.1}%)",
                  used_mem, total_mem, current_mem
              );
              let mem_title = format!(
-                 "Memory Usage ({:.1}GB of {:.1}GB)",
-                 used_mem, total_mem
+                 "Memory Usage ({:.1}GB of {:.1}GB{})",
+                 used_mem,
+                 total_mem,
+                 if self.effective_memory() < self.host_memory { " cgroup limit" } else { "" }
              );
  
              let mem_dataset = Dataset::default()
@@ -405,28 +643,85 @@
                  .marker(symbols::Marker::Dot)
                  .graph_type(GraphType::Line)
                  .style(Style::default().fg(Color::Green))
-                 .data(&self.mem_history);
+                 .data(&charts.mem);
  
              let mem_chart = Chart::new(vec![mem_dataset])
                  .block(Block::default().title(mem_title.as_str()).borders(Borders::ALL))
-                 .x_axis(tui::widgets::Axis::default().bounds([
-                     self.mem_history.first().map(|p| p.0).unwrap_or(0.0),
-                     self.mem_history.last().map(|p| p.0).unwrap_or(100.0),
-                 ]))
+                 .x_axis(tui::widgets::Axis::default().bounds(x_bounds(&charts.mem)))
                  .y_axis(tui::widgets::Axis::default().bounds([0.0, 100.0]));
  
-             f.render_widget(mem_chart, chunks[1]);
+             f.render_widget(mem_chart, mem_row[0]);
+ 
+             // PSI panel
+             let fmt_pct = |v: Option<f64>| {
+                 v.map(|v| format!("{:>7.2}", v)).unwrap_or_else(|| format!("{:>7}", "-"))
+             };
+             let pressure_line = |label: String, pressure: Option<psi::Pressure>| match pressure {
+                 Some(p) => Spans::from(Span::styled(
+                     format!(
+                         "{:<10}{}{}{}{}",
+                         label,
+                         fmt_pct(Some(p.some_avg10)),
+                         fmt_pct(Some(p.some_avg60)),
+                         fmt_pct(p.full_avg10),
+                         fmt_pct(p.full_avg60)
+                     ),
+                     Style::default().fg(if p.is_alert() {
+                         Color::Red
+                     } else if p.is_warning() {
+                         Color::Yellow
+                     } else {
+                         Color::White
+                     }),
+                 )),
+                 None => Spans::from(Span::styled(
+                     format!("{:<10}{:>7}", label, "n/a"),
+                     Style::default().fg(Color::Gray),
+                 )),
+             };
+ 
+             let mut psi_lines = vec![Spans::from(Span::styled(
+                 format!("{:<10}{:>7}{:>7}{:>7}{:>7}", "", "some10", "some60", "full10", "full60"),
+                 Style::default().fg(Color::Gray),
+             ))];
+             if self.system_pressure.is_empty() {
+                 psi_lines = vec![Spans::from(Span::styled(
+                     "PSI not available (CONFIG_PSI)",
+                     Style::default().fg(Color::Gray),
+                 ))];
+             } else {
+                 psi_lines.extend(
+                     self.system_pressure
+                         .iter()
+                         .map(|(resource, p)| pressure_line(resource.to_string(), p)),
+                 );
+             }
+             if let Some(kaspad_pressure) = &self.kaspad_pressure {
+                 psi_lines.extend(
+                     kaspad_pressure
+                         .iter()
+                         .map(|(resource, p)| pressure_line(format!("cg {}", resource), p)),
+                 );
+             }
+ 
+             let psi_panel = Paragraph::new(psi_lines)
+                 .block(Block::default().title("Pressure (PSI %)").borders(Borders::ALL));
+ 
+             f.render_widget(psi_panel, mem_row[1]);
+ 
+             // Disk row: chart on the left, temperatures on the right
+             let disk_row = Layout::default()
+                 .direction(Direction::Horizontal)
+                 .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+                 .split(chunks[2]);
  
              // Disk Graph
-             let current_disk = self.disk_history.last().map(|&(_, v)| v).unwrap_or(0.0);
+             let current_disk = self.recent.disk.last().map(|&(_, v)| v).unwrap_or(0.0);
              let disk_info = self
-                 .sys
-                 .disks()
-                 .into_iter()
-                 .next()
-                 .map(|disk| {
-                     let total = disk.total_space() as f64 / 1_000_000_000.0; // Convert to GB
-                     let free = disk.available_space() as f64 / 1_000_000_000.0;
+                 .disk_space
+                 .map(|(total, available)| {
+                     let total = total as f64 / 1_000_000_000.0; // Convert to GB
+                     let free = available as f64 / 1_000_000_000.0;
                      let used = total - free;
                      (total, used)
                  })
@@ -447,22 +742,102 @@
                  .marker(symbols::Marker::Dot)
                  .graph_type(GraphType::Line)
                  .style(Style::default().fg(Color::Yellow))
-                 .data(&self.disk_history);
+                 .data(&charts.disk);
  
              let disk_chart = Chart::new(vec![disk_dataset])
                  .block(Block::default().title(disk_title.as_str()).borders(Borders::ALL))
-                 .x_axis(tui::widgets::Axis::default().bounds([
-                     self.disk_history.first().map(|p| p.0).unwrap_or(0.0),
-                     self.disk_history.last().map(|p| p.0).unwrap_or(100.0),
-                 ]))
+                 .x_axis(tui::widgets::Axis::default().bounds(x_bounds(&charts.disk)))
                  .y_axis(tui::widgets::Axis::default().bounds([0.0, 100.0]));
  
-             f.render_widget(disk_chart, chunks[2]);
+             f.render_widget(disk_chart, disk_row[0]);
+ 
+             // Temperature chart with critical thresholds
+             let cpu_critical = sensors::hottest(
+                 &self.sensor_readings,
+                 &[SensorKind::CpuPackage, SensorKind::CpuCore],
+             )
+             .and_then(|(_, c)| c)
+             .unwrap_or(sensors::DEFAULT_CPU_CRITICAL);
+             let nvme_critical = sensors::hottest(&self.sensor_readings, &[SensorKind::Nvme])
+                 .and_then(|(_, c)| c)
+                 .unwrap_or(sensors::DEFAULT_NVME_CRITICAL);
+ 
+             let temp_x = x_bounds(if charts.cpu_temp.is_empty() { &charts.nvme_temp } else { &charts.cpu_temp });
+             let cpu_critical_line = vec![(temp_x[0], cpu_critical), (temp_x[1], cpu_critical)];
+             let nvme_critical_line = vec![(temp_x[0], nvme_critical), (temp_x[1], nvme_critical)];
+ 
+             let current_cpu_temp = self.recent.cpu_temp.last().map(|&(_, v)| v);
+             let current_nvme_temp = self.recent.nvme_temp.last().map(|&(_, v)| v);
+             let cpu_temp_label = format!(
+                 "CPU {}",
+                 current_cpu_temp.map(|t| format!("{:.0}°C", t)).unwrap_or_else(|| "n/a".to_string())
+             );
+             let nvme_temp_label = format!(
+                 "NVMe {}",
+                 current_nvme_temp.map(|t| format!("{:.0}°C", t)).unwrap_or_else(|| "n/a".to_string())
+             );
+ 
+             let mut temp_datasets = vec![
+                 Dataset::default()
+                     .name(cpu_temp_label.as_str())
+                     .marker(symbols::Marker::Dot)
+                     .graph_type(GraphType::Line)
+                     .style(Style::default().fg(Color::LightRed))
+                     .data(&charts.cpu_temp),
+                 Dataset::default()
+                     .name("CPU crit")
+                     .marker(symbols::Marker::Dot)
+                     .graph_type(GraphType::Line)
+                     .style(Style::default().fg(Color::Red))
+                     .data(&cpu_critical_line),
+             ];
+             if !charts.nvme_temp.is_empty() {
+                 temp_datasets.push(
+                     Dataset::default()
+                         .name(nvme_temp_label.as_str())
+                         .marker(symbols::Marker::Dot)
+                         .graph_type(GraphType::Line)
+                         .style(Style::default().fg(Color::LightYellow))
+                         .data(&charts.nvme_temp),
+                 );
+                 temp_datasets.push(
+                     Dataset::default()
+                         .name("NVMe crit")
+                         .marker(symbols::Marker::Dot)
+                         .graph_type(GraphType::Line)
+                         .style(Style::default().fg(Color::Yellow))
+                         .data(&nvme_critical_line),
+                 );
+             }
+ 
+             let freq_label = match &self.cpu_frequency {
+                 Some(freq) => format!(
+                     " - {:.2}/{:.2} GHz{}",
+                     freq.avg_mhz / 1000.0,
+                     freq.max_mhz / 1000.0,
+                     if self.thermal_throttled { " THROTTLED" } else { "" }
+                 ),
+                 None => String::new(),
+             };
+             let temp_title = Span::styled(
+                 format!("Temperatures{}", freq_label),
+                 Style::default().fg(if self.thermal_throttled { Color::Red } else { Color::White }),
+             );
+ 
+             let temp_chart = Chart::new(temp_datasets)
+                 .block(Block::default().title(temp_title).borders(Borders::ALL))
+                 .x_axis(tui::widgets::Axis::default().bounds(temp_x))
+                 .y_axis(
+                     tui::widgets::Axis::default()
+                         .bounds([0.0, cpu_critical.max(nvme_critical) + 10.0]),
+                 );
+ 
+             f.render_widget(temp_chart, disk_row[1]);
  
              // Network Graph
              // Get the latest network speed values
-             let current_received = self.received_history.last().map(|&(_, v)| v).unwrap_or(0.0);
-             let current_transmitted = self.transmitted_history.last().map(|&(_, v)| v).unwrap_or(0.0);
+             let current_received = self.recent.received.last().map(|&(_, v)| v).unwrap_or(0.0);
+             let current_transmitted = self.recent.transmitted.last().map(|&(_, v)| v).unwrap_or(0.0);
  
              // Create the label with actual speeds
              let net_label = format!(
@@ -479,28 +854,35 @@
                  .marker(symbols::Marker::Dot)
                  .graph_type(GraphType::Line)
                  .style(Style::default().fg(Color::Blue))
-                 .data(&self.received_history);
+                 .data(&charts.received);
  
              let transmitted_dataset = Dataset::default()
                  .name("Upload")
                  .marker(symbols::Marker::Dot)
                  .graph_type(GraphType::Line)
                  .style(Style::default().fg(Color::Magenta))
-                 .data(&self.transmitted_history);
+                 .data(&charts.transmitted);
+ 
+             // The bottom chart carries the start and end times of a selected time range
+             let mut net_x_axis = tui::widgets::Axis::default().bounds(x_bounds(&charts.received));
+             if let Some((from, to)) = range_window {
+                 net_x_axis = net_x_axis.labels(vec![
+                     Span::raw(authlog::format_local_time(from)),
+                     Span::raw(authlog::format_local_time(to)),
+                 ]);
+             }
  
              // Create the chart with both datasets
              let net_chart = Chart::new(vec![received_dataset, transmitted_dataset])
                  .block(Block::default().title(net_title).borders(Borders::ALL))
-                 .x_axis(tui::widgets::Axis::default().bounds([
-                     self.received_history.first().map(|p| p.0).unwrap_or(0.0),
-                     self.received_history.last().map(|p| p.0).unwrap_or(100.0),
-                 ]))
+                 .x_axis(net_x_axis)
                  .y_axis(
                      tui::widgets::Axis::default().bounds([
                          0.0,
-                         self.received_history
+                         charts
+                             .received
                              .iter()
-                             .chain(self.transmitted_history.iter())
+                             .chain(charts.transmitted.iter())
                              .map(|p| p.1)
                              .fold(0.0, f64::max),
                      ]),
@@ -508,46 +890,71 @@
  
              f.render_widget(net_chart, chunks[3]);
  
+             // Logs row: recent events on the left, top attackers on the right
+             let log_row = Layout::default()
+                 .direction(Direction::Horizontal)
+                 .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
+                 .split(chunks[4]);
+ 
              // Logs section
+             let log_title = match &self.status.auth_error {
+                 Some(e) => Span::styled(
+                     format!("Security Logs (↑↓ to scroll, l: browse) - auth log unavailable: {}", e),
+                     Style::default().fg(Color::Red),
+                 ),
+                 None => Span::raw("Security Logs (↑↓ to scroll, l: browse)"),
+             };
              let log_block = Block::default()
-                 .title("System Logs (↑↓ to scroll)")
+                 .title(log_title)
                  .borders(Borders::ALL);
  
              let logs: Vec<ListItem> = self
-                 .ssh_attempts
+                 .security_events
                  .iter()
                  .rev()
                  .skip(self.log_scroll)
                  .take(3)
-                 .map(|(timestamp, ip, status)| {
-                     let time = SystemTime::now()
-                         .duration_since(UNIX_EPOCH)
-                         .unwrap()
-                         .as_secs() as i64
-                         - timestamp.parse::<i64>().unwrap_or(0);
- 
-                     let time_str = if time < 60 {
-                         format!("{}s ago", time)
-                     } else if time < 3600 {
-                         format!("{}m ago", time / 60)
-                     } else {
-                         format!("{}h ago", time / 3600)
-                     };
+                 .map(|(timestamp, event, geo)| {
+                     let time_str = format_ago(*timestamp);
+                     let style = Style::default().fg(severity_color(event.severity));
+                     let mut spans = vec![Span::styled(
+                         format!("[{}] ", time_str),
+                         Style::default().fg(Color::Gray),
+                     )];
+ 
+                     match &event.ssh {
+                         Some(ssh) => {
+                             let geo_str = geo
+                                 .as_ref()
+                                 .map(|g| format!(" [{}]", g.summary()))
+                                 .unwrap_or_default();
+                             spans.push(Span::styled(
+                                 format!("{}{}: {}", ssh.ip_or_unknown(), geo_str, ssh.status()),
+                                 style,
+                             ));
+                             spans.push(Span::styled(
+                                 format!(
+                                     " {}{}{}",
+                                     ssh.event_type.as_str(),
+                                     ssh.username
+                                         .as_ref()
+                                         .map(|u| format!(" {}", u))
+                                         .unwrap_or_default(),
+                                     ssh.method
+                                         .as_ref()
+                                         .map(|m| format!(" ({})", m))
+                                         .unwrap_or_default()
+                                 ),
+                                 Style::default().fg(Color::Gray),
+                             ));
+                         }
+                         None => spans.push(Span::styled(
+                             format!("{}: {}", event.category.as_str(), event.summary()),
+                             style,
+                         )),
+                     }
  
-                     ListItem::new(Spans::from(vec![
-                         Span::styled(
-                             format!("[{}] ", time_str),
-                             Style::default().fg(Color::Gray),
-                         ),
-                         Span::styled(
-                             format!("{}: {}", ip, status),
-                             Style::default().fg(if status == "Failed" {
-                                 Color::Red
-                             } else {
-                                 Color::Green
-                             }),
-                         ),
-                     ]))
+                     ListItem::new(Spans::from(spans))
                  })
                  .collect();
  
@@ -555,15 +962,114 @@
                  .block(log_block)
                  .style(Style::default().fg(Color::White));
  
-             f.render_widget(log_list, chunks[4]);
+             f.render_widget(log_list, log_row[0]);
+ 
+             // Top attackers over the report window
+             let header = Row::new(vec!["IP", "Fails", "Recent", "First", "Last", "Users"])
+                 .style(Style::default().fg(Color::Gray));
+             let rows: Vec<Row> = self
+                 .status
+                 .brute_force
+                 .attackers
+                 .iter()
+                 .map(|a| {
+                     let banned = self.status.active_bans.contains(&a.ip);
+                     Row::new(vec![
+                         Cell::from(if banned { format!("{} ⛔", a.ip) } else { a.ip.clone() }),
+                         Cell::from(a.failures.to_string()),
+                         Cell::from(a.recent_failures.to_string()),
+                         Cell::from(format_ago(a.first_seen)),
+                         Cell::from(format_ago(a.last_seen)),
+                         Cell::from(a.usernames.join(",")),
+                     ])
+                     .style(Style::default().fg(if a.brute_forcer { Color::Red } else { Color::White }))
+                 })
+                 .collect();
+ 
+             let top_users = self
+                 .status
+                 .brute_force
+                 .users
+                 .iter()
+                 .take(3)
+                 .map(|u| format!("{}({}/{} IPs)", u.username, u.failures, u.distinct_ips))
+                 .collect::<Vec<_>>()
+                 .join(" ");
+             let ban_status = match &self.config.ban {
+                 Some(ban) => match &self.status.ban_error {
+                     Some(e) => format!(" - {} banned, ban error: {}", self.status.active_bans.len(), e),
+                     None => format!(
+                         " - {} banned{}",
+                         self.status.active_bans.len(),
+                         if ban.dry_run { " (dry-run)" } else { "" }
+                     ),
+                 },
+                 None => String::new(),
+             };
+             let attackers_title = format!(
+                 "Top attackers (g: by country) - {} brute-forcing{} - users: {}",
+                 self.status.brute_force.brute_forcers.len(),
+                 ban_status,
+                 if top_users.is_empty() { "-".to_string() } else { top_users }
+             );
+ 
+             let attackers_table = Table::new(rows)
+                 .header(header)
+                 .block(Block::default().title(attackers_title).borders(Borders::ALL))
+                 .widths(&[
+                     Constraint::Length(16),
+                     Constraint::Length(6),
+                     Constraint::Length(6),
+                     Constraint::Length(8),
+                     Constraint::Length(8),
+                     Constraint::Min(10),
+                 ]);
+ 
+             // Failed attempts per country over the same window
+             let country_rows: Vec<Row> = self
+                 .status
+                 .brute_force
+                 .countries
+                 .iter()
+                 .map(|c| {
+                     Row::new(vec![
+                         c.country.clone().unwrap_or_else(|| "??".to_string()),
+                         c.failures.to_string(),
+                         c.distinct_ips.to_string(),
+                     ])
+                 })
+                 .collect();
+             let countries_title = match (&self.status.geoip_error, self.status.geoip_enabled) {
+                 (Some(e), _) => format!("Attempts by country (g: by IP) - GeoIP error: {}", e),
+                 (None, true) => "Attempts by country (g: by IP)".to_string(),
+                 (None, false) => "Attempts by country (g: by IP) - no GeoIP database configured".to_string(),
+             };
+             let countries_table = Table::new(country_rows)
+                 .header(
+                     Row::new(vec!["Country", "Fails", "IPs"]).style(Style::default().fg(Color::Gray)),
+                 )
+                 .block(Block::default().title(countries_title).borders(Borders::ALL))
+                 .widths(&[Constraint::Length(8), Constraint::Length(8), Constraint::Length(8)]);
  
-             // Author section
+             match self.attackers_view {
+                 AttackersView::Ips => f.render_widget(attackers_table, log_row[1]),
+                 AttackersView::Countries => f.render_widget(countries_table, log_row[1]),
+             }
+ 
+             // Author section, replaced by the prompt while a custom time range is typed
              let info_block = Block::default().borders(Borders::ALL);
  
-             let info_text = Paragraph::new("Rymentz - kaspa:qqngpnpwrfhexgu8kzk3lteu5fakh6fylmt53gt7qwtf4vttjyvfyrnr8shwa")
-                 .style(Style::default().fg(Color::White))
-                 .block(info_block)
-                 .alignment(Alignment::Center);
+             let info_text = match &self.range_input {
+                 Some(input) => Paragraph::new(format!(
+                     "Time range: {}_ (e.g. 45m, 3d, 2026-10-17 08:00 6h) - Enter: show, Esc: cancel",
+                     input
+                 ))
+                 .style(Style::default().fg(Color::Yellow)),
+                 None => Paragraph::new("Rymentz - kaspa:qqngpnpwrfhexgu8kzk3lteu5fakh6fylmt53gt7qwtf4vttjyvfyrnr8shwa")
+                     .style(Style::default().fg(Color::White)),
+             }
+             .block(info_block)
+             .alignment(Alignment::Center);
  
              f.render_widget(info_text, chunks[5]);
          })?;
@@ -571,6 +1077,29 @@
      }
  }
  
+ // For the commands that only read the database, which must exist. Those reading the
+ // series also need it migrated, which they can't do themselves
+ fn open_read_only(migrated: bool) -> Result<Connection, Error> {
+     if !Path::new(DB_PATH).exists() {
+         eprintln!("No {} in the current directory", DB_PATH);
+         std::process::exit(1);
+     }
+     let db = db::open_read_only(DB_PATH)?;
+     let version = migrations::current_version(&db)?;
+     if migrated && version < migrations::latest_version() {
+         eprintln!("{} has schema version {}: run `server_monitor migrate` first", DB_PATH, version);
+         std::process::exit(1);
+     }
+     Ok(db)
+ }
+
+ fn migrate(db: &Connection, dry_run: bool) -> Result<migrations::Report, Error> {
+     migrations::run(db, Path::new(DB_PATH), dry_run).map_err(|e| {
+         eprintln!("Migration of {} failed: {}", DB_PATH, e);
+         Error::from(e)
+     })
+ }
+
  impl From<rusqlite::Error> for Error {
      fn from(_: rusqlite::Error) -> Error {
          Error::Rusqlite(())
@@ -604,54 +1133,233 @@
  }
  
  fn main() -> Result<(), Error> {
+     let args: Vec<String> = std::env::args().skip(1).collect();
+     // Only the monitor and the commands changing the database open it read-write
+     match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+         [] => {}
+         ["series"] => {
+             let db = open_read_only(true)?;
+             for series in SeriesStore::list(&db)? {
+                 let unit = series.unit.as_deref().unwrap_or("-");
+                 if series.labels == Labels::new() {
+                     println!("{} ({})", series.name, unit);
+                 } else {
+                     println!("{} {} ({})", series.name, series.labels, unit);
+                 }
+             }
+             return Ok(());
+         }
+         ["series", name] | ["series", name, _] => {
+             // Samples of the last hour
+             let labels = Labels::decode(args.get(2).map(String::as_str).unwrap_or(""));
+             let db = open_read_only(true)?;
+             let series = match SeriesStore::find(&db, name, &labels)? {
+                 Some(series) => series,
+                 None => {
+                     eprintln!("No series {} with labels '{}'", name, labels);
+                     std::process::exit(1);
+                 }
+             };
+             let now_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+             for (ts, value) in SeriesStore::query(&db, &series, now_ms - 3_600_000, now_ms + 1)? {
+                 let time = authlog::format_local_time((ts / 1000) as u64);
+                 println!("{}\t{}\t{}", time, value, series.unit.as_deref().unwrap_or(""));
+             }
+             return Ok(());
+         }
+         ["export", ..] => {
+             let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+             let options = match export::Options::parse(&args[1..], now) {
+                 Ok(options) => options,
+                 Err(message) => {
+                     eprintln!("{}", message);
+                     eprintln!("Usage: server_monitor export <metrics|ssh_attempts> [--from <time>] [--to <time>] [--columns <a,b,...>] [--format csv|jsonl|parquet] [--output <file>]");
+                     std::process::exit(2);
+                 }
+             };
+             match export::export(&open_read_only(true)?, &options) {
+                 Ok(rows) => eprintln!("Exported {} rows of {}", rows, options.table),
+                 Err(e) => {
+                     eprintln!("Export failed: {}", e);
+                     std::process::exit(1);
+                 }
+             }
+             return Ok(());
+         }
+         ["backup", ..] => {
+             let options = match backup::Options::parse(&args[1..]) {
+                 Ok(options) => options,
+                 Err(message) => {
+                     eprintln!("{}", message);
+                     eprintln!("Usage: server_monitor backup [--dir <dir>] [--keep <n>] [--compress]");
+                     std::process::exit(2);
+                 }
+             };
+             let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+             match backup::backup(&open_read_only(false)?, &options, now) {
+                 Ok((path, removed)) => {
+                     println!("Backup: {}", path.display());
+                     for path in removed {
+                         println!("Removed: {}", path.display());
+                     }
+                 }
+                 Err(e) => {
+                     eprintln!("Backup failed: {}", e);
+                     std::process::exit(1);
+                 }
+             }
+             return Ok(());
+         }
+         ["snapshot", path] => {
+             if let Err(e) = backup::snapshot(&open_read_only(false)?, Path::new(path), Path::new(DB_PATH)) {
+                 eprintln!("Snapshot failed: {}", e);
+                 std::process::exit(1);
+             }
+             println!("Snapshot: {}", path);
+             return Ok(());
+         }
+         ["restore", path] => {
+             match backup::restore(&mut db::open(DB_PATH)?, Path::new(DB_PATH), Path::new(path)) {
+                 Ok(version) => {
+                     println!("Restored {} from {} (schema version {})", DB_PATH, path, version);
+                     println!("Previous content: {}.pre-restore", DB_PATH);
+                     if version < migrations::latest_version() {
+                         println!("The schema is migrated to version {} on the next start", migrations::latest_version());
+                     }
+                 }
+                 Err(e) => {
+                     eprintln!("Restore failed: {}", e);
+                     std::process::exit(1);
+                 }
+             }
+             return Ok(());
+         }
+         ["vacuum"] => {
+             // The rewrite holds an exclusive lock far longer than the monitor waits for one
+             if backup::monitor_running() {
+                 eprintln!("The monitor is running in this directory: stop it before vacuuming");
+                 std::process::exit(1);
+             }
+             if retention::enable_incremental_vacuum(&db::open(DB_PATH)?)? {
+                 println!("Switched {} to incremental auto_vacuum", DB_PATH);
+             } else {
+                 println!("{} already uses incremental auto_vacuum", DB_PATH);
+             }
+             return Ok(());
+         }
+         ["migrate"] | ["migrate", "--dry-run"] => {
+             let dry_run = args.len() == 2;
+             let db = if dry_run { open_read_only(false)? } else { db::open(DB_PATH)? };
+             let report = migrate(&db, dry_run)?;
+             println!("{}", report);
+             if report.too_new() {
+                 std::process::exit(1);
+             }
+             return Ok(());
+         }
+         _ => {
+             eprintln!("Usage: server_monitor [migrate [--dry-run] | series [<name> [<label>=<value>,...]] | export <metrics|ssh_attempts> [options] | backup [options] | snapshot <file> | restore <file> | vacuum]");
+             std::process::exit(2);
+         }
+     }
+
+     let config = match Config::from_env() {
+         Ok(config) => config,
+         Err(e) => {
+             eprintln!("Invalid configuration: {}", e);
+             std::process::exit(2);
+         }
+     };
+
+     // Brings the schema up to date before the interface starts
+     let db = db::open(DB_PATH)?;
+     let report = migrate(&db, false)?;
+     if report.too_new() {
+         eprintln!("{}", report);
+         std::process::exit(1);
+     }
+
+     // Sampling and database work run on their own threads; this one only handles keys and draws
+     let storage = Storage::new(config.clone(), db)?;
+     let (request_tx, request_rx) = mpsc::channel();
+     let (update_tx, update_rx) = mpsc::channel();
+     let (sample_tx, sample_rx) = mpsc::channel();
+     let mut monitor = ServerMonitor::new(config.clone(), db::open_read_only(DB_PATH)?, request_tx.clone());
+     monitor.load_history()?;
+
      enable_raw_mode()?;
      let mut stdout = stdout();
      execute!(stdout, EnterAlternateScreen)?;
      let backend = CrosstermBackend::new(stdout);
      let mut terminal = Terminal::new(backend)?;
- 
-     let mut monitor = ServerMonitor::new()?;
-     let mut cleanup_counter = 0;
-     let mut last_update = SystemTime::now();
- 
+
+     // From here on, every exit goes through the shutdown and cleanup below
+     let storage_thread = storage.spawn(UPDATE_INTERVAL, request_rx, update_tx);
+     Sampler::new(config).spawn(UPDATE_INTERVAL, sample_tx, request_tx);
+     let result = run_interface(&mut monitor, &mut terminal, &sample_rx, &update_rx);
+
+     // The storage thread writes what is still buffered before stopping
+     let _ = monitor.storage.send(Request::Shutdown);
+     let stored = match storage_thread.join() {
+         Ok(stored) => stored.map_err(|e| e.to_string()),
+         Err(_) => Err("storage thread panicked".to_string()),
+     };
+
+     // Cleanup
+     let _ = disable_raw_mode();
+     let _ = execute!(terminal.backend_mut(), LeaveAlternateScreen);
+     let _ = terminal.show_cursor();
+
+     if let Err(e) = &result {
+         eprintln!("Terminal error: {}", e);
+     }
+     if let Err(e) = &stored {
+         eprintln!("Database error in {}: {}", DB_PATH, e);
+     }
+     if result.is_err() || stored.is_err() {
+         std::process::exit(1);
+     }
+     Ok(())
+ }
+
+ // Handles keys, samples and updates and draws until q is pressed. Database errors are shown
+ // in the interface; only a terminal error or the storage thread dying ends it early
+ fn run_interface<B: tui::backend::Backend>(
+     monitor: &mut ServerMonitor,
+     terminal: &mut Terminal<B>,
+     samples: &Receiver<Sample>,
+     updates: &Receiver<Update>,
+ ) -> std::io::Result<()> {
      loop {
          // Event handling with timeout
          if event::poll(Duration::from_millis(250))? {
              if let Event::Key(key) = event::read()? {
-                 match key.code {
-                     KeyCode::Char('q') => break,
-                     KeyCode::Up => monitor.scroll_logs_up(),
-                     KeyCode::Down => monitor.scroll_logs_down(),
-                     _ => {}
+                 if key.code == KeyCode::Char('q') && !monitor.is_typing() {
+                     return Ok(());
                  }
+                 let result = monitor.key(key.code);
+                 monitor.error = result.err().map(|e| e.to_string());
              }
          }
- 
-         // Periodic update every 2 seconds
-         if SystemTime::now().duration_since(last_update)?.as_secs() >= 2 {
-             monitor.update()?;
-             last_update = SystemTime::now();
- 
-             cleanup_counter += 1;
-            if cleanup_counter >= 302400 { 
-                monitor.cleanup()?;
-                cleanup_counter = 0;
-            }
+
+         // Samples and database updates received since the last frame
+         while let Ok(sample) = samples.try_recv() {
+             if let Err(e) = monitor.apply_sample(sample) {
+                 monitor.error = Some(e.to_string());
+             }
          }
- 
+         loop {
+             match updates.try_recv() {
+                 Ok(update) => monitor.apply_update(update),
+                 Err(TryRecvError::Empty) => break,
+                 // The storage thread only stops on a panic, reported by the caller
+                 Err(TryRecvError::Disconnected) => return Ok(()),
+             }
+         }
+
          // Render the interface
-         if let Err(e) = monitor.draw(&mut terminal) {
+         if let Err(e) = monitor.draw(terminal) {
              eprintln!("Draw error: {}", e);
          }
- 
-         // Pause to reduce CPU usage
-         thread::sleep(Duration::from_millis(250));
      }
- 
-     // Cleanup
-     disable_raw_mode()?;
-     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-     terminal.show_cursor()?;
- 
-     Ok(())
  }
\ No newline at end of file
@@ -0,0 +1,922 @@
+// Versioned schema of metrics.db: PRAGMA user_version is the last migration applied, and
+// pending migrations run in order, each in its own transaction, after a backup of the
+// database. Migrations are never edited once released; schema changes add a new one.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, Result};
+
+use crate::db;
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    apply: fn(&Connection) -> Result<()>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "schema of the versions before migrations (tables created if missing, ssh_attempts moved into security_events)",
+        apply: baseline,
+    },
+    Migration {
+        version: 2,
+        description: "metrics with millisecond timestamps and byte units, without the duplicate memory_used; metric_units table",
+        apply: normalize_metrics,
+    },
+    Migration {
+        version: 3,
+        description: "metrics moved to the long-format series and samples tables; metrics becomes a view",
+        apply: metrics_to_samples,
+    },
+    Migration {
+        version: 4,
+        description: "process_stats, pressure, cgroup_stats and sensors with millisecond timestamps",
+        apply: raw_tables_to_ms,
+    },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn current_version(db: &Connection) -> Result<u32> {
+    db.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+// What `run` did, or would do in a dry run
+pub struct Report {
+    pub from: u32,
+    pub to: u32,
+    pub dry_run: bool,
+    pub applied: Vec<&'static Migration>,
+    pub backup: Option<PathBuf>,
+    // Schema objects and row counts that changed
+    pub changes: Vec<String>,
+}
+
+impl Report {
+    // The database was written by a newer version of the monitor
+    pub fn too_new(&self) -> bool {
+        self.from > latest_version()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.too_new() {
+            return write!(
+                f,
+                "Schema version {} is newer than the latest known ({}): upgrade the monitor",
+                self.from,
+                latest_version()
+            );
+        }
+        if self.applied.is_empty() {
+            return write!(f, "Schema is up to date (version {})", self.from);
+        }
+        let verb = if self.dry_run { "Would migrate" } else { "Migrated" };
+        let mut lines = vec![format!("{} schema from version {} to {}:", verb, self.from, self.to)];
+        for migration in &self.applied {
+            lines.push(format!("  v{}: {}", migration.version, migration.description));
+        }
+        if let Some(backup) = &self.backup {
+            lines.push(format!("Backup: {}", backup.display()));
+        }
+        if !self.changes.is_empty() {
+            lines.push(format!("Changes{}:", if self.dry_run { " (not applied)" } else { "" }));
+            lines.extend(self.changes.iter().map(|change| format!("  {}", change)));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+// Applies the pending migrations of the database at `path`. A dry run applies them to a
+// temporary copy, to report the changes without writing to the database.
+pub fn run(db: &Connection, path: &Path, dry_run: bool) -> Result<Report> {
+    let from = current_version(db)?;
+    let pending: Vec<&'static Migration> = MIGRATIONS.iter().filter(|m| m.version > from).collect();
+    let mut report = Report {
+        from,
+        to: pending.last().map(|m| m.version).unwrap_or(from),
+        dry_run,
+        applied: Vec::new(),
+        backup: None,
+        changes: Vec::new(),
+    };
+    if report.too_new() || pending.is_empty() {
+        return Ok(report);
+    }
+
+    if dry_run {
+        // SQLite keeps a temporary database in memory until it grows, then in a temporary file
+        let mut copy = Connection::open("")?;
+        let backup = Backup::new(db, &mut copy)?;
+        // All pages in one step, so writes of the monitor meanwhile don't restart the copy
+        while backup.step(-1)? != StepResult::Done {
+            thread::sleep(Duration::from_millis(250));
+        }
+        drop(backup);
+        apply_all(&copy, &pending, &mut report)?;
+    } else {
+        if has_tables(db)? {
+            report.backup = Some(backup(db, path, from)?);
+        }
+        apply_all(db, &pending, &mut report)?;
+    }
+    Ok(report)
+}
+
+fn apply_all(db: &Connection, pending: &[&'static Migration], report: &mut Report) -> Result<()> {
+    let before = snapshot(db)?;
+    for migration in pending {
+        apply(db, migration)?;
+        report.applied.push(migration);
+    }
+    report.changes = diff(&before, &snapshot(db)?);
+    Ok(())
+}
+
+fn apply(db: &Connection, migration: &Migration) -> Result<()> {
+    db.execute_batch("SAVEPOINT migration")?;
+    let result = (|| {
+        (migration.apply)(db)?;
+        db.execute_batch(&format!("PRAGMA user_version = {}", migration.version))
+    })();
+    match result {
+        Ok(()) => db.execute_batch("RELEASE migration"),
+        Err(e) => {
+            db.execute_batch("ROLLBACK TO migration; RELEASE migration")?;
+            Err(e)
+        }
+    }
+}
+
+fn has_tables(db: &Connection) -> Result<bool> {
+    db.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table'",
+        [],
+        |row| row.get(0),
+    )
+}
+
+// Copies the database next to it as <path>.v<version>-<timestamp>.bak
+fn backup(db: &Connection, path: &Path, version: u32) -> Result<PathBuf> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let backup = PathBuf::from(format!("{}.v{}-{}.bak", path.display(), version, timestamp));
+    db.execute("VACUUM INTO ?1", [backup.to_string_lossy()])?;
+    Ok(backup)
+}
+
+// Schema objects (name → type and SQL) and row counts of the tables
+struct Snapshot {
+    objects: BTreeMap<String, (String, Option<String>)>,
+    rows: BTreeMap<String, i64>,
+}
+
+fn snapshot(db: &Connection) -> Result<Snapshot> {
+    let objects: BTreeMap<String, (String, Option<String>)> = db
+        .prepare("SELECT name, type, sql FROM sqlite_master WHERE name NOT LIKE 'sqlite_%'")?
+        .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+        .collect::<Result<_>>()?;
+    let mut rows = BTreeMap::new();
+    for (name, (object_type, _)) in &objects {
+        if object_type == "table" {
+            let count = db.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", name), [], |row| row.get(0))?;
+            rows.insert(name.clone(), count);
+        }
+    }
+    Ok(Snapshot { objects, rows })
+}
+
+// "+ table metrics_1m", "- table ssh_attempts", "~ view ssh_attempts (was table)",
+// "security_events: 0 → 1234 rows"
+fn diff(before: &Snapshot, after: &Snapshot) -> Vec<String> {
+    let mut changes = Vec::new();
+    for (name, (object_type, sql)) in &after.objects {
+        match before.objects.get(name) {
+            None => changes.push(format!("+ {} {}", object_type, name)),
+            Some((old_type, _)) if old_type != object_type => {
+                changes.push(format!("~ {} {} (was {})", object_type, name, old_type))
+            }
+            Some((_, old_sql)) if old_sql != sql => changes.push(format!("~ {} {}", object_type, name)),
+            Some(_) => {}
+        }
+    }
+    for (name, (object_type, _)) in &before.objects {
+        if !after.objects.contains_key(name) {
+            changes.push(format!("- {} {}", object_type, name));
+        }
+    }
+    for (name, count) in &after.rows {
+        let old = before.rows.get(name).copied().unwrap_or(0);
+        if old != *count {
+            changes.push(format!("{}: {} → {} rows", name, old, count));
+        }
+    }
+    for (name, count) in &before.rows {
+        if !after.rows.contains_key(name) && *count > 0 {
+            changes.push(format!("{}: {} → 0 rows", name, count));
+        }
+    }
+    changes
+}
+
+// v1: every table of the versions that created the schema at startup. Databases from those
+// versions may be at any point of it, so each step only creates what is missing.
+fn baseline(db: &Connection) -> Result<()> {
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metrics (
+            timestamp INTEGER PRIMARY KEY,
+            cpu_usage REAL,
+            memory_usage REAL,
+            memory_total INTEGER,
+            memory_used INTEGER,
+            disk_usage REAL,
+            network_received INTEGER,
+            network_transmitted INTEGER,
+            kaspad_memory INTEGER,
+            kaspad_disk_read INTEGER,
+            kaspad_disk_write INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS idx_timestamp ON metrics(timestamp);
+
+        CREATE TABLE IF NOT EXISTS state (
+            key TEXT PRIMARY KEY,
+            value TEXT
+        );",
+    )?;
+
+    // Security events, with the ssh_attempts view of the SSH ones
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS security_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp INTEGER,
+            timestamp_us INTEGER,
+            cursor TEXT UNIQUE,
+            category TEXT,
+            event TEXT,
+            severity TEXT,
+            status TEXT,
+            actor TEXT,
+            target TEXT,
+            source TEXT,
+            details TEXT,
+            ip TEXT,
+            port INTEGER,
+            invalid_user INTEGER,
+            method TEXT,
+            fingerprint TEXT,
+            country TEXT,
+            city TEXT,
+            asn INTEGER,
+            org TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_security_category_status_timestamp
+            ON security_events(category, status, timestamp);
+        CREATE INDEX IF NOT EXISTS idx_security_timestamp ON security_events(timestamp);",
+    )?;
+    if db::object_type(db, "ssh_attempts")?.as_deref() == Some("table") {
+        move_ssh_attempts(db)?;
+    }
+    db.execute_batch(
+        "CREATE VIEW IF NOT EXISTS ssh_attempts AS
+        SELECT timestamp, timestamp_us, cursor, ip, status, event, actor AS username,
+            invalid_user, port, method, fingerprint, country, city, asn, org
+        FROM security_events
+        WHERE category = 'ssh';
+
+        CREATE TABLE IF NOT EXISTS bans (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            ip TEXT NOT NULL,
+            banned_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            unbanned_at INTEGER,
+            reason TEXT,
+            backend TEXT,
+            dry_run INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS idx_bans_active ON bans(unbanned_at, expires_at);
+
+        CREATE TABLE IF NOT EXISTS known_logins (
+            kind TEXT NOT NULL,
+            value TEXT NOT NULL,
+            first_seen INTEGER,
+            last_seen INTEGER,
+            source TEXT,
+            PRIMARY KEY (kind, value)
+        );
+        CREATE TABLE IF NOT EXISTS login_alerts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp INTEGER NOT NULL,
+            ip TEXT,
+            subnet TEXT,
+            username TEXT,
+            country TEXT,
+            reasons TEXT,
+            acknowledged_at INTEGER,
+            trusted INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS file_integrity (
+            path TEXT PRIMARY KEY,
+            kind TEXT,
+            present INTEGER,
+            sha256 TEXT,
+            size INTEGER,
+            mtime INTEGER,
+            ctime INTEGER,
+            inode INTEGER,
+            mode INTEGER,
+            uid INTEGER,
+            gid INTEGER,
+            content BLOB,
+            hashed_at INTEGER,
+            checked_at INTEGER,
+            changed_at INTEGER
+        );
+        -- Snapshots of the authorized_keys watcher file_integrity replaced
+        DELETE FROM state WHERE key LIKE 'authorized_keys:%';",
+    )?;
+
+    // kaspad process stats (file descriptors, threads, context switches)
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS process_stats (
+            timestamp INTEGER PRIMARY KEY,
+            open_fds INTEGER,
+            fd_soft_limit INTEGER,
+            fd_hard_limit INTEGER,
+            fd_usage_percent REAL,
+            threads INTEGER,
+            voluntary_ctxt_switches INTEGER,
+            nonvoluntary_ctxt_switches INTEGER,
+            ctxt_switch_rate REAL
+        )",
+        [],
+    )?;
+
+    // Pressure Stall Information, one row per scope (system / kaspad cgroup) and resource
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS pressure (
+            timestamp INTEGER,
+            scope TEXT,
+            resource TEXT,
+            some_avg10 REAL,
+            some_avg60 REAL,
+            full_avg10 REAL,
+            full_avg60 REAL,
+            PRIMARY KEY (timestamp, scope, resource)
+        )",
+        [],
+    )?;
+
+    // cgroup limits and CPU throttling of kaspad
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS cgroup_stats (
+            timestamp INTEGER PRIMARY KEY,
+            version TEXT,
+            path TEXT,
+            memory_limit INTEGER,
+            memory_usage INTEGER,
+            cpu_quota_cores REAL,
+            nr_periods INTEGER,
+            nr_throttled INTEGER,
+            throttled_usec INTEGER,
+            throttled_percent REAL
+        )",
+        [],
+    )?;
+
+    // Hardware sensors: temperatures (°C) and CPU frequency (MHz)
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS sensors (
+            timestamp INTEGER,
+            label TEXT,
+            kind TEXT,
+            value REAL,
+            unit TEXT,
+            critical REAL,
+            PRIMARY KEY (timestamp, label)
+        )",
+        [],
+    )?;
+
+    // 1-minute and 1-hour aggregates of the raw samples
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metrics_1m (
+            metric TEXT,
+            bucket INTEGER,
+            samples INTEGER,
+            min REAL,
+            max REAL,
+            avg REAL,
+            p95 REAL,
+            PRIMARY KEY (metric, bucket)
+        );
+        CREATE INDEX IF NOT EXISTS idx_metrics_1m_bucket ON metrics_1m(bucket);
+        CREATE TABLE IF NOT EXISTS metrics_1h (
+            metric TEXT,
+            bucket INTEGER,
+            samples INTEGER,
+            min REAL,
+            max REAL,
+            avg REAL,
+            p95 REAL,
+            PRIMARY KEY (metric, bucket)
+        );
+        CREATE INDEX IF NOT EXISTS idx_metrics_1h_bucket ON metrics_1h(bucket);",
+    )?;
+    Ok(())
+}
+
+// Moves the rows of the ssh_attempts table of the versions before security_events into it.
+// Older ones used PRIMARY KEY (timestamp, ip), which dropped distinct events from the same
+// IP within a second, and had no cursor or GeoIP columns.
+fn move_ssh_attempts(db: &Connection) -> Result<()> {
+    if !db::has_column(db, "ssh_attempts", "cursor")? {
+        db::rebuild_table(
+            db,
+            "ssh_attempts",
+            "timestamp INTEGER,
+            timestamp_us INTEGER,
+            cursor TEXT UNIQUE,
+            ip TEXT,
+            status TEXT,
+            event TEXT,
+            username TEXT,
+            invalid_user INTEGER,
+            port INTEGER,
+            method TEXT,
+            fingerprint TEXT,
+            country TEXT,
+            city TEXT,
+            asn INTEGER,
+            org TEXT",
+        )?;
+    }
+    db::add_missing_columns(
+        db,
+        "ssh_attempts",
+        &[
+            ("timestamp_us", "INTEGER"),
+            ("country", "TEXT"),
+            ("city", "TEXT"),
+            ("asn", "INTEGER"),
+            ("org", "TEXT"),
+        ],
+    )?;
+    db.execute_batch(
+        "-- The first versions only stored the status
+        UPDATE ssh_attempts
+        SET event = CASE status WHEN 'Failed' THEN 'failed' WHEN 'Success' THEN 'accepted' END
+        WHERE event IS NULL;
+
+        INSERT OR IGNORE INTO security_events (
+            timestamp, timestamp_us, cursor, category, event, severity, status, actor,
+            source, ip, port, invalid_user, method, fingerprint, country, city, asn, org
+        )
+        SELECT timestamp, COALESCE(timestamp_us, timestamp * 1000000), cursor, 'ssh', event,
+            -- Same mapping as SecurityEvent::from_ssh at the time
+            CASE
+                WHEN event = 'accepted' THEN 'notice'
+                WHEN event IN ('failed', 'invalid_user', 'max_auth_exceeded') THEN 'warning'
+                ELSE 'info'
+            END,
+            status, username, ip, ip, port, invalid_user, method, fingerprint,
+            country, city, asn, org
+        FROM ssh_attempts
+        ORDER BY timestamp;
+        DROP TABLE ssh_attempts;",
+    )
+}
+
+// Columns of the v2 metrics table: name, type, unit, description
+const METRICS_V2: [(&str, &str, &str, &str); 13] = [
+    ("timestamp_ms", "INTEGER PRIMARY KEY", "ms", "Unix time of the sample"),
+    ("kaspad_cpu_percent", "REAL", "%", "kaspad CPU usage relative to the cores it may use (cgroup quota or all cores)"),
+    ("kaspad_cpu_cores", "REAL", "cores", "kaspad CPU usage in cores, 1.0 = one core fully busy"),
+    ("kaspad_memory_bytes", "INTEGER", "bytes", "kaspad resident memory"),
+    ("kaspad_memory_percent", "REAL", "%", "kaspad memory relative to memory_limit_bytes"),
+    ("memory_limit_bytes", "INTEGER", "bytes", "Memory available to kaspad: cgroup limit or host total"),
+    ("system_memory_total_bytes", "INTEGER", "bytes", "Host memory"),
+    ("system_memory_used_bytes", "INTEGER", "bytes", "Host memory in use"),
+    ("disk_usage_percent", "REAL", "%", "Used space of the first disk, counting the kaspad data directory"),
+    ("network_received_bytes", "INTEGER", "bytes", "Received bytes counter (host or kaspad container)"),
+    ("network_transmitted_bytes", "INTEGER", "bytes", "Transmitted bytes counter (host or kaspad container)"),
+    ("kaspad_disk_read_bytes", "INTEGER", "bytes", "Bytes read by kaspad since the previous sample"),
+    ("kaspad_disk_written_bytes", "INTEGER", "bytes", "Bytes written by kaspad since the previous sample"),
+];
+
+// Units of the other time-series tables: table, column, unit, description
+const OTHER_UNITS: [(&str, &str, &str, &str); 14] = [
+    ("process_stats", "timestamp", "s", "Unix time of the sample"),
+    ("process_stats", "fd_usage_percent", "%", "Open file descriptors relative to the soft limit"),
+    ("process_stats", "ctxt_switch_rate", "1/s", "Context switches per second"),
+    ("cgroup_stats", "timestamp", "s", "Unix time of the sample"),
+    ("cgroup_stats", "memory_limit", "bytes", "cgroup memory limit"),
+    ("cgroup_stats", "memory_usage", "bytes", "cgroup memory usage"),
+    ("cgroup_stats", "cpu_quota_cores", "cores", "cgroup CPU quota"),
+    ("cgroup_stats", "throttled_usec", "us", "Cumulative CPU throttling"),
+    ("cgroup_stats", "throttled_percent", "%", "Share of the interval kaspad was throttled"),
+    ("pressure", "timestamp", "s", "Unix time of the sample"),
+    ("pressure", "some_avg10", "%", "PSI share of time some tasks stalled, 10s average"),
+    ("pressure", "full_avg10", "%", "PSI share of time all tasks stalled, 10s average"),
+    ("sensors", "timestamp", "s", "Unix time of the sample"),
+    ("sensors", "value", "unit column", "°C for temperatures, MHz for CPU frequency"),
+];
+
+// v2: the v1 metrics table truncated timestamps to seconds as its primary key (two samples
+// within a second failed), wrote kaspad's memory into both memory_used and kaspad_memory,
+// and named kaspad's CPU share of all cores cpu_usage. Byte values went through a
+// 1_024_000 factor both ways, so they convert as is.
+fn normalize_metrics(db: &Connection) -> Result<()> {
+    let columns = METRICS_V2
+        .iter()
+        .map(|(name, column_type, _, _)| format!("{} {}", name, column_type))
+        .collect::<Vec<_>>()
+        .join(", ");
+    db.execute(&format!("CREATE TABLE metrics_v2 ({})", columns), [])?;
+    db.execute(
+        "INSERT OR IGNORE INTO metrics_v2 (
+            timestamp_ms, kaspad_cpu_percent, kaspad_memory_bytes, kaspad_memory_percent,
+            system_memory_total_bytes, disk_usage_percent, network_received_bytes,
+            network_transmitted_bytes, kaspad_disk_read_bytes, kaspad_disk_written_bytes
+        )
+        SELECT timestamp * 1000, cpu_usage, kaspad_memory, memory_usage, memory_total,
+            disk_usage, network_received, network_transmitted, kaspad_disk_read, kaspad_disk_write
+        FROM metrics",
+        [],
+    )?;
+    db.execute("DROP TABLE metrics", [])?;
+    db.execute("ALTER TABLE metrics_v2 RENAME TO metrics", [])?;
+
+    // Rollups follow the renamed columns; memory_used only duplicated kaspad_memory
+    for table in ["metrics_1m", "metrics_1h"] {
+        db.execute(&format!("DELETE FROM {} WHERE metric = 'metrics.memory_used'", table), [])?;
+        for (old, new) in [
+            ("cpu_usage", "kaspad_cpu_percent"),
+            ("memory_usage", "kaspad_memory_percent"),
+            ("kaspad_memory", "kaspad_memory_bytes"),
+            ("disk_usage", "disk_usage_percent"),
+            ("network_received", "network_received_bytes"),
+            ("network_transmitted", "network_transmitted_bytes"),
+            ("kaspad_disk_read", "kaspad_disk_read_bytes"),
+            ("kaspad_disk_write", "kaspad_disk_written_bytes"),
+        ] {
+            db.execute(
+                &format!("UPDATE {} SET metric = ?2 WHERE metric = ?1", table),
+                [format!("metrics.{}", old), format!("metrics.{}", new)],
+            )?;
+        }
+    }
+
+    db.execute(
+        "CREATE TABLE metric_units (
+            table_name TEXT,
+            column_name TEXT,
+            unit TEXT,
+            description TEXT,
+            PRIMARY KEY (table_name, column_name)
+        )",
+        [],
+    )?;
+    let units = METRICS_V2
+        .iter()
+        .map(|(column, _, unit, description)| ("metrics", *column, *unit, *description))
+        .chain(OTHER_UNITS.iter().copied());
+    for (table, column, unit, description) in units {
+        db.execute(
+            "INSERT INTO metric_units (table_name, column_name, unit, description) VALUES (?1, ?2, ?3, ?4)",
+            [table, column, unit, description],
+        )?;
+    }
+    Ok(())
+}
+
+// v3: every metrics column becomes a series without labels, named after the column, and
+// metrics a view pivoting them back so existing queries keep working
+fn metrics_to_samples(db: &Connection) -> Result<()> {
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS series (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            labels TEXT NOT NULL DEFAULT '',
+            unit TEXT,
+            UNIQUE (name, labels)
+        );
+        CREATE TABLE IF NOT EXISTS samples (
+            series_id INTEGER NOT NULL REFERENCES series(id),
+            ts INTEGER NOT NULL,
+            value REAL,
+            PRIMARY KEY (series_id, ts)
+        );
+        CREATE INDEX IF NOT EXISTS idx_samples_ts ON samples(ts);",
+    )?;
+    let columns: Vec<(&str, &str)> = METRICS_V2[1..]
+        .iter()
+        .map(|(name, _, unit, _)| (*name, *unit))
+        .collect();
+    for (name, unit) in &columns {
+        db.execute(
+            "INSERT OR IGNORE INTO series (name, labels, unit) VALUES (?1, '', ?2)",
+            [name, unit],
+        )?;
+        db.execute(
+            &format!(
+                "INSERT OR REPLACE INTO samples (series_id, ts, value)
+                SELECT (SELECT id FROM series WHERE name = ?1 AND labels = ''), timestamp_ms, {}
+                FROM metrics WHERE {} IS NOT NULL",
+                name, name
+            ),
+            [name],
+        )?;
+    }
+    db.execute("DROP TABLE metrics", [])?;
+
+    let pivot = columns
+        .iter()
+        .map(|(name, unit)| {
+            let value = format!("MAX(CASE WHEN series.name = '{}' THEN samples.value END)", name);
+            match *unit {
+                "bytes" => format!("CAST({} AS INTEGER) AS {}", value, name),
+                _ => format!("{} AS {}", value, name),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",\n    ");
+    db.execute(
+        &format!(
+            "CREATE VIEW metrics AS
+            SELECT samples.ts AS timestamp_ms,
+                {}
+            FROM samples JOIN series ON series.id = samples.series_id
+            WHERE series.labels = ''
+            GROUP BY samples.ts",
+            pivot
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+// v4: the other time-series tables kept Unix seconds as (part of) their primary key, so two
+// samples within a second replaced each other. Their timestamp becomes timestamp_ms like
+// samples.ts; seconds times 1000 can't collide.
+const MS_TABLES: [&str; 4] = ["process_stats", "pressure", "cgroup_stats", "sensors"];
+
+fn raw_tables_to_ms(db: &Connection) -> Result<()> {
+    for table in MS_TABLES {
+        db.execute_batch(&format!(
+            "ALTER TABLE {table} RENAME COLUMN timestamp TO timestamp_ms;
+            UPDATE {table} SET timestamp_ms = timestamp_ms * 1000;",
+            table = table
+        ))?;
+        db.execute(
+            "UPDATE metric_units SET column_name = 'timestamp_ms', unit = 'ms'
+            WHERE table_name = ?1 AND column_name = 'timestamp'",
+            [table],
+        )?;
+    }
+    Ok(())
+}
+
+// In-memory database with the latest schema
+#[cfg(test)]
+pub fn test_database() -> Connection {
+    let db = Connection::open_in_memory().unwrap();
+    run(&db, Path::new(":memory:"), false).unwrap();
+    db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    // Tables with their columns, indexes and views, without the SQL text: tables created by
+    // older versions differ from the migrations' only in whitespace
+    fn schema(db: &Connection) -> Vec<String> {
+        let objects: Vec<(String, String, Option<String>)> = db
+            .prepare("SELECT type, name, tbl_name FROM sqlite_master WHERE name NOT LIKE 'sqlite_%' ORDER BY type, name")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        objects
+            .into_iter()
+            .map(|(object_type, name, table)| {
+                if object_type != "table" {
+                    return format!("{} {} on {}", object_type, name, table.unwrap_or_default());
+                }
+                let columns: Vec<String> = db
+                    .prepare(&format!("PRAGMA table_info({})", name))
+                    .unwrap()
+                    .query_map([], |row| {
+                        Ok(format!("{} {} pk{}", row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, i64>(5)?))
+                    })
+                    .unwrap()
+                    .collect::<Result<_>>()
+                    .unwrap();
+                format!("table {} ({})", name, columns.join(", "))
+            })
+            .collect()
+    }
+
+    // A database of the versions before migrations: the metrics table, ssh_attempts before
+    // security_events, sensors in seconds, rollups with the old metric names
+    fn baseline_era(dir: &TempDir) -> Connection {
+        let db = Connection::open(dir.path().join("metrics.db")).unwrap();
+        db.execute_batch(
+            "CREATE TABLE metrics (
+                timestamp INTEGER PRIMARY KEY,
+                cpu_usage REAL,
+                memory_usage REAL,
+                memory_total INTEGER,
+                memory_used INTEGER,
+                disk_usage REAL,
+                network_received INTEGER,
+                network_transmitted INTEGER,
+                kaspad_memory INTEGER,
+                kaspad_disk_read INTEGER,
+                kaspad_disk_write INTEGER
+            );
+            CREATE INDEX idx_timestamp ON metrics(timestamp);
+            INSERT INTO metrics VALUES
+                (1792310400, 12.5, 40.0, 8000000000, 3200000000, 61.0, 1000, 2000, 3200000000, 10, 20),
+                (1792310402, 13.0, 40.5, 8000000000, 3240000000, 61.0, 1500, 2600, 3240000000, NULL, NULL);
+
+            CREATE TABLE ssh_attempts (
+                timestamp INTEGER,
+                ip TEXT,
+                status TEXT,
+                PRIMARY KEY (timestamp, ip)
+            );
+            INSERT INTO ssh_attempts VALUES
+                (1792310400, '192.0.2.1', 'Failed'),
+                (1792310401, '192.0.2.2', 'Success');
+
+            CREATE TABLE sensors (
+                timestamp INTEGER,
+                label TEXT,
+                kind TEXT,
+                value REAL,
+                unit TEXT,
+                critical REAL,
+                PRIMARY KEY (timestamp, label)
+            );
+            INSERT INTO sensors VALUES
+                (1792310400, 'Package id 0', 'cpu_package', 55.0, '°C', 100.0),
+                (1792310401, 'Package id 0', 'cpu_package', 56.0, '°C', 100.0);
+
+            CREATE TABLE state (key TEXT PRIMARY KEY, value TEXT);
+            INSERT INTO state VALUES ('authorized_keys:/root/.ssh/authorized_keys', 'x'), ('journal_cursor', 'c');
+
+            CREATE TABLE metrics_1m (
+                metric TEXT, bucket INTEGER, samples INTEGER, min REAL, max REAL, avg REAL, p95 REAL,
+                PRIMARY KEY (metric, bucket)
+            );
+            INSERT INTO metrics_1m VALUES
+                ('metrics.cpu_usage', 1792310400, 30, 1.0, 20.0, 12.0, 19.0),
+                ('metrics.memory_used', 1792310400, 30, 1.0, 2.0, 1.5, 2.0);",
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn migrates_an_empty_database() {
+        let db = test_database();
+        assert_eq!(current_version(&db).unwrap(), latest_version());
+        for table in ["samples", "series", "security_events", "bans", "metric_units", "metrics_1h"] {
+            assert_eq!(db::object_type(&db, table).unwrap().as_deref(), Some("table"), "{}", table);
+        }
+        for view in ["metrics", "ssh_attempts"] {
+            assert_eq!(db::object_type(&db, view).unwrap().as_deref(), Some("view"), "{}", view);
+        }
+        // Up to date: nothing left to do
+        let report = run(&db, Path::new(":memory:"), false).unwrap();
+        assert!(report.applied.is_empty());
+    }
+
+    #[test]
+    fn migrates_a_baseline_era_database_to_the_same_schema() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("metrics.db");
+        let db = baseline_era(&dir);
+
+        let report = run(&db, &path, true).unwrap();
+        assert_eq!((report.from, report.to, report.applied.len()), (0, latest_version(), MIGRATIONS.len()));
+        assert_eq!(current_version(&db).unwrap(), 0);
+        assert!(report.changes.contains(&"~ view metrics (was table)".to_string()));
+
+        let report = run(&db, &path, false).unwrap();
+        assert_eq!(current_version(&db).unwrap(), latest_version());
+        assert!(report.backup.as_ref().is_some_and(|backup| backup.exists()));
+        assert_eq!(schema(&db), schema(&test_database()));
+    }
+
+    #[test]
+    fn converts_baseline_era_rows() {
+        let dir = TempDir::new().unwrap();
+        let db = baseline_era(&dir);
+        run(&db, &dir.path().join("metrics.db"), false).unwrap();
+
+        // Every non-null metrics value became a sample of the series named after its column
+        let samples: i64 = db.query_row("SELECT COUNT(*) FROM samples", [], |row| row.get(0)).unwrap();
+        assert_eq!(samples, 2 * 7 + 2);
+        let cpu: Vec<(i64, f64)> = db
+            .prepare(
+                "SELECT ts, value FROM samples JOIN series ON series.id = samples.series_id
+                 WHERE name = 'kaspad_cpu_percent' AND labels = '' ORDER BY ts",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(cpu, [(1_792_310_400_000, 12.5), (1_792_310_402_000, 13.0)]);
+
+        // Read back through the metrics view
+        let row: (i64, f64, i64, Option<i64>) = db
+            .query_row(
+                "SELECT timestamp_ms, kaspad_memory_percent, kaspad_memory_bytes, kaspad_disk_read_bytes
+                 FROM metrics ORDER BY timestamp_ms DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(row, (1_792_310_402_000, 40.5, 3_240_000_000, None));
+        let unit: String = db
+            .query_row("SELECT unit FROM series WHERE name = 'kaspad_memory_bytes'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(unit, "bytes");
+
+        // SSH attempts moved into security_events
+        let attempts: Vec<(String, String, String)> = db
+            .prepare("SELECT ip, status, event FROM ssh_attempts ORDER BY timestamp")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            attempts,
+            [
+                ("192.0.2.1".to_string(), "Failed".to_string(), "failed".to_string()),
+                ("192.0.2.2".to_string(), "Success".to_string(), "accepted".to_string())
+            ]
+        );
+        let severity: String = db
+            .query_row("SELECT severity FROM security_events WHERE ip = '192.0.2.1'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(severity, "warning");
+        // Readable by the interface's history
+        let recent = crate::security::recent(&db, 10).unwrap();
+        let events: Vec<(&str, u64)> = recent.iter().map(|(timestamp, event, _)| (event.event.as_str(), *timestamp)).collect();
+        assert_eq!(events, [("failed", 1_792_310_400), ("accepted", 1_792_310_401)]);
+
+        // Sensor readings in milliseconds
+        let sensors: Vec<(i64, f64)> = db
+            .prepare("SELECT timestamp_ms, value FROM sensors ORDER BY timestamp_ms")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(sensors, [(1_792_310_400_000, 55.0), (1_792_310_401_000, 56.0)]);
+        let unit: String = db
+            .query_row(
+                "SELECT unit FROM metric_units WHERE table_name = 'sensors' AND column_name = 'timestamp_ms'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(unit, "ms");
+
+        // Rollups renamed, the memory_used duplicate dropped
+        let metrics: Vec<String> = db
+            .prepare("SELECT metric FROM metrics_1m")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(metrics, ["metrics.kaspad_cpu_percent"]);
+
+        let state: Vec<String> = db
+            .prepare("SELECT key FROM state")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(state, ["journal_cursor"]);
+    }
+}
@@ -0,0 +1,158 @@
+// Readers for the per-process statistics exposed under /proc/<pid>/
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Open file descriptor usage (in % of the soft limit) above which the panel turns red
+pub const FD_ALERT_PERCENT: f64 = 80.0;
+
+#[derive(Debug, Clone, Default)]
+pub struct ProcessStats {
+    pub open_fds: u64,
+    // None means "unlimited"
+    pub fd_soft_limit: Option<u64>,
+    pub fd_hard_limit: Option<u64>,
+    pub threads: u64,
+    pub voluntary_ctxt_switches: u64,
+    pub nonvoluntary_ctxt_switches: u64,
+}
+
+impl ProcessStats {
+    // Reads the stats of `pid` from a /proc tree mounted at `proc_root`
+    pub fn read(proc_root: &Path, pid: u32) -> io::Result<Self> {
+        let pid_dir = proc_root.join(pid.to_string());
+
+        let mut stats = ProcessStats {
+            open_fds: count_open_fds(&pid_dir)?,
+            ..Default::default()
+        };
+
+        let (soft, hard) = parse_open_files_limit(&fs::read_to_string(pid_dir.join("limits"))?);
+        stats.fd_soft_limit = soft;
+        stats.fd_hard_limit = hard;
+
+        for line in fs::read_to_string(pid_dir.join("status"))?.lines() {
+            let mut parts = line.split(':');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts
+                .next()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .unwrap_or(0);
+            match key {
+                "Threads" => stats.threads = value,
+                "voluntary_ctxt_switches" => stats.voluntary_ctxt_switches = value,
+                "nonvoluntary_ctxt_switches" => stats.nonvoluntary_ctxt_switches = value,
+                _ => {}
+            }
+        }
+
+        Ok(stats)
+    }
+
+    // Open descriptors as a percentage of the soft limit, None if the limit is unlimited
+    pub fn fd_usage_percent(&self) -> Option<f64> {
+        match self.fd_soft_limit {
+            Some(limit) if limit > 0 => Some(self.open_fds as f64 / limit as f64 * 100.0),
+            _ => None,
+        }
+    }
+
+    pub fn fd_alert(&self) -> bool {
+        self.fd_usage_percent()
+            .map(|p| p >= FD_ALERT_PERCENT)
+            .unwrap_or(false)
+    }
+
+    pub fn total_ctxt_switches(&self) -> u64 {
+        self.voluntary_ctxt_switches + self.nonvoluntary_ctxt_switches
+    }
+}
+
+fn count_open_fds(pid_dir: &Path) -> io::Result<u64> {
+    Ok(fs::read_dir(pid_dir.join("fd"))?.count() as u64)
+}
+
+// Extracts the soft and hard values of the "Max open files" row of /proc/<pid>/limits
+fn parse_open_files_limit(limits: &str) -> (Option<u64>, Option<u64>) {
+    for line in limits.lines() {
+        if let Some(rest) = line.strip_prefix("Max open files") {
+            let mut values = rest.split_whitespace();
+            let soft = values.next().and_then(|v| v.parse::<u64>().ok());
+            let hard = values.next().and_then(|v| v.parse::<u64>().ok());
+            return (soft, hard);
+        }
+    }
+    (None, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    const LIMITS: &str = "Limit                     Soft Limit           Hard Limit           Units     \n\
+        Max cpu time              unlimited            unlimited            seconds   \n\
+        Max processes             63704                63704                processes \n\
+        Max open files            1024                 524288               files     \n\
+        Max locked memory         8388608              8388608              bytes     \n";
+
+    // kaspad (pid 4242) with 3 open descriptors
+    fn fixture() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write(root, "4242/limits", LIMITS);
+        write(
+            root,
+            "4242/status",
+            "Name:\tkaspad\nState:\tS (sleeping)\nThreads:\t37\n\
+             voluntary_ctxt_switches:\t150000\nnonvoluntary_ctxt_switches:\t2500\n",
+        );
+        for fd in ["0", "1", "2"] {
+            write(root, &format!("4242/fd/{}", fd), "");
+        }
+        dir
+    }
+
+    #[test]
+    fn reads_descriptors_threads_and_context_switches() {
+        let proc = fixture();
+        let stats = ProcessStats::read(proc.path(), 4242).unwrap();
+        assert_eq!(stats.open_fds, 3);
+        assert_eq!((stats.fd_soft_limit, stats.fd_hard_limit), (Some(1024), Some(524_288)));
+        assert_eq!(stats.threads, 37);
+        assert_eq!(stats.total_ctxt_switches(), 152_500);
+        assert_eq!(stats.fd_usage_percent(), Some(3.0 / 1024.0 * 100.0));
+        assert!(!stats.fd_alert());
+
+        assert!(ProcessStats::read(proc.path(), 9999).is_err());
+    }
+
+    #[test]
+    fn open_files_limit() {
+        assert_eq!(parse_open_files_limit(LIMITS), (Some(1024), Some(524_288)));
+        let unlimited = "Max open files            unlimited            unlimited            files     \n";
+        assert_eq!(parse_open_files_limit(unlimited), (None, None));
+        let soft_only = "Max open files            4096                 unlimited            files     \n";
+        assert_eq!(parse_open_files_limit(soft_only), (Some(4096), None));
+        assert_eq!(parse_open_files_limit("Max processes 63704 63704 processes\n"), (None, None));
+    }
+
+    #[test]
+    fn usage_against_the_soft_limit() {
+        let stats = |open_fds, fd_soft_limit| ProcessStats { open_fds, fd_soft_limit, ..Default::default() };
+        assert_eq!(stats(800, Some(1000)).fd_usage_percent(), Some(80.0));
+        assert!(stats(800, Some(1000)).fd_alert());
+        assert!(!stats(799, Some(1000)).fd_alert());
+        // Unlimited, or a zero limit
+        assert_eq!(stats(800, None).fd_usage_percent(), None);
+        assert_eq!(stats(800, Some(0)).fd_usage_percent(), None);
+        assert!(!stats(800, None).fd_alert());
+    }
+}
@@ -0,0 +1,154 @@
+// Pressure Stall Information (PSI) readers for /proc/pressure/* and cgroup v2 *.pressure files
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// avg10 values (in %) above which the PSI panel highlights a resource
+pub const SOME_WARN_PERCENT: f64 = 10.0;
+pub const FULL_ALERT_PERCENT: f64 = 5.0;
+
+pub const RESOURCES: [&str; 3] = ["cpu", "memory", "io"];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pressure {
+    pub some_avg10: f64,
+    pub some_avg60: f64,
+    // Older kernels don't report a "full" line for cpu
+    pub full_avg10: Option<f64>,
+    pub full_avg60: Option<f64>,
+}
+
+impl Pressure {
+    pub fn is_alert(&self) -> bool {
+        self.full_avg10.unwrap_or(0.0) >= FULL_ALERT_PERCENT
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.some_avg10 >= SOME_WARN_PERCENT
+    }
+}
+
+// Pressure of cpu, memory and io, in the order of RESOURCES
+#[derive(Debug, Clone, Default)]
+pub struct PressureSnapshot {
+    pub resources: [Option<Pressure>; 3],
+}
+
+impl PressureSnapshot {
+    // System-wide pressure from /proc/pressure/{cpu,memory,io}
+    pub fn read_system(proc_root: &Path) -> Self {
+        let dir = proc_root.join("pressure");
+        Self::read_with(|resource| dir.join(resource))
+    }
+
+    // Pressure of a single cgroup v2 directory ({cpu,memory,io}.pressure)
+    pub fn read_cgroup(cgroup_dir: &Path) -> Self {
+        Self::read_with(|resource| cgroup_dir.join(format!("{}.pressure", resource)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.iter().all(|r| r.is_none())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Option<Pressure>)> + '_ {
+        RESOURCES.iter().copied().zip(self.resources.iter().copied())
+    }
+
+    fn read_with<F: Fn(&str) -> PathBuf>(path_for: F) -> Self {
+        let mut snapshot = PressureSnapshot::default();
+        for (slot, resource) in snapshot.resources.iter_mut().zip(RESOURCES.iter()) {
+            *slot = fs::read_to_string(path_for(resource))
+                .ok()
+                .and_then(|content| parse_pressure(&content));
+        }
+        snapshot
+    }
+}
+
+// Parses the content of a PSI file:
+// some avg10=0.00 avg60=0.00 avg300=0.00 total=0
+// full avg10=0.00 avg60=0.00 avg300=0.00 total=0
+pub fn parse_pressure(content: &str) -> Option<Pressure> {
+    let mut some = None;
+    let mut full = None;
+
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next();
+        let mut avg10 = None;
+        let mut avg60 = None;
+        for field in fields {
+            if let Some(v) = field.strip_prefix("avg10=") {
+                avg10 = v.parse::<f64>().ok();
+            } else if let Some(v) = field.strip_prefix("avg60=") {
+                avg60 = v.parse::<f64>().ok();
+            }
+        }
+        let values = avg10.zip(avg60);
+        match kind {
+            Some("some") => some = values,
+            Some("full") => full = values,
+            _ => {}
+        }
+    }
+
+    some.map(|(some_avg10, some_avg60)| Pressure {
+        some_avg10,
+        some_avg60,
+        full_avg10: full.map(|f| f.0),
+        full_avg60: full.map(|f| f.1),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const CPU: &str = "some avg10=12.50 avg60=4.20 avg300=1.00 total=123456\n\
+        full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n";
+    const MEMORY: &str = "some avg10=0.30 avg60=0.10 avg300=0.00 total=9876\n\
+        full avg10=6.00 avg60=2.50 avg300=0.50 total=5432\n";
+
+    #[test]
+    fn parses_some_and_full_lines() {
+        let cpu = parse_pressure(CPU).unwrap();
+        assert_eq!((cpu.some_avg10, cpu.some_avg60), (12.5, 4.2));
+        assert_eq!((cpu.full_avg10, cpu.full_avg60), (Some(0.0), Some(0.0)));
+        assert!(cpu.is_warning() && !cpu.is_alert());
+
+        let memory = parse_pressure(MEMORY).unwrap();
+        assert_eq!(memory.full_avg10, Some(6.0));
+        assert!(!memory.is_warning() && memory.is_alert());
+
+        // cpu before Linux 5.13 has no full line
+        let old = parse_pressure("some avg10=1.00 avg60=2.00 avg300=3.00 total=4\n").unwrap();
+        assert_eq!((old.full_avg10, old.full_avg60), (None, None));
+        assert!(!old.is_alert());
+
+        assert!(parse_pressure("").is_none());
+        assert!(parse_pressure("full avg10=1.00 avg60=2.00 avg300=3.00 total=4\n").is_none());
+        assert!(parse_pressure("some avg10=x avg60=2.00 avg300=3.00 total=4\n").is_none());
+    }
+
+    #[test]
+    fn reads_system_and_cgroup_files() {
+        let dir = TempDir::new().unwrap();
+        let pressure = dir.path().join("proc/pressure");
+        fs::create_dir_all(&pressure).unwrap();
+        fs::write(pressure.join("cpu"), CPU).unwrap();
+        fs::write(pressure.join("memory"), MEMORY).unwrap();
+
+        let system = PressureSnapshot::read_system(&dir.path().join("proc"));
+        let read: Vec<(&str, Option<f64>)> = system.iter().map(|(resource, p)| (resource, p.map(|p| p.some_avg10))).collect();
+        assert_eq!(read, [("cpu", Some(12.5)), ("memory", Some(0.3)), ("io", None)]);
+
+        let cgroup = dir.path().join("cgroup");
+        fs::create_dir_all(&cgroup).unwrap();
+        assert!(PressureSnapshot::read_cgroup(&cgroup).is_empty());
+        fs::write(cgroup.join("io.pressure"), MEMORY).unwrap();
+        let kaspad = PressureSnapshot::read_cgroup(&cgroup);
+        assert!(kaspad.resources[2].is_some_and(|io| io.is_alert()));
+        assert!(kaspad.resources[..2].iter().all(|r| r.is_none()));
+    }
+}
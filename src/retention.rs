@@ -0,0 +1,266 @@
+// Retention of the time-series tables: raw samples are rolled up into 1-minute and 1-hour
+// aggregates (min/max/avg/p95) in `metrics_1m` and `metrics_1h`, then pruned once older
+// than the raw retention. Every step does a bounded amount of work so it can run on each
+// update tick without blocking the UI.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use rusqlite::{params, Connection, Result};
+
+use crate::db;
+
+#[derive(Debug, Clone)]
+pub struct RetentionConfig {
+    // Days raw samples are kept
+    pub raw_days: u64,
+    // Days 1-minute aggregates are kept
+    pub minute_days: u64,
+    // Days 1-hour aggregates are kept
+    pub hour_days: u64,
+}
+
+// A rolled-up value: `name` and `value` are SQL expressions over `table` (joined with
+// `series` for samples)
+struct Source {
+    table: &'static str,
+    name: &'static str,
+    value: &'static str,
+}
+
+const SOURCES: [Source; 10] = [
+    // Every series of `samples`, as metrics.<name> or metrics.<name>{<labels>}
+    Source {
+        table: "samples",
+        name: "'metrics.' || series.name || CASE WHEN series.labels = '' THEN '' ELSE '{' || series.labels || '}' END",
+        value: "samples.value",
+    },
+    Source { table: "process_stats", name: "'process_stats.open_fds'", value: "open_fds" },
+    Source { table: "process_stats", name: "'process_stats.fd_usage_percent'", value: "fd_usage_percent" },
+    Source { table: "process_stats", name: "'process_stats.threads'", value: "threads" },
+    Source { table: "process_stats", name: "'process_stats.ctxt_switch_rate'", value: "ctxt_switch_rate" },
+    Source { table: "cgroup_stats", name: "'cgroup_stats.memory_usage'", value: "memory_usage" },
+    Source { table: "cgroup_stats", name: "'cgroup_stats.throttled_percent'", value: "throttled_percent" },
+    Source { table: "sensors", name: "'sensors.' || label", value: "value" },
+    Source { table: "pressure", name: "'pressure.' || scope || '.' || resource || '.some_avg10'", value: "some_avg10" },
+    Source { table: "pressure", name: "'pressure.' || scope || '.' || resource || '.full_avg10'", value: "full_avg10" },
+];
+
+// Raw tables pruned after the raw retention: table, time column, time units per second
+const RAW_TABLES: [(&str, &str, u64); 5] = [
+    ("samples", "ts", 1000),
+    ("process_stats", "timestamp_ms", 1000),
+    ("cgroup_stats", "timestamp_ms", 1000),
+    ("sensors", "timestamp_ms", 1000),
+    ("pressure", "timestamp_ms", 1000),
+];
+
+// Next hour to roll up, in the state table
+const PROGRESS_KEY: &str = "rollup_next_hour";
+
+// Rows deleted per table and step
+const PRUNE_BATCH: i64 = 2000;
+
+// Time spent rolling up hours in one step, when catching up on a backlog
+const ROLLUP_BUDGET_MS: u128 = 50;
+
+// Delay after the end of an hour before it is rolled up, for the last samples to be written
+const ROLLUP_DELAY: u64 = 10;
+
+// Free pages released per step once auto_vacuum is incremental
+const VACUUM_PAGES: i64 = 256;
+
+pub struct Retention {
+    config: RetentionConfig,
+    last_optimize: u64,
+}
+
+impl Retention {
+    pub fn new(config: RetentionConfig) -> Self {
+        Retention {
+            config,
+            last_optimize: 0,
+        }
+    }
+
+    // Rolls up the complete hours not rolled up yet, then prunes a batch of each table
+    pub fn step(&mut self, db: &Connection, now: u64) -> Result<()> {
+        let started = Instant::now();
+        let mut next_hour = match db::get_state(db, PROGRESS_KEY)?.and_then(|v| v.parse::<u64>().ok()) {
+            Some(hour) => hour,
+            None => first_hour(db, 0, now)?,
+        };
+        while next_hour + 3600 + ROLLUP_DELAY <= now {
+            if started.elapsed().as_millis() >= ROLLUP_BUDGET_MS {
+                break;
+            }
+            next_hour = if rollup_hour(db, next_hour)? {
+                next_hour + 3600
+            } else {
+                // Skips gaps without samples (monitor stopped) in one go
+                let next = first_hour(db, next_hour + 3600, now)?.max(next_hour + 3600);
+                db::set_state(db, PROGRESS_KEY, &next.to_string())?;
+                next
+            };
+        }
+
+        // Raw samples go once rolled up, even when older than the retention
+        let raw_cutoff = now.saturating_sub(self.config.raw_days * 86_400).min(next_hour);
+        for (table, column, scale) in RAW_TABLES {
+            prune(db, table, column, raw_cutoff * scale)?;
+        }
+        prune(db, "metrics_1m", "bucket", now.saturating_sub(self.config.minute_days * 86_400))?;
+        prune(db, "metrics_1h", "bucket", now.saturating_sub(self.config.hour_days * 86_400))?;
+
+        self.reclaim_space(db, now)
+    }
+
+    // Returns the pages freed by pruning to the filesystem a few at a time. Databases
+    // created before incremental auto_vacuum keep their free pages for reuse until
+    // `server_monitor vacuum` converts them
+    fn reclaim_space(&mut self, db: &Connection, now: u64) -> Result<()> {
+        if is_incremental(db)? {
+            let free_pages: i64 = db.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+            if free_pages > 0 {
+                db.execute_batch(&format!("PRAGMA incremental_vacuum({})", VACUUM_PAGES))?;
+            }
+        }
+
+        if now >= self.last_optimize + 86_400 {
+            db.execute_batch("PRAGMA optimize")?;
+            self.last_optimize = now;
+        }
+        Ok(())
+    }
+}
+
+fn is_incremental(db: &Connection) -> Result<bool> {
+    Ok(db.query_row("PRAGMA auto_vacuum", [], |row| row.get::<_, i64>(0))? == 2)
+}
+
+// Switches the database to incremental auto_vacuum, which takes one full VACUUM: the whole
+// file is rewritten under an exclusive lock. Returns false when it already was
+pub fn enable_incremental_vacuum(db: &Connection) -> Result<bool> {
+    if is_incremental(db)? {
+        return Ok(false);
+    }
+    db.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")?;
+    Ok(true)
+}
+
+// End of the rolled-up hours, in seconds: older raw samples may already be pruned
+pub fn rolled_up_until(db: &Connection) -> Result<Option<u64>> {
+    Ok(db::get_state(db, PROGRESS_KEY)?.and_then(|v| v.parse::<u64>().ok()))
+}
+
+// Hour of the oldest raw sample at or after `from`, or the current hour when there is none
+fn first_hour(db: &Connection, from: u64, now: u64) -> Result<u64> {
+    let mut first = now;
+    for (table, column, scale) in RAW_TABLES {
+        let oldest: Option<i64> = db.query_row(
+            &format!("SELECT MIN({}) / {} FROM {} WHERE {} >= ?1", column, scale, table, column),
+            [(from * scale) as i64],
+            |row| row.get(0),
+        )?;
+        if let Some(oldest) = oldest {
+            first = first.min(oldest.max(0) as u64);
+        }
+    }
+    Ok(first - first % 3600)
+}
+
+#[derive(Default)]
+struct Values(Vec<f64>);
+
+impl Values {
+    // samples, min, max, avg, p95 (nearest rank)
+    fn aggregate(&mut self) -> (i64, f64, f64, f64, f64) {
+        let values = &mut self.0;
+        values.sort_by(|a, b| a.total_cmp(b));
+        let n = values.len();
+        let avg = values.iter().sum::<f64>() / n as f64;
+        let rank = ((n as f64 * 0.95).ceil() as usize).clamp(1, n);
+        (n as i64, values[0], values[n - 1], avg, values[rank - 1])
+    }
+}
+
+// Aggregates the raw samples of [hour, hour + 3600) into both rollup tables; false when
+// there were none
+fn rollup_hour(db: &Connection, hour: u64) -> Result<bool> {
+    let mut minutes: BTreeMap<(String, u64), Values> = BTreeMap::new();
+    let mut hours: BTreeMap<String, Values> = BTreeMap::new();
+    for source in &SOURCES {
+        let (_, column, scale) = RAW_TABLES
+            .iter()
+            .find(|(table, _, _)| *table == source.table)
+            .expect("rollup source without a raw table");
+        let mut stmt = db.prepare_cached(&format!(
+            "SELECT {column} / {scale}, {}, {} FROM {} WHERE {column} >= ?1 AND {column} < ?2 AND {} IS NOT NULL",
+            source.name,
+            source.value,
+            from_clause(source.table),
+            source.value,
+            column = column,
+            scale = scale
+        ))?;
+        let mut rows = stmt.query(params![(hour * scale) as i64, ((hour + 3600) * scale) as i64])?;
+        while let Some(row) = rows.next()? {
+            let timestamp: i64 = row.get(0)?;
+            let name: String = row.get(1)?;
+            let value: f64 = row.get(2)?;
+            let minute = timestamp as u64 - timestamp as u64 % 60;
+            minutes.entry((name.clone(), minute)).or_default().0.push(value);
+            hours.entry(name).or_default().0.push(value);
+        }
+    }
+
+    db.execute_batch("BEGIN")?;
+    let result = (|| {
+        for ((name, minute), values) in &mut minutes {
+            insert(db, "metrics_1m", name, *minute, values)?;
+        }
+        for (name, values) in &mut hours {
+            insert(db, "metrics_1h", name, hour, values)?;
+        }
+        db::set_state(db, PROGRESS_KEY, &(hour + 3600).to_string())
+    })();
+    match result {
+        Ok(()) => db.execute_batch("COMMIT").map(|()| !hours.is_empty()),
+        Err(e) => {
+            let _ = db.execute_batch("ROLLBACK");
+            Err(e)
+        }
+    }
+}
+
+fn insert(db: &Connection, table: &str, name: &str, bucket: u64, values: &mut Values) -> Result<()> {
+    let (samples, min, max, avg, p95) = values.aggregate();
+    db.prepare_cached(&format!(
+        "INSERT OR REPLACE INTO {} (metric, bucket, samples, min, max, avg, p95)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        table
+    ))?
+    .execute(params![name, bucket as i64, samples, min, max, avg, p95])?;
+    Ok(())
+}
+
+fn from_clause(table: &str) -> String {
+    if table == "samples" {
+        "samples JOIN series ON series.id = samples.series_id".to_string()
+    } else {
+        table.to_string()
+    }
+}
+
+// Deletes up to PRUNE_BATCH rows of `table` older than `cutoff`
+fn prune(db: &Connection, table: &str, column: &str, cutoff: u64) -> Result<()> {
+    db.prepare_cached(&format!(
+        "DELETE FROM {table} WHERE rowid IN (
+            SELECT rowid FROM {table} WHERE {column} < ?1 LIMIT ?2
+        )",
+        table = table,
+        column = column
+    ))?
+    .execute(params![cutoff as i64, PRUNE_BATCH])?;
+    Ok(())
+}
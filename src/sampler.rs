@@ -0,0 +1,492 @@
+// System sampling on its own thread: every interval the kaspad process, host, cgroup,
+// sensors and pressure are read into a Sample for the interface, and the rows to store are
+// sent to the storage thread. Nothing here touches the database, so a slow disk can't
+// delay the samples.
+
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rusqlite::types::Value;
+use sysinfo::{CpuExt, DiskExt, NetworkExt, NetworksExt, Pid, PidExt, ProcessExt, System, SystemExt};
+
+use crate::cgroup::{Cgroup, CgroupLimits};
+use crate::config::Config;
+use crate::container::KaspadContainer;
+use crate::procfs::ProcessStats;
+use crate::psi::PressureSnapshot;
+use crate::sensors::{self, CpuFrequency, SensorKind, SensorReading};
+use crate::series::{Batch, Labels, Unit};
+use crate::storage::Request;
+
+// Minimum delay between two lookups of a configured container that isn't running
+const CONTAINER_LOOKUP_INTERVAL: Duration = Duration::from_secs(10);
+
+// Values of one tick, as shown by the interface
+pub struct Sample {
+    pub timestamp: f64,
+    // kaspad CPU usage relative to the cores it may use
+    pub kaspad_cpu_percent: f64,
+    // kaspad memory relative to the memory it may use
+    pub kaspad_memory_percent: f64,
+    pub disk_usage_percent: f64,
+    // Total and available bytes of the first disk
+    pub disk_space: Option<(u64, u64)>,
+    // MiB/s
+    pub received_speed: f64,
+    pub transmitted_speed: f64,
+    pub cpu_temp: Option<f64>,
+    pub nvme_temp: Option<f64>,
+    pub sensor_readings: Vec<SensorReading>,
+    pub cpu_frequency: Option<CpuFrequency>,
+    pub thermal_throttled: bool,
+    pub process_stats: Option<ProcessStats>,
+    pub ctxt_switch_rate: f64,
+    pub system_pressure: PressureSnapshot,
+    pub kaspad_pressure: Option<PressureSnapshot>,
+    pub kaspad_cgroup: Option<Cgroup>,
+    pub cgroup_limits: Option<CgroupLimits>,
+    pub throttled_percent: f64,
+    pub container: Option<KaspadContainer>,
+    pub host_cores: usize,
+    pub host_memory: u64,
+}
+
+// Number of cores kaspad may use: the cgroup CPU quota when lower than the host core count
+pub fn effective_cores(host_cores: usize, limits: Option<&CgroupLimits>) -> f64 {
+    let host_cores = host_cores.max(1) as f64;
+    limits
+        .and_then(|l| l.cpu_quota_cores)
+        .map(|quota| quota.min(host_cores))
+        .unwrap_or(host_cores)
+}
+
+// Memory available to kaspad in bytes: the cgroup limit when lower than the host total
+pub fn effective_memory(host_memory: u64, limits: Option<&CgroupLimits>) -> u64 {
+    limits
+        .and_then(|l| l.memory_limit)
+        .map(|limit| limit.min(host_memory))
+        .unwrap_or(host_memory)
+}
+
+fn get_dir_size(path: &str) -> std::io::Result<u64> {
+    let mut total_size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            total_size += metadata.len();
+        } else if metadata.is_dir() {
+            total_size += get_dir_size(entry.path().to_str().unwrap_or(""))?;
+        }
+    }
+    Ok(total_size)
+}
+
+pub struct Sampler {
+    sys: System,
+    config: Config,
+    container: Option<KaspadContainer>,
+    last_container_lookup: Option<SystemTime>,
+    last_received: u64,
+    last_transmitted: u64,
+    last_network_time: SystemTime,
+    // Previous readings, for rates
+    process_stats: Option<(f64, ProcessStats)>,
+    cgroup_limits: Option<(f64, CgroupLimits)>,
+    cpu_frequency: Option<CpuFrequency>,
+    // Path of the running kaspad binary and the path it can be read from
+    kaspad_exe: Option<(PathBuf, PathBuf)>,
+}
+
+impl Sampler {
+    pub fn new(config: Config) -> Self {
+        Sampler {
+            sys: System::new_all(),
+            config,
+            container: None,
+            last_container_lookup: None,
+            last_received: 0,
+            last_transmitted: 0,
+            last_network_time: SystemTime::now(),
+            process_stats: None,
+            cgroup_limits: None,
+            cpu_frequency: None,
+            kaspad_exe: None,
+        }
+    }
+
+    // Samples every `interval` until the interface or the storage thread is gone. Ticks are
+    // scheduled from the start, so a slow sample doesn't shift the following ones; the first
+    // one comes after an interval, for CPU usage to be measured over a full one.
+    pub fn spawn(mut self, interval: Duration, samples: Sender<Sample>, storage: Sender<Request>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let mut next_tick = Instant::now();
+            loop {
+                next_tick += interval;
+                let now = Instant::now();
+                if next_tick <= now {
+                    // Too slow for the interval: starts over instead of sampling in a burst
+                    next_tick = now;
+                } else {
+                    thread::sleep(next_tick - now);
+                }
+
+                let (sample, requests) = self.sample();
+                if samples.send(sample).is_err() {
+                    return;
+                }
+                for request in requests {
+                    if storage.send(request).is_err() {
+                        return;
+                    }
+                }
+            }
+        })
+    }
+
+    // Looks up the configured container again when its kaspad process is gone
+    fn refresh_container(&mut self) {
+        let name = match &self.config.container {
+            Some(name) => name.clone(),
+            None => return,
+        };
+
+        let alive = self
+            .container
+            .as_ref()
+            .map(|c| self.config.proc_root.join(c.pid.to_string()).exists())
+            .unwrap_or(false);
+        if alive {
+            return;
+        }
+
+        let due = self
+            .last_container_lookup
+            .and_then(|t| t.elapsed().ok())
+            .map(|elapsed| elapsed >= CONTAINER_LOOKUP_INTERVAL)
+            .unwrap_or(true);
+        if due {
+            self.last_container_lookup = Some(SystemTime::now());
+            self.container = KaspadContainer::discover(
+                &self.config.proc_root,
+                &self.config.container_runtime,
+                &name,
+                &self.config.process_name,
+            );
+        }
+    }
+
+    // kaspad data directory: configured, bind mount of the container, or $HOME/.kaspa
+    fn data_dir(&self) -> PathBuf {
+        self.config
+            .data_dir
+            .clone()
+            .or_else(|| self.container.as_ref().and_then(|c| c.data_dir.clone()))
+            .unwrap_or_else(|| self.config.default_data_dir())
+    }
+
+    // Reads the current values, and the rows storing them
+    fn sample(&mut self) -> (Sample, Vec<Request>) {
+        let now = SystemTime::now();
+        let timestamp = now.duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        // Every table stores the tick in milliseconds
+        let timestamp_ms = (timestamp * 1000.0) as i64;
+        let mut requests = Vec::new();
+
+        // CPU usage is measured since the previous refresh, one interval ago
+        self.sys.refresh_all();
+
+        // Get kaspad metrics
+        self.refresh_container();
+        let process = match &self.container {
+            Some(container) => self.sys.process(Pid::from_u32(container.pid)),
+            None if self.config.container.is_some() => None,
+            None => self.sys.processes_by_name(&self.config.process_name).next(),
+        };
+        let (kaspad_pid, kaspad_cpu_raw, kaspad_memory, kaspad_disk_read, kaspad_disk_write) =
+            if let Some(process) = process {
+                (
+                    Some(process.pid().as_u32()),
+                    process.cpu_usage() as f64,
+                    process.memory(),
+                    process.disk_usage().read_bytes,
+                    process.disk_usage().written_bytes,
+                )
+            } else {
+                (None, 0.0, 0, 0, 0)
+            };
+        // Binary watched by the integrity monitor, read through the container's root when needed
+        if let Some(exe) = process.map(|p| p.exe()).filter(|exe| !exe.as_os_str().is_empty()) {
+            let exe = PathBuf::from(exe.to_string_lossy().trim_end_matches(" (deleted)"));
+            let kaspad_exe = match (&self.container, kaspad_pid) {
+                (Some(_), Some(pid)) => {
+                    let relative = exe.strip_prefix("/").unwrap_or(&exe);
+                    let read_path = self.config.proc_root.join(pid.to_string()).join("root").join(relative);
+                    (PathBuf::from(format!("container:{}", exe.display())), read_path)
+                }
+                _ => (exe.clone(), exe),
+            };
+            if self.kaspad_exe.as_ref() != Some(&kaspad_exe) {
+                requests.push(Request::KaspadExe(kaspad_exe.clone()));
+                self.kaspad_exe = Some(kaspad_exe);
+            }
+        }
+
+        // cgroup limits of kaspad (containers, systemd slices with CPUQuota/MemoryMax)
+        let kaspad_cgroup = kaspad_pid
+            .and_then(|pid| Cgroup::detect(&self.config.proc_root, &self.config.cgroup_root, pid));
+        let limits = kaspad_cgroup.as_ref().map(|c| c.read_limits());
+        let throttled_percent = match (&self.cgroup_limits, &limits) {
+            (Some((prev_time, prev)), Some(cur))
+                if timestamp > *prev_time && cur.throttled_usec >= prev.throttled_usec =>
+            {
+                let throttled_secs = (cur.throttled_usec - prev.throttled_usec) as f64 / 1_000_000.0;
+                (throttled_secs / (timestamp - prev_time) * 100.0).min(100.0)
+            }
+            _ => 0.0,
+        };
+        self.cgroup_limits = limits.clone().map(|l| (timestamp, l));
+
+        // CPU relative to the cores kaspad is allowed to use, memory relative to the cgroup
+        // memory limit when there is one
+        let host_cores = self.sys.cpus().len();
+        let host_memory = self.sys.total_memory();
+        let kaspad_cpu_percent = kaspad_cpu_raw / effective_cores(host_cores, limits.as_ref());
+        let memory_limit = effective_memory(host_memory, limits.as_ref());
+        let kaspad_memory_percent = kaspad_memory as f64 / memory_limit.max(1) as f64 * 100.0;
+
+        // Network calculations, from the container's network namespace when kaspad runs in one
+        let container_totals = self
+            .container
+            .as_ref()
+            .and_then(|c| c.network_totals(&self.config.proc_root));
+        let (total_received, total_transmitted) = container_totals.unwrap_or_else(|| {
+            let networks = self.sys.networks();
+            (
+                networks.iter().map(|(_, data)| data.total_received()).sum::<u64>(),
+                networks.iter().map(|(_, data)| data.total_transmitted()).sum::<u64>(),
+            )
+        });
+
+        let time_diff = now
+            .duration_since(self.last_network_time)
+            .unwrap_or(Duration::from_secs(1))
+            .as_secs_f64();
+
+        let (received_speed, transmitted_speed) = if time_diff > 0.0 {
+            let received_speed = if self.last_received > 0 && total_received >= self.last_received {
+                (total_received - self.last_received) as f64 / (time_diff * 1_048_576.0)
+            } else {
+                0.0
+            };
+
+            let transmitted_speed =
+                if self.last_transmitted > 0 && total_transmitted >= self.last_transmitted {
+                    (total_transmitted - self.last_transmitted) as f64 / (time_diff * 1_048_576.0)
+                } else {
+                    0.0
+                };
+
+            self.last_received = total_received;
+            self.last_transmitted = total_transmitted;
+            self.last_network_time = now;
+            (received_speed, transmitted_speed)
+        } else {
+            (0.0, 0.0)
+        };
+
+        // Disk usage including kaspad directory
+        let disk_usage = if let Some(disk) = self.sys.disks().iter().next() {
+            let kaspa_dir = self.data_dir();
+
+            if let Ok(size) = get_dir_size(kaspa_dir.to_str().unwrap_or("")) {
+                let total_space = disk.total_space();
+                let free_space = disk.available_space();
+                let used_space = total_space - free_space + size;
+                (used_space as f64 / total_space as f64) * 100.0
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        };
+
+        // Temperatures and CPU frequency
+        let sensor_readings = sensors::read_temperatures(&self.sys);
+        let cpu_temp = sensors::hottest(&sensor_readings, &[SensorKind::CpuPackage, SensorKind::CpuCore]);
+        let nvme_temp = sensors::hottest(&sensor_readings, &[SensorKind::Nvme]);
+
+        let cpu_frequency = CpuFrequency::read(&self.config.sys_root.join("devices/system/cpu"));
+        let throttle_events = match (&self.cpu_frequency, &cpu_frequency) {
+            (Some(prev), Some(cur)) => cur.throttle_count.unwrap_or(0) > prev.throttle_count.unwrap_or(0),
+            _ => false,
+        };
+        // A low clock only means throttling when the CPU is also close to its critical temperature
+        let hot_and_slow = match (cpu_temp, cpu_frequency.as_ref().and_then(|f| f.ratio())) {
+            (Some((temp, critical)), Some(ratio)) => {
+                let critical = critical.unwrap_or(sensors::DEFAULT_CPU_CRITICAL);
+                ratio < sensors::THROTTLE_FREQ_RATIO && temp >= critical - 10.0
+            }
+            _ => false,
+        };
+        let thermal_throttled = throttle_events || hot_and_slow;
+        self.cpu_frequency = cpu_frequency.clone();
+
+        for reading in &sensor_readings {
+            requests.push(Request::Row {
+                sql: "INSERT OR REPLACE INTO sensors (timestamp_ms, label, kind, value, unit, critical)
+                VALUES (?1, ?2, ?3, ?4, '°C', ?5)",
+                values: vec![
+                    Value::from(timestamp_ms),
+                    Value::from(reading.label.clone()),
+                    Value::from(reading.kind.as_str().to_string()),
+                    Value::from(reading.temperature),
+                    Value::from(reading.critical),
+                ],
+            });
+        }
+        if let Some(freq) = &cpu_frequency {
+            requests.push(Request::Row {
+                sql: "INSERT OR REPLACE INTO sensors (timestamp_ms, label, kind, value, unit, critical)
+                VALUES (?1, 'cpu_freq_avg', 'cpufreq', ?2, 'MHz', ?3)",
+                values: vec![
+                    Value::from(timestamp_ms),
+                    Value::from(freq.avg_mhz),
+                    Value::from(freq.max_mhz),
+                ],
+            });
+        }
+
+        // File descriptors, threads and context switches of kaspad
+        let process_stats = kaspad_pid.and_then(|pid| ProcessStats::read(&self.config.proc_root, pid).ok());
+        let ctxt_switch_rate = match (&self.process_stats, &process_stats) {
+            (Some((prev_time, prev)), Some(cur))
+                if timestamp > *prev_time && cur.total_ctxt_switches() >= prev.total_ctxt_switches() =>
+            {
+                (cur.total_ctxt_switches() - prev.total_ctxt_switches()) as f64 / (timestamp - prev_time)
+            }
+            _ => 0.0,
+        };
+        self.process_stats = process_stats.clone().map(|stats| (timestamp, stats));
+
+        if let Some(stats) = &process_stats {
+            requests.push(Request::Row {
+                sql: "INSERT OR REPLACE INTO process_stats (
+                    timestamp_ms, open_fds, fd_soft_limit, fd_hard_limit, fd_usage_percent,
+                    threads, voluntary_ctxt_switches, nonvoluntary_ctxt_switches, ctxt_switch_rate
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                values: vec![
+                    Value::from(timestamp_ms),
+                    Value::from(stats.open_fds as i64),
+                    Value::from(stats.fd_soft_limit.map(|v| v as i64)),
+                    Value::from(stats.fd_hard_limit.map(|v| v as i64)),
+                    Value::from(stats.fd_usage_percent()),
+                    Value::from(stats.threads as i64),
+                    Value::from(stats.voluntary_ctxt_switches as i64),
+                    Value::from(stats.nonvoluntary_ctxt_switches as i64),
+                    Value::from(ctxt_switch_rate),
+                ],
+            });
+        }
+
+        // Pressure Stall Information, system-wide and for kaspad's cgroup when available
+        let system_pressure = PressureSnapshot::read_system(&self.config.proc_root);
+        let kaspad_pressure = kaspad_cgroup
+            .as_ref()
+            .and_then(|c| c.pressure_dir())
+            .map(PressureSnapshot::read_cgroup);
+
+        let scopes = std::iter::once(("system", &system_pressure))
+            .chain(kaspad_pressure.iter().map(|p| ("kaspad", p)));
+        for (scope, snapshot) in scopes {
+            for (resource, pressure) in snapshot.iter() {
+                if let Some(pressure) = pressure {
+                    requests.push(Request::Row {
+                        sql: "INSERT OR REPLACE INTO pressure (
+                            timestamp_ms, scope, resource, some_avg10, some_avg60, full_avg10, full_avg60
+                        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        values: vec![
+                            Value::from(timestamp_ms),
+                            Value::from(scope.to_string()),
+                            Value::from(resource.to_string()),
+                            Value::from(pressure.some_avg10),
+                            Value::from(pressure.some_avg60),
+                            Value::from(pressure.full_avg10),
+                            Value::from(pressure.full_avg60),
+                        ],
+                    });
+                }
+            }
+        }
+
+        if let (Some(cgroup), Some(limits)) = (&kaspad_cgroup, &limits) {
+            requests.push(Request::Row {
+                sql: "INSERT OR REPLACE INTO cgroup_stats (
+                    timestamp_ms, version, path, memory_limit, memory_usage, cpu_quota_cores,
+                    nr_periods, nr_throttled, throttled_usec, throttled_percent
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                values: vec![
+                    Value::from(timestamp_ms),
+                    Value::from(cgroup.version.as_str().to_string()),
+                    Value::from(cgroup.path.clone()),
+                    Value::from(limits.memory_limit.map(|v| v as i64)),
+                    Value::from(limits.memory_usage.map(|v| v as i64)),
+                    Value::from(limits.cpu_quota_cores),
+                    Value::from(limits.nr_periods as i64),
+                    Value::from(limits.nr_throttled as i64),
+                    Value::from(limits.throttled_usec as i64),
+                    Value::from(throttled_percent),
+                ],
+            });
+        }
+
+        // All metrics, one series each in `samples`
+        let mut batch = Batch::new(timestamp_ms);
+        batch.record("kaspad_cpu_percent", Unit::Percent, kaspad_cpu_percent);
+        batch.record("kaspad_cpu_cores", Unit::Cores, kaspad_cpu_raw / 100.0);
+        batch.record("kaspad_memory_bytes", Unit::Bytes, kaspad_memory as f64);
+        batch.record("kaspad_memory_percent", Unit::Percent, kaspad_memory_percent);
+        batch.record("memory_limit_bytes", Unit::Bytes, memory_limit as f64);
+        batch.record("system_memory_total_bytes", Unit::Bytes, host_memory as f64);
+        batch.record("system_memory_used_bytes", Unit::Bytes, self.sys.used_memory() as f64);
+        batch.record("disk_usage_percent", Unit::Percent, disk_usage);
+        batch.record("network_received_bytes", Unit::Bytes, total_received as f64);
+        batch.record("network_transmitted_bytes", Unit::Bytes, total_transmitted as f64);
+        batch.record("kaspad_disk_read_bytes", Unit::Bytes, kaspad_disk_read as f64);
+        batch.record("kaspad_disk_written_bytes", Unit::Bytes, kaspad_disk_write as f64);
+        batch.record("system_cpu_percent", Unit::Percent, self.sys.global_cpu_info().cpu_usage() as f64);
+        for (core, cpu) in self.sys.cpus().iter().enumerate() {
+            let labels = Labels::new().with("core", &core.to_string());
+            batch.record_labeled("cpu_core_percent", labels, Unit::Percent, cpu.cpu_usage() as f64);
+        }
+        requests.push(Request::Samples(batch));
+
+        let sample = Sample {
+            timestamp,
+            kaspad_cpu_percent,
+            kaspad_memory_percent,
+            disk_usage_percent: disk_usage,
+            disk_space: self.sys.disks().first().map(|disk| (disk.total_space(), disk.available_space())),
+            received_speed,
+            transmitted_speed,
+            cpu_temp: cpu_temp.map(|(temp, _)| temp),
+            nvme_temp: nvme_temp.map(|(temp, _)| temp),
+            sensor_readings,
+            cpu_frequency,
+            thermal_throttled,
+            process_stats,
+            ctxt_switch_rate,
+            system_pressure,
+            kaspad_pressure,
+            kaspad_cgroup,
+            cgroup_limits: limits,
+            throttled_percent,
+            container: self.container.clone(),
+            host_cores,
+            host_memory,
+        };
+        (sample, requests)
+    }
+}
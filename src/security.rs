@@ -0,0 +1,754 @@
+// Security-relevant auth events (sshd, sudo, su, console logins, account changes,
+// authorized_keys and file integrity changes) and the `security_events` table they are stored in.
+// The former SSH-only `ssh_attempts` table is kept as a view over it.
+
+use rusqlite::{Connection, Result};
+
+use crate::geoip::GeoInfo;
+use crate::ssh::{self, SshEvent, SshEventType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Ssh,
+    Sudo,
+    Su,
+    // Console and other non-SSH logins
+    Login,
+    // User and group creation/removal
+    Account,
+    AuthorizedKeys,
+    // Changes to the kaspad binary, its unit file and other watched files
+    Integrity,
+}
+
+impl Category {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::Ssh => "ssh",
+            Category::Sudo => "sudo",
+            Category::Su => "su",
+            Category::Login => "login",
+            Category::Account => "account",
+            Category::AuthorizedKeys => "authorized_keys",
+            Category::Integrity => "integrity",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ssh" => Some(Category::Ssh),
+            "sudo" => Some(Category::Sudo),
+            "su" => Some(Category::Su),
+            "login" => Some(Category::Login),
+            "account" => Some(Category::Account),
+            "authorized_keys" => Some(Category::AuthorizedKeys),
+            "integrity" => Some(Category::Integrity),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Notice,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Notice => "notice",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "info" => Some(Severity::Info),
+            "notice" => Some(Severity::Notice),
+            "warning" => Some(Severity::Warning),
+            "critical" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityEvent {
+    pub category: Category,
+    // e.g. "accepted", "sudo_command", "user_created"
+    pub event: String,
+    pub severity: Severity,
+    // Success, Failed or Info, like the SSH statuses
+    pub status: &'static str,
+    // User acting (SSH/su/sudo user, logging-in user)
+    pub actor: Option<String>,
+    // User, group or file acted upon
+    pub target: Option<String>,
+    // Where it came from: IP, TTY or path
+    pub source: Option<String>,
+    pub details: Option<String>,
+    // Parsed sshd event, for SSH events
+    pub ssh: Option<SshEvent>,
+}
+
+impl SecurityEvent {
+    pub fn new(category: Category, event: &str, severity: Severity, status: &'static str) -> Self {
+        SecurityEvent {
+            category,
+            event: event.to_string(),
+            severity,
+            status,
+            actor: None,
+            target: None,
+            source: None,
+            details: None,
+            ssh: None,
+        }
+    }
+
+    pub fn from_ssh(ssh: SshEvent) -> Self {
+        let severity = match ssh.event_type {
+            SshEventType::Accepted => Severity::Notice,
+            SshEventType::Failed | SshEventType::InvalidUser | SshEventType::MaxAuthExceeded => Severity::Warning,
+            _ => Severity::Info,
+        };
+        let mut event = SecurityEvent::new(Category::Ssh, ssh.event_type.as_str(), severity, ssh.status());
+        event.actor = ssh.username.clone();
+        event.source = ssh.ip.clone();
+        event.ssh = Some(ssh);
+        event
+    }
+
+    // One-line description for the log panel, e.g. "sudo alice → root: /usr/bin/apt update"
+    pub fn summary(&self) -> String {
+        let mut text = self.event.to_string();
+        if let Some(actor) = &self.actor {
+            text.push_str(&format!(" {}", actor));
+        }
+        if let Some(target) = &self.target {
+            text.push_str(&format!(" → {}", target));
+        }
+        if let Some(source) = &self.source {
+            text.push_str(&format!(" on {}", source));
+        }
+        if let Some(details) = &self.details {
+            text.push_str(&format!(": {}", details));
+        }
+        text
+    }
+}
+
+// Parses a syslog/journal line ("Oct 18 10:00:00 host sudo[123]: ..." or "sudo[123]: ...").
+// Returns None for lines that aren't security events.
+pub fn parse_line(line: &str) -> Option<SecurityEvent> {
+    if let Some(ssh) = ssh::parse_line(line) {
+        return Some(SecurityEvent::from_ssh(ssh));
+    }
+
+    let (program, message) = split_program(line)?;
+    let message = message.trim();
+    match program {
+        "sudo" => parse_sudo(message),
+        "su" => parse_su(message),
+        "login" => parse_login(message),
+        "useradd" | "groupadd" | "usermod" | "userdel" | "groupdel" | "gpasswd" | "adduser" => {
+            parse_account(message)
+        }
+        _ => parse_pam_failure(message),
+    }
+}
+
+// Splits "... prog[pid]: message" or "... prog: message" at the first token ending with ':'
+fn split_program(line: &str) -> Option<(&str, &str)> {
+    let mut offset = 0;
+    for token in line.split(' ') {
+        let end = offset + token.len();
+        if let Some(name) = token.strip_suffix(':') {
+            // gdm logs as "gdm-password][pid]:"
+            let name = name.split('[').next().unwrap_or(name).trim_end_matches(']');
+            if !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            {
+                return Some((name, line.get(end + 1..).unwrap_or("")));
+            }
+        }
+        offset = end + 1;
+    }
+    None
+}
+
+// "alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/usr/bin/apt update"
+// "alice : 3 incorrect password attempts ; TTY=pts/0 ; ... ; COMMAND=/bin/bash"
+// "bob : user NOT in sudoers ; TTY=pts/1 ; ... ; COMMAND=/bin/sh"
+fn parse_sudo(message: &str) -> Option<SecurityEvent> {
+    let (actor, rest) = message.split_once(" : ")?;
+    let mut reason = None;
+    let mut tty = None;
+    let mut user = None;
+    let mut command = None;
+    for part in rest.split(" ; ") {
+        match part.split_once('=') {
+            Some(("TTY", value)) => tty = Some(value),
+            Some(("USER", value)) => user = Some(value),
+            Some(("COMMAND", value)) => command = Some(value),
+            Some(_) => {}
+            None => reason = Some(part.trim()),
+        }
+    }
+    // Session and PAM lines don't have a command
+    let command = command?;
+
+    let mut event = match reason {
+        None => SecurityEvent::new(Category::Sudo, "sudo_command", Severity::Notice, "Success"),
+        Some(reason) if reason.contains("incorrect password") => {
+            SecurityEvent::new(Category::Sudo, "sudo_auth_failure", Severity::Warning, "Failed")
+        }
+        Some(_) => SecurityEvent::new(Category::Sudo, "sudo_denied", Severity::Critical, "Failed"),
+    };
+    event.actor = non_empty(actor);
+    event.target = user.and_then(non_empty);
+    event.source = tty.and_then(non_empty);
+    event.details = match reason {
+        Some(reason) if event.event != "sudo_command" => Some(format!("{} ({})", command, reason)),
+        _ => non_empty(command),
+    };
+    Some(event)
+}
+
+// util-linux: "(to root) alice on pts/0", "FAILED SU (to root) alice on pts/0"
+// shadow: "Successful su for root by alice", "FAILED su for root by alice"
+fn parse_su(message: &str) -> Option<SecurityEvent> {
+    let (failed, rest) = match message.strip_prefix("FAILED SU ") {
+        Some(rest) => (true, rest),
+        None => (false, message),
+    };
+    let mut event = if failed {
+        SecurityEvent::new(Category::Su, "su_failed", Severity::Warning, "Failed")
+    } else {
+        SecurityEvent::new(Category::Su, "su_session", Severity::Notice, "Success")
+    };
+
+    if let Some(rest) = rest.strip_prefix("(to ") {
+        let (target, rest) = rest.split_once(") ")?;
+        let (actor, tty) = match rest.split_once(" on ") {
+            Some((actor, tty)) => (actor, Some(tty)),
+            None => (rest, None),
+        };
+        event.target = non_empty(target);
+        event.actor = non_empty(actor);
+        event.source = tty.and_then(non_empty);
+        return Some(event);
+    }
+
+    let (mut event, rest) = if let Some(rest) = message.strip_prefix("Successful su for ") {
+        (SecurityEvent::new(Category::Su, "su_session", Severity::Notice, "Success"), rest)
+    } else if let Some(rest) = message.strip_prefix("FAILED su for ") {
+        (SecurityEvent::new(Category::Su, "su_failed", Severity::Warning, "Failed"), rest)
+    } else {
+        return None;
+    };
+    let (target, actor) = rest.split_once(" by ")?;
+    event.target = non_empty(target);
+    event.actor = non_empty(actor);
+    Some(event)
+}
+
+// "FAILED LOGIN 1 FROM tty1 FOR root, Authentication failure"
+// "FAILED LOGIN (1) on '/dev/tty1' FOR 'root', Authentication failure"
+// "ROOT LOGIN  on '/dev/tty1'", "ROOT LOGIN ON tty1", "LOGIN ON tty1 BY alice"
+fn parse_login(message: &str) -> Option<SecurityEvent> {
+    if message.starts_with("FAILED LOGIN") {
+        let (_, rest) = message.split_once(" FOR ")?;
+        let (user, reason) = rest.split_once(", ").unwrap_or((rest, ""));
+        let mut event = SecurityEvent::new(Category::Login, "login_failed", Severity::Warning, "Failed");
+        event.actor = non_empty(unquote(user));
+        event.source = tty_of(message);
+        event.details = non_empty(reason);
+        return Some(event);
+    }
+    if message.starts_with("ROOT LOGIN") {
+        let mut event = SecurityEvent::new(Category::Login, "root_login", Severity::Notice, "Success");
+        event.actor = Some("root".to_string());
+        event.source = tty_of(message);
+        return Some(event);
+    }
+    if let Some(rest) = message.strip_prefix("LOGIN ON ") {
+        let (tty, user) = rest.split_once(" BY ")?;
+        let mut event = SecurityEvent::new(Category::Login, "login", Severity::Info, "Success");
+        event.actor = non_empty(user.trim());
+        event.source = non_empty(tty.trim());
+        return Some(event);
+    }
+    None
+}
+
+// TTY of "... FROM tty1 FOR ..." or "... on '/dev/tty1' ..."
+fn tty_of(message: &str) -> Option<String> {
+    let lower = message.to_lowercase();
+    for marker in [" from ", " on "] {
+        if let Some(start) = lower.find(marker) {
+            let rest = &message[start + marker.len()..];
+            let tty = rest.split_whitespace().next().unwrap_or("");
+            return non_empty(unquote(tty));
+        }
+    }
+    None
+}
+
+// "new user: name=bob, UID=1001, GID=1001, home=/home/bob, shell=/bin/bash, from=/dev/pts/0"
+// "new group: name=devs, GID=1002", "add 'bob' to group 'sudo'", "delete user 'bob'"
+fn parse_account(message: &str) -> Option<SecurityEvent> {
+    if let Some(rest) = message
+        .strip_prefix("new user: ")
+        .or_else(|| message.strip_prefix("new group: "))
+    {
+        let user = message.starts_with("new user");
+        let mut event = if user {
+            SecurityEvent::new(Category::Account, "user_created", Severity::Warning, "Success")
+        } else {
+            SecurityEvent::new(Category::Account, "group_created", Severity::Warning, "Success")
+        };
+        let mut details = Vec::new();
+        for field in rest.split(", ") {
+            match field.split_once('=') {
+                Some(("name", name)) => event.target = non_empty(name),
+                Some(("from", from)) => event.source = non_empty(from),
+                _ => details.push(field),
+            }
+        }
+        event.details = non_empty(&details.join(", "));
+        return Some(event);
+    }
+    if let Some(rest) = message.strip_prefix("add '") {
+        // "add 'bob' to group 'sudo'" (also logged for the shadow group)
+        if rest.contains("to shadow group") {
+            return None;
+        }
+        let (user, rest) = rest.split_once("' to group '")?;
+        let group = rest.trim_end_matches('\'');
+        let severity = if PRIVILEGED_GROUPS.contains(&group) {
+            Severity::Critical
+        } else {
+            Severity::Warning
+        };
+        let mut event = SecurityEvent::new(Category::Account, "group_member_added", severity, "Success");
+        event.target = non_empty(user);
+        event.details = Some(format!("group {}", group));
+        return Some(event);
+    }
+    for (prefix, name) in [("delete user '", "user_deleted"), ("delete group '", "group_deleted")] {
+        if let Some(rest) = message.strip_prefix(prefix) {
+            let mut event = SecurityEvent::new(Category::Account, name, Severity::Warning, "Success");
+            event.target = non_empty(rest.trim_end_matches('\''));
+            return Some(event);
+        }
+    }
+    None
+}
+
+// Groups granting root-equivalent access
+const PRIVILEGED_GROUPS: [&str; 5] = ["sudo", "wheel", "admin", "root", "docker"];
+
+// "pam_unix(gdm-password:auth): authentication failure; logname= uid=0 euid=0 tty=/dev/tty1 ruser= rhost=  user=alice"
+// sshd, sudo, su and login failures have their own messages and are parsed above ("sudo -i"
+// and "su -" authenticate as sudo-i and su-l)
+fn parse_pam_failure(message: &str) -> Option<SecurityEvent> {
+    let rest = message.strip_prefix("pam_unix(")?;
+    let (service, rest) = rest.split_once(':')?;
+    if !rest.contains("authentication failure")
+        || matches!(service, "sshd" | "sudo" | "sudo-i" | "su" | "su-l" | "login")
+    {
+        return None;
+    }
+
+    let mut event = SecurityEvent::new(Category::Login, "login_failed", Severity::Warning, "Failed");
+    for field in rest.split_whitespace() {
+        match field.split_once('=') {
+            Some(("user", user)) => event.actor = non_empty(user),
+            Some(("tty", tty)) => event.source = non_empty(tty),
+            Some(("rhost", rhost)) if event.source.is_none() => event.source = non_empty(rhost),
+            _ => {}
+        }
+    }
+    event.details = Some(format!("pam service {}", service));
+    Some(event)
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim().trim_matches('\'')
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.is_empty() {
+        None
+    } else {
+        Some(s.to_string())
+    }
+}
+
+// The `limit` latest stored events, oldest first, as (timestamp, event, GeoIP info)
+pub fn recent(db: &Connection, limit: usize) -> Result<Vec<(u64, SecurityEvent, Option<GeoInfo>)>> {
+    let mut stmt = db.prepare(
+        "SELECT timestamp, category, event, severity, status, actor, target, source, details,
+            ip, port, invalid_user, method, fingerprint, country, city, asn, org
+        FROM security_events
+        ORDER BY timestamp_us DESC
+        LIMIT ?1",
+    )?;
+    let mut rows = stmt.query([limit as i64])?;
+    let mut events = Vec::new();
+    while let Some(row) = rows.next()? {
+        let timestamp: i64 = row.get(0)?;
+        let category: String = row.get(1)?;
+        let name: Option<String> = row.get(2)?;
+        let severity: Option<String> = row.get(3)?;
+        let status = match row.get::<_, Option<String>>(4)?.as_deref() {
+            Some("Success") => "Success",
+            Some("Failed") => "Failed",
+            _ => "Info",
+        };
+        let (category, name) = match (Category::parse(&category), name) {
+            (Some(category), Some(name)) => (category, name),
+            _ => continue,
+        };
+
+        let mut event = SecurityEvent::new(
+            category,
+            &name,
+            severity.as_deref().and_then(Severity::parse).unwrap_or(Severity::Info),
+            status,
+        );
+        event.actor = row.get(5)?;
+        event.target = row.get(6)?;
+        event.source = row.get(7)?;
+        event.details = row.get(8)?;
+        if let (Category::Ssh, Some(event_type)) = (category, SshEventType::parse(&name)) {
+            let ip: Option<String> = row.get(9)?;
+            event.ssh = Some(SshEvent {
+                event_type,
+                username: event.actor.clone(),
+                invalid_user: row.get::<_, Option<bool>>(11)?.unwrap_or(false),
+                ip: ip.filter(|ip| ip != "unknown"),
+                port: row.get(10)?,
+                method: row.get(12)?,
+                fingerprint: row.get(13)?,
+            });
+        }
+
+        let geo = GeoInfo {
+            country: row.get(14)?,
+            city: row.get(15)?,
+            asn: row.get(16)?,
+            org: row.get(17)?,
+        };
+        let geo = if geo == GeoInfo::default() { None } else { Some(geo) };
+        events.push((timestamp.max(0) as u64, event, geo));
+    }
+    events.reverse();
+    Ok(events)
+}
+
+// Stores an event; returns false when its cursor was already stored
+pub fn insert(
+    db: &Connection,
+    timestamp_us: u64,
+    cursor: Option<&str>,
+    event: &SecurityEvent,
+    geo: Option<&GeoInfo>,
+) -> Result<bool> {
+    let ssh = event.ssh.as_ref();
+    let inserted = db
+        .prepare_cached(
+            "INSERT OR IGNORE INTO security_events (
+                timestamp, timestamp_us, cursor, category, event, severity, status, actor,
+                target, source, details, ip, port, invalid_user, method, fingerprint,
+                country, city, asn, org
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+        )?
+        .execute(rusqlite::params![
+            (timestamp_us / 1_000_000) as i64,
+            timestamp_us as i64,
+            cursor,
+            event.category.as_str(),
+            event.event,
+            event.severity.as_str(),
+            event.status,
+            event.actor,
+            event.target,
+            event.source,
+            event.details,
+            ssh.map(|s| s.ip_or_unknown()),
+            ssh.and_then(|s| s.port),
+            ssh.map(|s| s.invalid_user),
+            ssh.and_then(|s| s.method.as_ref()),
+            ssh.and_then(|s| s.fingerprint.as_ref()),
+            geo.and_then(|g| g.country.as_ref()),
+            geo.and_then(|g| g.city.as_ref()),
+            geo.and_then(|g| g.asn),
+            geo.and_then(|g| g.org.as_ref()),
+        ])?;
+    Ok(inserted > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Expected {
+        category: Category,
+        event: &'static str,
+        severity: Severity,
+        actor: Option<&'static str>,
+        target: Option<&'static str>,
+        source: Option<&'static str>,
+        details: Option<&'static str>,
+    }
+
+    fn expect(category: Category, event: &'static str, severity: Severity) -> Expected {
+        Expected {
+            category,
+            event,
+            severity,
+            actor: None,
+            target: None,
+            source: None,
+            details: None,
+        }
+    }
+
+    fn check(line: &str, expected: Expected) {
+        let event = parse_line(line).unwrap_or_else(|| panic!("not parsed: {}", line));
+        assert_eq!(event.category, expected.category, "{}", line);
+        assert_eq!(event.event, expected.event, "{}", line);
+        assert_eq!(event.severity, expected.severity, "{}", line);
+        assert_eq!(event.actor.as_deref(), expected.actor, "{}", line);
+        assert_eq!(event.target.as_deref(), expected.target, "{}", line);
+        assert_eq!(event.source.as_deref(), expected.source, "{}", line);
+        assert_eq!(event.details.as_deref(), expected.details, "{}", line);
+    }
+
+    fn ignored(line: &str) {
+        assert!(parse_line(line).is_none(), "parsed: {}", line);
+    }
+
+    #[test]
+    fn sudo() {
+        check(
+            "Oct 18 10:00:00 node sudo[2101]:    alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/usr/bin/apt update",
+            Expected {
+                actor: Some("alice"),
+                target: Some("root"),
+                source: Some("pts/0"),
+                details: Some("/usr/bin/apt update"),
+                ..expect(Category::Sudo, "sudo_command", Severity::Notice)
+            },
+        );
+        check(
+            "Oct 18 10:00:01 node sudo[2102]:    alice : 3 incorrect password attempts ; TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/bin/bash",
+            Expected {
+                actor: Some("alice"),
+                target: Some("root"),
+                source: Some("pts/0"),
+                details: Some("/bin/bash (3 incorrect password attempts)"),
+                ..expect(Category::Sudo, "sudo_auth_failure", Severity::Warning)
+            },
+        );
+        check(
+            "Oct 18 10:00:02 node sudo[2103]:      bob : user NOT in sudoers ; TTY=pts/1 ; PWD=/home/bob ; USER=root ; COMMAND=/bin/sh",
+            Expected {
+                actor: Some("bob"),
+                target: Some("root"),
+                source: Some("pts/1"),
+                details: Some("/bin/sh (user NOT in sudoers)"),
+                ..expect(Category::Sudo, "sudo_denied", Severity::Critical)
+            },
+        );
+        // Bare message, as in journald's MESSAGE field, from a cron job without a TTY
+        check(
+            "sudo[2104]:  kaspa : PWD=/opt/kaspa ; USER=root ; COMMAND=/usr/bin/systemctl restart kaspad",
+            Expected {
+                actor: Some("kaspa"),
+                target: Some("root"),
+                details: Some("/usr/bin/systemctl restart kaspad"),
+                ..expect(Category::Sudo, "sudo_command", Severity::Notice)
+            },
+        );
+        ignored("Oct 18 10:00:04 node sudo[2105]: pam_unix(sudo:session): session opened for user root(uid=0) by alice(uid=1000)");
+        ignored("Oct 18 10:00:05 node sudo[2106]: pam_unix(sudo:auth): authentication failure; logname=alice uid=1000 euid=0 tty=/dev/pts/0 ruser=alice rhost=  user=alice");
+    }
+
+    #[test]
+    fn su() {
+        // util-linux
+        check(
+            "Oct 18 10:01:00 node su[2201]: (to root) alice on pts/0",
+            Expected {
+                actor: Some("alice"),
+                target: Some("root"),
+                source: Some("pts/0"),
+                ..expect(Category::Su, "su_session", Severity::Notice)
+            },
+        );
+        check(
+            "Oct 18 10:01:01 node su[2202]: FAILED SU (to root) alice on pts/0",
+            Expected {
+                actor: Some("alice"),
+                target: Some("root"),
+                source: Some("pts/0"),
+                ..expect(Category::Su, "su_failed", Severity::Warning)
+            },
+        );
+        ignored("Oct 18 10:01:02 node su[2203]: pam_unix(su-l:auth): authentication failure; logname=alice uid=1000 euid=0 tty=pts/0 ruser=alice rhost=  user=root");
+        ignored("Oct 18 10:01:03 node su[2204]: pam_unix(su-l:session): session opened for user root(uid=0) by alice(uid=1000)");
+        // shadow
+        check(
+            "Oct 18 10:01:04 node su[2205]: Successful su for root by alice",
+            Expected {
+                actor: Some("alice"),
+                target: Some("root"),
+                ..expect(Category::Su, "su_session", Severity::Notice)
+            },
+        );
+        check(
+            "Oct 18 10:01:05 node su[2206]: FAILED su for root by alice",
+            Expected {
+                actor: Some("alice"),
+                target: Some("root"),
+                ..expect(Category::Su, "su_failed", Severity::Warning)
+            },
+        );
+        ignored("Oct 18 10:01:06 node su[2207]: + /dev/pts/0 alice:root");
+    }
+
+    #[test]
+    fn console_logins() {
+        check(
+            "Oct 18 10:02:00 node login[2301]: FAILED LOGIN (1) on '/dev/tty1' FOR 'root', Authentication failure",
+            Expected {
+                actor: Some("root"),
+                source: Some("/dev/tty1"),
+                details: Some("Authentication failure"),
+                ..expect(Category::Login, "login_failed", Severity::Warning)
+            },
+        );
+        check(
+            "Oct 18 10:02:01 node login[2302]: FAILED LOGIN 1 FROM tty1 FOR root, Authentication failure",
+            Expected {
+                actor: Some("root"),
+                source: Some("tty1"),
+                details: Some("Authentication failure"),
+                ..expect(Category::Login, "login_failed", Severity::Warning)
+            },
+        );
+        check(
+            "Oct 18 10:02:02 node login[2303]: ROOT LOGIN  on '/dev/tty1'",
+            Expected {
+                actor: Some("root"),
+                source: Some("/dev/tty1"),
+                ..expect(Category::Login, "root_login", Severity::Notice)
+            },
+        );
+        check(
+            "Oct 18 10:02:03 node login[2304]: LOGIN ON tty2 BY alice",
+            Expected {
+                actor: Some("alice"),
+                source: Some("tty2"),
+                ..expect(Category::Login, "login", Severity::Info)
+            },
+        );
+        ignored("Oct 18 10:02:04 node login[2305]: pam_unix(login:session): session opened for user alice(uid=1000) by LOGIN(uid=0)");
+    }
+
+    #[test]
+    fn accounts() {
+        check(
+            "Oct 18 10:03:00 node useradd[2401]: new group: name=bob, GID=1001",
+            Expected {
+                target: Some("bob"),
+                details: Some("GID=1001"),
+                ..expect(Category::Account, "group_created", Severity::Warning)
+            },
+        );
+        check(
+            "Oct 18 10:03:00 node useradd[2401]: new user: name=bob, UID=1001, GID=1001, home=/home/bob, shell=/bin/bash, from=/dev/pts/0",
+            Expected {
+                target: Some("bob"),
+                source: Some("/dev/pts/0"),
+                details: Some("UID=1001, GID=1001, home=/home/bob, shell=/bin/bash"),
+                ..expect(Category::Account, "user_created", Severity::Warning)
+            },
+        );
+        check(
+            "Oct 18 10:03:01 node groupadd[2402]: new group: name=devs, GID=1002",
+            Expected {
+                target: Some("devs"),
+                details: Some("GID=1002"),
+                ..expect(Category::Account, "group_created", Severity::Warning)
+            },
+        );
+        ignored("Oct 18 10:03:01 node groupadd[2402]: group added to /etc/group: name=devs, GID=1002");
+        check(
+            "Oct 18 10:03:02 node usermod[2403]: add 'bob' to group 'sudo'",
+            Expected {
+                target: Some("bob"),
+                details: Some("group sudo"),
+                ..expect(Category::Account, "group_member_added", Severity::Critical)
+            },
+        );
+        ignored("Oct 18 10:03:02 node usermod[2403]: add 'bob' to shadow group 'sudo'");
+        check(
+            "Oct 18 10:03:03 node usermod[2404]: add 'bob' to group 'video'",
+            Expected {
+                target: Some("bob"),
+                details: Some("group video"),
+                ..expect(Category::Account, "group_member_added", Severity::Warning)
+            },
+        );
+        check(
+            "Oct 18 10:03:04 node userdel[2405]: delete user 'bob'",
+            Expected {
+                target: Some("bob"),
+                ..expect(Category::Account, "user_deleted", Severity::Warning)
+            },
+        );
+    }
+
+    #[test]
+    fn other_pam_failures() {
+        check(
+            "Oct 18 10:04:00 node gdm-password][2501]: pam_unix(gdm-password:auth): authentication failure; logname= uid=0 euid=0 tty=/dev/tty1 ruser= rhost=  user=alice",
+            Expected {
+                actor: Some("alice"),
+                source: Some("/dev/tty1"),
+                details: Some("pam service gdm-password"),
+                ..expect(Category::Login, "login_failed", Severity::Warning)
+            },
+        );
+        check(
+            "Oct 18 10:04:01 node cockpit-session[2502]: pam_unix(cockpit:auth): authentication failure; logname= uid=0 euid=0 tty= ruser= rhost=192.0.2.50 user=admin",
+            Expected {
+                actor: Some("admin"),
+                source: Some("192.0.2.50"),
+                details: Some("pam service cockpit"),
+                ..expect(Category::Login, "login_failed", Severity::Warning)
+            },
+        );
+        ignored("Oct 18 10:04:02 node sshd[2503]: pam_unix(sshd:auth): authentication failure; logname= uid=0 euid=0 tty=ssh ruser= rhost=192.0.2.51  user=root");
+        ignored("Oct 18 10:04:03 node systemd-logind[2504]: New session 12 of user alice.");
+
+        // Counted once, from the su and sudo lines, whatever program name carries them
+        for service in ["su", "su-l", "sudo", "sudo-i", "login"] {
+            let message = format!(
+                "pam_unix({}:auth): authentication failure; logname=alice uid=1000 euid=0 tty=pts/0 ruser=alice rhost=  user=root",
+                service
+            );
+            assert!(parse_pam_failure(&message).is_none(), "{}", service);
+        }
+    }
+}
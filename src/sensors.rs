@@ -0,0 +1,223 @@
+// Hardware sensors: temperatures from sysinfo's components and CPU frequency from cpufreq
+
+use std::fs;
+use std::path::Path;
+
+use sysinfo::{ComponentExt, System, SystemExt};
+
+// Used for the chart when a sensor doesn't report its own critical threshold
+pub const DEFAULT_CPU_CRITICAL: f64 = 100.0;
+pub const DEFAULT_NVME_CRITICAL: f64 = 80.0;
+
+// Average current frequency below this share of the max frequency counts as throttled
+pub const THROTTLE_FREQ_RATIO: f64 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorKind {
+    CpuPackage,
+    CpuCore,
+    Nvme,
+    Other,
+}
+
+impl SensorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SensorKind::CpuPackage => "cpu_package",
+            SensorKind::CpuCore => "cpu_core",
+            SensorKind::Nvme => "nvme",
+            SensorKind::Other => "other",
+        }
+    }
+
+    // Guesses the kind of a sensor from its hwmon label (e.g. "coretemp Package id 0",
+    // "k10temp Tctl", "nvme Composite")
+    pub fn from_label(label: &str) -> Self {
+        let label = label.to_lowercase();
+        if label.contains("nvme") {
+            SensorKind::Nvme
+        } else if label.contains("package") || label.contains("tctl") || label.contains("tdie") {
+            SensorKind::CpuPackage
+        } else if label.contains("core") || label.contains("k10temp") || label.contains("cpu") {
+            SensorKind::CpuCore
+        } else {
+            SensorKind::Other
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SensorReading {
+    pub label: String,
+    pub kind: SensorKind,
+    pub temperature: f64,
+    pub critical: Option<f64>,
+}
+
+pub fn read_temperatures(sys: &System) -> Vec<SensorReading> {
+    sys.components()
+        .iter()
+        .filter(|c| c.temperature().is_finite() && c.temperature() > 0.0)
+        .map(|c| SensorReading {
+            label: c.label().to_string(),
+            kind: SensorKind::from_label(c.label()),
+            temperature: c.temperature() as f64,
+            critical: c.critical().map(|v| v as f64),
+        })
+        .collect()
+}
+
+// Hottest reading of the given kinds with its critical threshold
+pub fn hottest(readings: &[SensorReading], kinds: &[SensorKind]) -> Option<(f64, Option<f64>)> {
+    readings
+        .iter()
+        .filter(|r| kinds.contains(&r.kind))
+        .max_by(|a, b| a.temperature.total_cmp(&b.temperature))
+        .map(|r| (r.temperature, r.critical))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CpuFrequency {
+    pub avg_mhz: f64,
+    pub max_mhz: f64,
+    // Sum of the Intel thermal_throttle counters, when the driver exposes them
+    pub throttle_count: Option<u64>,
+}
+
+impl CpuFrequency {
+    // Reads every cpu*/cpufreq under `cpu_root` (usually /sys/devices/system/cpu)
+    pub fn read(cpu_root: &Path) -> Option<Self> {
+        let mut current = Vec::new();
+        let mut max: f64 = 0.0;
+        let mut throttle_count = None;
+
+        for entry in fs::read_dir(cpu_root).ok()?.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let is_cpu = name
+                .strip_prefix("cpu")
+                .map(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+                .unwrap_or(false);
+            if !is_cpu {
+                continue;
+            }
+
+            let cpufreq = entry.path().join("cpufreq");
+            if let Some(khz) = read_u64(&cpufreq.join("scaling_cur_freq")) {
+                current.push(khz as f64 / 1000.0);
+            }
+            if let Some(khz) = read_u64(&cpufreq.join("cpuinfo_max_freq")) {
+                max = max.max(khz as f64 / 1000.0);
+            }
+
+            let throttle = entry.path().join("thermal_throttle");
+            for counter in ["core_throttle_count", "package_throttle_count"] {
+                if let Some(count) = read_u64(&throttle.join(counter)) {
+                    *throttle_count.get_or_insert(0) += count;
+                }
+            }
+        }
+
+        if current.is_empty() {
+            return None;
+        }
+        Some(CpuFrequency {
+            avg_mhz: current.iter().sum::<f64>() / current.len() as f64,
+            max_mhz: max,
+            throttle_count,
+        })
+    }
+
+    pub fn ratio(&self) -> Option<f64> {
+        if self.max_mhz > 0.0 {
+            Some(self.avg_mhz / self.max_mhz)
+        } else {
+            None
+        }
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse::<u64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn reading(label: &str, temperature: f64, critical: Option<f64>) -> SensorReading {
+        SensorReading { label: label.to_string(), kind: SensorKind::from_label(label), temperature, critical }
+    }
+
+    #[test]
+    fn kinds_of_hwmon_labels() {
+        for (label, kind) in [
+            ("coretemp Package id 0", SensorKind::CpuPackage),
+            ("k10temp Tctl", SensorKind::CpuPackage),
+            ("k10temp Tdie", SensorKind::CpuPackage),
+            ("coretemp Core 3", SensorKind::CpuCore),
+            ("k10temp Tccd1", SensorKind::CpuCore),
+            ("cpu_thermal", SensorKind::CpuCore),
+            ("nvme Composite", SensorKind::Nvme),
+            ("nvme Sensor 1", SensorKind::Nvme),
+            ("acpitz temp1", SensorKind::Other),
+            ("amdgpu edge", SensorKind::Other),
+        ] {
+            assert_eq!(SensorKind::from_label(label), kind, "{}", label);
+        }
+    }
+
+    #[test]
+    fn hottest_reading_of_the_kinds() {
+        let readings = [
+            reading("coretemp Package id 0", 64.0, Some(100.0)),
+            reading("coretemp Core 0", 71.0, None),
+            reading("nvme Composite", 48.0, Some(84.85)),
+            reading("acpitz temp1", 90.0, None),
+        ];
+        assert_eq!(hottest(&readings, &[SensorKind::CpuPackage, SensorKind::CpuCore]), Some((71.0, None)));
+        assert_eq!(hottest(&readings, &[SensorKind::Nvme]), Some((48.0, Some(84.85))));
+        assert_eq!(hottest(&readings[..2], &[SensorKind::Nvme]), None);
+    }
+
+    #[test]
+    fn reads_cpu_frequency_and_throttle_counters() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write(root, "cpu0/cpufreq/scaling_cur_freq", "1200000\n");
+        write(root, "cpu0/cpufreq/cpuinfo_max_freq", "4000000\n");
+        write(root, "cpu0/thermal_throttle/core_throttle_count", "3\n");
+        write(root, "cpu0/thermal_throttle/package_throttle_count", "10\n");
+        write(root, "cpu1/cpufreq/scaling_cur_freq", "2000000\n");
+        write(root, "cpu1/cpufreq/cpuinfo_max_freq", "4200000\n");
+        write(root, "cpu1/thermal_throttle/core_throttle_count", "2\n");
+        // Not CPUs
+        write(root, "cpufreq/boost", "1\n");
+        write(root, "cpuidle/current_driver", "intel_idle\n");
+
+        let freq = CpuFrequency::read(root).unwrap();
+        assert_eq!((freq.avg_mhz, freq.max_mhz), (1600.0, 4200.0));
+        assert_eq!(freq.throttle_count, Some(15));
+        assert_eq!(freq.ratio(), Some(1600.0 / 4200.0));
+        assert!(freq.ratio().unwrap() < THROTTLE_FREQ_RATIO);
+
+        // No thermal_throttle (AMD) and no max frequency
+        fs::remove_dir_all(root.join("cpu0")).unwrap();
+        fs::remove_dir_all(root.join("cpu1/thermal_throttle")).unwrap();
+        fs::remove_file(root.join("cpu1/cpufreq/cpuinfo_max_freq")).unwrap();
+        let freq = CpuFrequency::read(root).unwrap();
+        assert_eq!((freq.avg_mhz, freq.throttle_count, freq.ratio()), (2000.0, None, None));
+
+        // Without cpufreq (VMs)
+        fs::remove_dir_all(root.join("cpu1/cpufreq")).unwrap();
+        assert!(CpuFrequency::read(root).is_none());
+        assert!(CpuFrequency::read(&root.join("missing")).is_none());
+    }
+}
@@ -0,0 +1,226 @@
+// Long-format time series: `series` holds one row per metric name and label set with its
+// unit, `samples` one row per series and timestamp. Collectors fill a Batch per tick and
+// SeriesStore writes it in one transaction, so new metrics need no schema change.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use rusqlite::{params, Connection, OptionalExtension, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Percent,
+    Bytes,
+    Cores,
+}
+
+impl Unit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Unit::Percent => "%",
+            Unit::Bytes => "bytes",
+            Unit::Cores => "cores",
+        }
+    }
+}
+
+// Label set of a series, kept sorted by key so equal sets encode the same way
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Labels(Vec<(String, String)>);
+
+impl Labels {
+    pub fn new() -> Self {
+        Labels(Vec::new())
+    }
+
+    // ',' and '=' separate labels in their encoding, so they become '_'
+    pub fn with(mut self, key: &str, value: &str) -> Self {
+        let clean = |s: &str| s.replace([',', '='], "_");
+        let key = clean(key);
+        self.0.retain(|(k, _)| *k != key);
+        self.0.push((key, clean(value)));
+        self.0.sort();
+        self
+    }
+
+    // "k1=v1,k2=v2", the empty string without labels
+    pub fn encode(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn decode(encoded: &str) -> Self {
+        let mut labels = Labels::new();
+        for pair in encoded.split(',').filter(|p| !p.is_empty()) {
+            if let Some((key, value)) = pair.split_once('=') {
+                labels = labels.with(key, value);
+            }
+        }
+        labels
+    }
+}
+
+impl fmt::Display for Labels {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pairs: Vec<String> = self.0.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub id: i64,
+    pub name: String,
+    pub labels: Labels,
+    pub unit: Option<String>,
+}
+
+struct Sample {
+    name: &'static str,
+    labels: Labels,
+    unit: Unit,
+    value: f64,
+}
+
+// Samples of one tick, all stored with the same timestamp
+pub struct Batch {
+    pub timestamp_ms: i64,
+    samples: Vec<Sample>,
+}
+
+impl Batch {
+    pub fn new(timestamp_ms: i64) -> Self {
+        Batch {
+            timestamp_ms,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, name: &'static str, unit: Unit, value: f64) {
+        self.record_labeled(name, Labels::new(), unit, value);
+    }
+
+    pub fn record_labeled(&mut self, name: &'static str, labels: Labels, unit: Unit, value: f64) {
+        // NaN (e.g. a ratio over a zero total) is not a measurement
+        if value.is_finite() {
+            self.samples.push(Sample { name, labels, unit, value });
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+}
+
+// Writes batches, caching the IDs of the series already seen
+#[derive(Default)]
+pub struct SeriesStore {
+    ids: HashMap<(&'static str, Labels), i64>,
+}
+
+impl SeriesStore {
+    pub fn new() -> Self {
+        SeriesStore::default()
+    }
+
+    // Stores the samples of `batch` in one transaction (a savepoint, so it also nests in a
+    // transaction of the caller)
+    pub fn write(&mut self, db: &Connection, batch: &Batch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        db.execute_batch("SAVEPOINT series_batch")?;
+        let result = (|| {
+            for sample in &batch.samples {
+                let id = self.series_id(db, sample)?;
+                db.prepare_cached("INSERT OR REPLACE INTO samples (series_id, ts, value) VALUES (?1, ?2, ?3)")?
+                    .execute(params![id, batch.timestamp_ms, sample.value])?;
+            }
+            Ok(())
+        })();
+        match result {
+            Ok(()) => db.execute_batch("RELEASE series_batch"),
+            Err(e) => {
+                db.execute_batch("ROLLBACK TO series_batch; RELEASE series_batch")?;
+                // IDs created in the rolled back savepoint are gone
+                self.forget();
+                Err(e)
+            }
+        }
+    }
+
+    // Drops the cached IDs, for callers rolling back a transaction that wrote batches
+    pub fn forget(&mut self) {
+        self.ids.clear();
+    }
+
+    fn series_id(&mut self, db: &Connection, sample: &Sample) -> Result<i64> {
+        let key = (sample.name, sample.labels.clone());
+        if let Some(id) = self.ids.get(&key) {
+            return Ok(*id);
+        }
+        let encoded = sample.labels.encode();
+        db.prepare_cached("INSERT OR IGNORE INTO series (name, labels, unit) VALUES (?1, ?2, ?3)")?
+            .execute(params![sample.name, encoded, sample.unit.as_str()])?;
+        let id = db
+            .prepare_cached("SELECT id FROM series WHERE name = ?1 AND labels = ?2")?
+            .query_row(params![sample.name, encoded], |row| row.get(0))?;
+        self.ids.insert(key, id);
+        Ok(id)
+    }
+
+    pub fn list(db: &Connection) -> Result<Vec<Series>> {
+        db.prepare("SELECT id, name, labels, unit FROM series ORDER BY name, labels")?
+            .query_map([], |row| {
+                Ok(Series {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    labels: Labels::decode(&row.get::<_, String>(2)?),
+                    unit: row.get(3)?,
+                })
+            })?
+            .collect()
+    }
+
+    pub fn find(db: &Connection, name: &str, labels: &Labels) -> Result<Option<Series>> {
+        db.prepare_cached("SELECT id, unit FROM series WHERE name = ?1 AND labels = ?2")?
+            .query_row(params![name, labels.encode()], |row| {
+                Ok(Series {
+                    id: row.get(0)?,
+                    name: name.to_string(),
+                    labels: labels.clone(),
+                    unit: row.get(1)?,
+                })
+            })
+            .optional()
+    }
+
+    // (timestamp in ms, value) of a series within [from_ms, to_ms), oldest first
+    pub fn query(db: &Connection, series: &Series, from_ms: i64, to_ms: i64) -> Result<Vec<(i64, f64)>> {
+        db.prepare_cached(
+            "SELECT ts, value FROM samples
+             WHERE series_id = ?1 AND ts >= ?2 AND ts < ?3 AND value IS NOT NULL
+             ORDER BY ts",
+        )?
+        .query_map(params![series.id, from_ms, to_ms], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect()
+    }
+
+    // The `limit` latest samples of a series, oldest first
+    pub fn latest(db: &Connection, series: &Series, limit: usize) -> Result<Vec<(i64, f64)>> {
+        let mut samples = db
+            .prepare_cached(
+                "SELECT ts, value FROM samples
+                 WHERE series_id = ?1 AND value IS NOT NULL
+                 ORDER BY ts DESC
+                 LIMIT ?2",
+            )?
+            .query_map(params![series.id, limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(i64, f64)>>>()?;
+        samples.reverse();
+        Ok(samples)
+    }
+}
@@ -0,0 +1,454 @@
+// Parser for the sshd authentication events found in journald and auth.log
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SshEventType {
+    // Successful authentication
+    Accepted,
+    // Failed password/publickey/keyboard-interactive/none attempt
+    Failed,
+    // Connection attempt for a username that doesn't exist
+    InvalidUser,
+    // "maximum authentication attempts exceeded" / "Too many authentication failures"
+    MaxAuthExceeded,
+    // Client gave up during authentication of a known or invalid user (preauth).
+    // With the default LogLevel this is the only trace of failed public-key attempts.
+    AuthAbandoned,
+    // Connection closed, reset or disconnected before any user was named (preauth)
+    ClosedPreauth,
+    // Client never sent an SSH banner (port scanners)
+    NoIdentification,
+    // End of an authenticated session
+    Disconnected,
+}
+
+impl SshEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SshEventType::Accepted => "accepted",
+            SshEventType::Failed => "failed",
+            SshEventType::InvalidUser => "invalid_user",
+            SshEventType::MaxAuthExceeded => "max_auth_exceeded",
+            SshEventType::AuthAbandoned => "auth_abandoned",
+            SshEventType::ClosedPreauth => "closed_preauth",
+            SshEventType::NoIdentification => "no_identification",
+            SshEventType::Disconnected => "disconnected",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "accepted" => Some(SshEventType::Accepted),
+            "failed" => Some(SshEventType::Failed),
+            "invalid_user" => Some(SshEventType::InvalidUser),
+            "max_auth_exceeded" => Some(SshEventType::MaxAuthExceeded),
+            "auth_abandoned" => Some(SshEventType::AuthAbandoned),
+            "closed_preauth" => Some(SshEventType::ClosedPreauth),
+            "no_identification" => Some(SshEventType::NoIdentification),
+            "disconnected" => Some(SshEventType::Disconnected),
+            _ => None,
+        }
+    }
+
+    // Coarse status kept in the "status" column: Success, Failed or Info
+    pub fn status(&self) -> &'static str {
+        match self {
+            SshEventType::Accepted => "Success",
+            SshEventType::Failed
+            | SshEventType::InvalidUser
+            | SshEventType::MaxAuthExceeded
+            | SshEventType::AuthAbandoned => "Failed",
+            SshEventType::ClosedPreauth
+            | SshEventType::NoIdentification
+            | SshEventType::Disconnected => "Info",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshEvent {
+    pub event_type: SshEventType,
+    pub username: Option<String>,
+    pub invalid_user: bool,
+    pub ip: Option<String>,
+    pub port: Option<u16>,
+    // "password", "publickey", "keyboard-interactive/pam", "none", ...
+    pub method: Option<String>,
+    // Key type and fingerprint, e.g. "ED25519 SHA256:..."
+    pub fingerprint: Option<String>,
+}
+
+impl SshEvent {
+    fn new(event_type: SshEventType) -> Self {
+        SshEvent {
+            event_type,
+            username: None,
+            invalid_user: false,
+            ip: None,
+            port: None,
+            method: None,
+            fingerprint: None,
+        }
+    }
+
+    pub fn status(&self) -> &'static str {
+        self.event_type.status()
+    }
+
+    pub fn ip_or_unknown(&self) -> &str {
+        self.ip.as_deref().unwrap_or("unknown")
+    }
+}
+
+// Parses a syslog/journal line ("Oct 18 10:00:00 host sshd[123]: ...") or a bare sshd
+// message. Returns None for lines that aren't authentication events.
+pub fn parse_line(line: &str) -> Option<SshEvent> {
+    let message = sshd_message(line);
+    let message = message.strip_prefix("error: ").unwrap_or(message).trim();
+    let message = message.strip_suffix("[preauth]").unwrap_or(message).trim_end();
+
+    if let Some(rest) = message.strip_prefix("Accepted ") {
+        return parse_auth_result(rest, SshEventType::Accepted);
+    }
+    if let Some(rest) = message.strip_prefix("Failed ") {
+        return parse_auth_result(rest, SshEventType::Failed);
+    }
+    if let Some(rest) = message.strip_prefix("Invalid user ") {
+        // "Invalid user admin from 1.2.3.4 port 5555"
+        let (user, endpoint) = rsplit_once_str(rest, "from ")?;
+        let mut event = SshEvent::new(SshEventType::InvalidUser);
+        event.username = non_empty(user.trim());
+        event.invalid_user = true;
+        parse_endpoint(endpoint, &mut event);
+        return Some(event);
+    }
+    if let Some(rest) = message.strip_prefix("maximum authentication attempts exceeded for ") {
+        // "root from 1.2.3.4 port 5555 ssh2" or "invalid user admin from ..."
+        let mut event = SshEvent::new(SshEventType::MaxAuthExceeded);
+        let (user, endpoint) = rsplit_once_str(rest, " from ")?;
+        set_user(user, &mut event);
+        parse_endpoint(endpoint, &mut event);
+        return Some(event);
+    }
+    if let Some(rest) = message.strip_prefix("Disconnecting ") {
+        // "authenticating user root 1.2.3.4 port 5555: Too many authentication failures"
+        let (who, reason) = rest.split_once(':').unwrap_or((rest, ""));
+        if reason.contains("Too many authentication failures") {
+            let mut event = SshEvent::new(SshEventType::MaxAuthExceeded);
+            parse_user_endpoint(who, &mut event);
+            return Some(event);
+        }
+        return None;
+    }
+    for prefix in ["Connection closed by ", "Connection reset by ", "Disconnected from "] {
+        if let Some(rest) = message.strip_prefix(prefix) {
+            let mut event = SshEvent::new(SshEventType::ClosedPreauth);
+            parse_user_endpoint(rest, &mut event);
+            event.event_type = if rest.starts_with("user ") {
+                SshEventType::Disconnected
+            } else if event.username.is_some() {
+                SshEventType::AuthAbandoned
+            } else {
+                SshEventType::ClosedPreauth
+            };
+            return Some(event);
+        }
+    }
+    if let Some(rest) = message.strip_prefix("Received disconnect from ") {
+        // "1.2.3.4 port 5555:11: Bye Bye" (the session end is logged by "Disconnected from user")
+        let endpoint = rest.split(':').next().unwrap_or(rest);
+        let mut event = SshEvent::new(SshEventType::ClosedPreauth);
+        parse_endpoint(endpoint, &mut event);
+        return if line.contains("[preauth]") { Some(event) } else { None };
+    }
+    if let Some(rest) = message.strip_prefix("Did not receive identification string from ") {
+        let mut event = SshEvent::new(SshEventType::NoIdentification);
+        parse_endpoint(rest, &mut event);
+        return Some(event);
+    }
+
+    None
+}
+
+// Strips the syslog/journal prefix up to "sshd[pid]: " (or "sshd-session[pid]: ")
+fn sshd_message(line: &str) -> &str {
+    for marker in ["sshd-session[", "sshd["] {
+        if let Some(start) = line.find(marker) {
+            if let Some(end) = line[start..].find("]: ") {
+                return &line[start + end + 3..];
+            }
+        }
+    }
+    line.strip_prefix("sshd: ").unwrap_or(line)
+}
+
+// "<method> for [invalid user ]<user> from <ip> port <port> ssh2[: <type> <fingerprint>]"
+fn parse_auth_result(rest: &str, event_type: SshEventType) -> Option<SshEvent> {
+    let (method, rest) = rest.split_once(" for ")?;
+    let (user, endpoint) = rsplit_once_str(rest, " from ")?;
+
+    let mut event = SshEvent::new(event_type);
+    event.method = Some(method.to_string());
+    set_user(user, &mut event);
+
+    let (endpoint, key) = match endpoint.split_once(": ") {
+        Some((endpoint, key)) => (endpoint, Some(key)),
+        None => (endpoint, None),
+    };
+    parse_endpoint(endpoint, &mut event);
+    event.fingerprint = key.and_then(|k| non_empty(k.trim()));
+
+    Some(event)
+}
+
+// "[authenticating |invalid ]user <user> <ip> port <port>" or "<ip> port <port>"
+fn parse_user_endpoint(rest: &str, event: &mut SshEvent) {
+    let tokens: Vec<&str> = rest.split_whitespace().collect();
+    let port_index = tokens.iter().rposition(|&t| t == "port");
+
+    let ip_index = match port_index {
+        Some(i) if i > 0 => i - 1,
+        _ => return,
+    };
+    event.ip = Some(tokens[ip_index].to_string());
+    event.port = port_index
+        .and_then(|i| tokens.get(i + 1))
+        .and_then(|p| p.trim_end_matches(':').parse::<u16>().ok());
+
+    let who = tokens[..ip_index].join(" ");
+    let user = if let Some(user) = who.strip_prefix("authenticating user ") {
+        Some(user)
+    } else if let Some(user) = who.strip_prefix("invalid user ") {
+        event.invalid_user = true;
+        Some(user)
+    } else {
+        who.strip_prefix("user ")
+    };
+    event.username = user.and_then(|u| non_empty(u.trim()));
+}
+
+// "<ip> port <port>[ ssh2]"
+fn parse_endpoint(endpoint: &str, event: &mut SshEvent) {
+    let mut tokens = endpoint.split_whitespace();
+    event.ip = tokens.next().map(|ip| ip.to_string());
+    if tokens.next() == Some("port") {
+        event.port = tokens.next().and_then(|p| p.parse::<u16>().ok());
+    }
+}
+
+fn set_user(user: &str, event: &mut SshEvent) {
+    match user.strip_prefix("invalid user ") {
+        Some(user) => {
+            event.invalid_user = true;
+            event.username = non_empty(user.trim());
+        }
+        None => event.username = non_empty(user.trim()),
+    }
+}
+
+fn rsplit_once_str<'a>(s: &'a str, separator: &str) -> Option<(&'a str, &'a str)> {
+    s.rfind(separator)
+        .map(|i| (&s[..i], &s[i + separator.len()..]))
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Expected {
+        event_type: SshEventType,
+        username: Option<&'static str>,
+        invalid_user: bool,
+        ip: Option<&'static str>,
+        port: Option<u16>,
+        method: Option<&'static str>,
+        fingerprint: Option<&'static str>,
+    }
+
+    fn expect(event_type: SshEventType, username: Option<&'static str>, ip: &'static str, port: u16) -> Expected {
+        Expected {
+            event_type,
+            username,
+            invalid_user: false,
+            ip: Some(ip),
+            port: Some(port),
+            method: None,
+            fingerprint: None,
+        }
+    }
+
+    fn check(line: &str, expected: Expected) {
+        let event = parse_line(line).unwrap_or_else(|| panic!("not parsed: {}", line));
+        assert_eq!(event.event_type, expected.event_type, "{}", line);
+        assert_eq!(event.username.as_deref(), expected.username, "{}", line);
+        assert_eq!(event.invalid_user, expected.invalid_user, "{}", line);
+        assert_eq!(event.ip.as_deref(), expected.ip, "{}", line);
+        assert_eq!(event.port, expected.port, "{}", line);
+        assert_eq!(event.method.as_deref(), expected.method, "{}", line);
+        assert_eq!(event.fingerprint.as_deref(), expected.fingerprint, "{}", line);
+    }
+
+    #[test]
+    fn accepted_logins() {
+        check(
+            "Oct 18 10:00:01 node sshd[1201]: Accepted publickey for kaspa from 203.0.113.7 port 50122 ssh2: ED25519 SHA256:Xq1mX3pBv0mY6kQ5Vd2zT1YzQ0c0b8bYk3p8JfFv2Nw",
+            Expected {
+                method: Some("publickey"),
+                fingerprint: Some("ED25519 SHA256:Xq1mX3pBv0mY6kQ5Vd2zT1YzQ0c0b8bYk3p8JfFv2Nw"),
+                ..expect(SshEventType::Accepted, Some("kaspa"), "203.0.113.7", 50122)
+            },
+        );
+        check(
+            "Oct 18 10:00:02 node sshd[1202]: Accepted password for root from 198.51.100.23 port 41866 ssh2",
+            Expected {
+                method: Some("password"),
+                ..expect(SshEventType::Accepted, Some("root"), "198.51.100.23", 41866)
+            },
+        );
+        check(
+            "Oct 18 10:00:03 node sshd-session[1203]: Accepted keyboard-interactive/pam for admin from 2001:db8::42 port 60022 ssh2",
+            Expected {
+                method: Some("keyboard-interactive/pam"),
+                ..expect(SshEventType::Accepted, Some("admin"), "2001:db8::42", 60022)
+            },
+        );
+    }
+
+    #[test]
+    fn failed_logins() {
+        check(
+            "Oct 18 10:01:00 node sshd[1301]: Failed password for root from 192.0.2.10 port 33412 ssh2",
+            Expected {
+                method: Some("password"),
+                ..expect(SshEventType::Failed, Some("root"), "192.0.2.10", 33412)
+            },
+        );
+        check(
+            "Oct 18 10:01:01 node sshd[1302]: Failed password for invalid user oracle from 192.0.2.11 port 52814 ssh2",
+            Expected {
+                method: Some("password"),
+                invalid_user: true,
+                ..expect(SshEventType::Failed, Some("oracle"), "192.0.2.11", 52814)
+            },
+        );
+        check(
+            "Oct 18 10:01:02 node sshd[1303]: Failed publickey for kaspa from 192.0.2.12 port 40022 ssh2: RSA SHA256:4n8uE0WwJZzV1mYb3QbqJmO4S5yJQ0Jx1Fv3kqf0Z9s",
+            Expected {
+                method: Some("publickey"),
+                fingerprint: Some("RSA SHA256:4n8uE0WwJZzV1mYb3QbqJmO4S5yJQ0Jx1Fv3kqf0Z9s"),
+                ..expect(SshEventType::Failed, Some("kaspa"), "192.0.2.12", 40022)
+            },
+        );
+        check(
+            "Oct 18 10:01:03 node sshd[1304]: Failed keyboard-interactive/pam for invalid user test from 192.0.2.13 port 49152 ssh2",
+            Expected {
+                method: Some("keyboard-interactive/pam"),
+                invalid_user: true,
+                ..expect(SshEventType::Failed, Some("test"), "192.0.2.13", 49152)
+            },
+        );
+        check(
+            "Oct 18 10:01:04 node sshd[1305]: Failed none for invalid user  from 192.0.2.14 port 40100 ssh2",
+            Expected {
+                method: Some("none"),
+                invalid_user: true,
+                ..expect(SshEventType::Failed, None, "192.0.2.14", 40100)
+            },
+        );
+    }
+
+    #[test]
+    fn invalid_users() {
+        check(
+            "Oct 18 10:02:00 node sshd[1401]: Invalid user ubnt from 192.0.2.20 port 38200",
+            Expected {
+                invalid_user: true,
+                ..expect(SshEventType::InvalidUser, Some("ubnt"), "192.0.2.20", 38200)
+            },
+        );
+        // Bare message, as in journald's MESSAGE field
+        check(
+            "Invalid user admin from 192.0.2.21 port 51000",
+            Expected {
+                invalid_user: true,
+                ..expect(SshEventType::InvalidUser, Some("admin"), "192.0.2.21", 51000)
+            },
+        );
+    }
+
+    #[test]
+    fn connections_closed_during_authentication() {
+        check(
+            "Oct 18 10:03:00 node sshd[1501]: Connection closed by authenticating user root 192.0.2.30 port 45678 [preauth]",
+            expect(SshEventType::AuthAbandoned, Some("root"), "192.0.2.30", 45678),
+        );
+        check(
+            "Oct 18 10:03:01 node sshd[1502]: Connection closed by invalid user support 192.0.2.31 port 45679 [preauth]",
+            Expected {
+                invalid_user: true,
+                ..expect(SshEventType::AuthAbandoned, Some("support"), "192.0.2.31", 45679)
+            },
+        );
+        check(
+            "Oct 18 10:03:02 node sshd[1503]: Connection closed by 192.0.2.32 port 45680 [preauth]",
+            expect(SshEventType::ClosedPreauth, None, "192.0.2.32", 45680),
+        );
+        check(
+            "Oct 18 10:03:03 node sshd[1504]: Disconnected from user kaspa 203.0.113.7 port 50122",
+            expect(SshEventType::Disconnected, Some("kaspa"), "203.0.113.7", 50122),
+        );
+    }
+
+    #[test]
+    fn too_many_attempts() {
+        check(
+            "Oct 18 10:04:00 node sshd[1601]: error: maximum authentication attempts exceeded for root from 192.0.2.40 port 52200 ssh2 [preauth]",
+            expect(SshEventType::MaxAuthExceeded, Some("root"), "192.0.2.40", 52200),
+        );
+        check(
+            "Oct 18 10:04:01 node sshd[1602]: error: maximum authentication attempts exceeded for invalid user admin from 192.0.2.41 port 52201 ssh2 [preauth]",
+            Expected {
+                invalid_user: true,
+                ..expect(SshEventType::MaxAuthExceeded, Some("admin"), "192.0.2.41", 52201)
+            },
+        );
+        check(
+            "Oct 18 10:04:02 node sshd[1603]: Disconnecting authenticating user root 192.0.2.42 port 52202: Too many authentication failures [preauth]",
+            expect(SshEventType::MaxAuthExceeded, Some("root"), "192.0.2.42", 52202),
+        );
+        check(
+            "Oct 18 10:04:03 node sshd[1604]: Disconnecting invalid user guest 192.0.2.43 port 52203: Too many authentication failures [preauth]",
+            Expected {
+                invalid_user: true,
+                ..expect(SshEventType::MaxAuthExceeded, Some("guest"), "192.0.2.43", 52203)
+            },
+        );
+    }
+
+    #[test]
+    fn scanners_without_identification() {
+        check(
+            "Oct 18 10:05:00 node sshd[1701]: Did not receive identification string from 192.0.2.50 port 61000",
+            expect(SshEventType::NoIdentification, None, "192.0.2.50", 61000),
+        );
+    }
+
+    #[test]
+    fn ignores_other_lines() {
+        for line in [
+            "Oct 18 10:06:00 node sshd[1801]: Server listening on 0.0.0.0 port 22.",
+            "Oct 18 10:06:01 node sshd[1802]: pam_unix(sshd:session): session opened for user kaspa(uid=1000) by (uid=0)",
+            "Oct 18 10:06:02 node sshd[1803]: Received disconnect from 203.0.113.7 port 50122:11: disconnected by user",
+            "Oct 18 10:06:03 node sshd[1804]: Disconnecting user root 203.0.113.7 port 50122: Timeout",
+        ] {
+            assert_eq!(parse_line(line), None, "{}", line);
+        }
+    }
+}
@@ -0,0 +1,375 @@
+// Database work on its own thread: the storage thread owns the read-write connection, stores
+// the rows sent by the sampler (buffered, see writer.rs), reads the auth log, checks file
+// integrity, analyzes brute-force attempts, rolls up and prunes. The interface gets new
+// security events and a status snapshot after every tick, so a slow disk, a lock or a VACUUM
+// never blocks keyboard input.
+
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rusqlite::types::Value;
+use rusqlite::{Connection, Result};
+
+use crate::authlog::{self, AuthLogSource, LogRecord};
+use crate::bruteforce::{self, Analysis};
+use crate::config::Config;
+use crate::db;
+use crate::firewall::BanEngine;
+use crate::geoip::{GeoInfo, GeoIp};
+use crate::integrity::IntegrityWatch;
+use crate::logins::{LoginAlert, LoginWatch};
+use crate::retention::Retention;
+use crate::security::{self, SecurityEvent};
+use crate::series::{Batch, SeriesStore};
+use crate::ssh::SshEventType;
+use crate::writer::WriteBuffer;
+
+// Delay between two brute-force analyses of ssh_attempts
+const ANALYSIS_INTERVAL: Duration = Duration::from_secs(10);
+
+// Rows kept in memory while the database can't be written, about 6 hours of samples
+const WRITE_QUEUE_CAPACITY: usize = 50_000;
+
+pub enum Request {
+    // Row for a time-series table, committed with the next batch
+    Row { sql: &'static str, values: Vec<Value> },
+    Samples(Batch),
+    // Path of the running kaspad binary and the path it can be read from
+    KaspadExe((PathBuf, PathBuf)),
+    AcknowledgeAlert,
+    TrustAlert,
+    // Flushes the buffered rows and stops the thread
+    Shutdown,
+}
+
+// What the interface shows of the database side
+#[derive(Debug, Clone, Default)]
+pub struct Status {
+    // Why the auth log can't be read
+    pub auth_error: Option<String>,
+    pub geoip_enabled: bool,
+    pub geoip_error: Option<String>,
+    pub brute_force: Analysis,
+    pub active_bans: Vec<String>,
+    pub ban_error: Option<String>,
+    // Unacknowledged login alerts, oldest first, and the last hook failure
+    pub login_alerts: Vec<LoginAlert>,
+    pub login_error: Option<String>,
+    // Last failed commit of the buffered rows
+    pub write_error: Option<String>,
+    pub queued_rows: usize,
+    pub dropped_rows: u64,
+    // Last failed tick or request; the thread goes on with the next tick
+    pub db_error: Option<String>,
+}
+
+pub struct Update {
+    // Events stored since the previous update, oldest first
+    pub events: Vec<(u64, SecurityEvent, Option<GeoInfo>)>,
+    pub status: Status,
+}
+
+pub struct Storage {
+    db: Connection,
+    config: Config,
+    auth_source: Result<Box<dyn AuthLogSource>, String>,
+    // Last error of the auth log source
+    auth_source_status: Option<String>,
+    geoip: Result<Option<GeoIp>, String>,
+    brute_force: Analysis,
+    last_analysis: Option<SystemTime>,
+    ban_engine: Option<BanEngine>,
+    active_bans: Vec<String>,
+    login_watch: LoginWatch,
+    integrity: IntegrityWatch,
+    last_integrity_check: Option<SystemTime>,
+    kaspad_exe: Option<(PathBuf, PathBuf)>,
+    retention: Retention,
+    series: SeriesStore,
+    // Time-series rows waiting for the next commit
+    writes: WriteBuffer,
+    // Events not sent to the interface yet
+    events: Vec<(u64, SecurityEvent, Option<GeoInfo>)>,
+    db_error: Option<String>,
+}
+
+impl Storage {
+    // `db` must be migrated to the latest schema version
+    pub fn new(config: Config, db: Connection) -> Result<Self> {
+        let auth_source = authlog::from_config(
+            &config.auth_source,
+            &config.ssh_units,
+            config.auth_log_file.as_deref(),
+            config.auth_replay_file.as_deref(),
+        )
+        .map_err(|e| e.to_string());
+        let geoip = match (&config.geoip_city_db, &config.geoip_asn_db) {
+            (None, None) => Ok(None),
+            (city, asn) => GeoIp::open(city.as_deref(), asn.as_deref()).map(Some),
+        };
+
+        // Bans of brute-force IPs
+        let ban_engine = config.ban.clone().map(BanEngine::from_config);
+        let active_bans = BanEngine::active_bans(&db, config.ban.as_ref().is_some_and(|ban| ban.dry_run))?;
+
+        // Baseline of known login IPs/subnets/users and alerts on logins outside of it
+        LoginWatch::seed(&db)?;
+        let mut login_watch = LoginWatch::new(config.login_alert_hook.clone());
+        login_watch.load_pending(&db)?;
+
+        // Hashes of the kaspad binary, unit file, authorized_keys and configured files
+        let integrity = IntegrityWatch::new(
+            &config.process_name,
+            config.authorized_keys.clone(),
+            config.integrity_paths.clone(),
+        );
+
+        // 1-minute and 1-hour aggregates of the raw samples
+        let retention = Retention::new(config.retention.clone());
+
+        // Samples are committed together, once per commit interval
+        let writes = WriteBuffer::new(Duration::from_secs(config.commit_interval), WRITE_QUEUE_CAPACITY);
+
+        Ok(Storage {
+            db,
+            config,
+            auth_source,
+            auth_source_status: None,
+            geoip,
+            brute_force: Analysis::default(),
+            last_analysis: None,
+            ban_engine,
+            active_bans,
+            login_watch,
+            integrity,
+            last_integrity_check: None,
+            kaspad_exe: None,
+            retention,
+            series: SeriesStore::new(),
+            writes,
+            events: Vec::new(),
+            db_error: None,
+        })
+    }
+
+    // Ticks every `interval` and handles requests in between, until a Shutdown request or
+    // until every sender is gone. Database errors are reported in the status and don't stop
+    // the thread. Buffered rows are flushed before it ends; only that last flush can fail.
+    pub fn spawn(mut self, interval: Duration, requests: Receiver<Request>, updates: Sender<Update>) -> JoinHandle<Result<()>> {
+        thread::spawn(move || {
+            self.run(interval, &requests, &updates);
+            self.writes.flush(&self.db, &mut self.series, true)
+        })
+    }
+
+    fn run(&mut self, interval: Duration, requests: &Receiver<Request>, updates: &Sender<Update>) {
+        let mut next_tick = Instant::now();
+        loop {
+            let timeout = next_tick.saturating_duration_since(Instant::now());
+            let changed = match requests.recv_timeout(timeout) {
+                Ok(Request::Shutdown) | Err(RecvTimeoutError::Disconnected) => return,
+                Ok(request) => self.handle(request).unwrap_or_else(|e| {
+                    self.db_error = Some(e.to_string());
+                    true
+                }),
+                Err(RecvTimeoutError::Timeout) => false,
+            };
+
+            let now = Instant::now();
+            let tick = now >= next_tick;
+            if tick {
+                self.db_error = self.tick().err().map(|e| e.to_string());
+                next_tick = (next_tick + interval).max(now);
+            }
+            if (tick || changed) && updates.send(self.update()).is_err() {
+                // The interface is gone
+                return;
+            }
+        }
+    }
+
+    // True when the interface should be updated right away
+    fn handle(&mut self, request: Request) -> Result<bool> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        match request {
+            Request::Row { sql, values } => self.writes.push(sql, values),
+            Request::Samples(batch) => self.writes.push_samples(batch),
+            Request::KaspadExe(exe) => self.kaspad_exe = Some(exe),
+            Request::AcknowledgeAlert => {
+                self.login_watch.acknowledge(&self.db, now)?;
+                return Ok(true);
+            }
+            Request::TrustAlert => {
+                self.login_watch.trust(&self.db, now)?;
+                return Ok(true);
+            }
+            Request::Shutdown => {}
+        }
+        Ok(false)
+    }
+
+    // Every step runs even when an earlier one failed (a lock held past the busy timeout,
+    // a full disk); the first error is returned
+    fn tick(&mut self) -> Result<()> {
+        let auth_log = self.read_auth_log();
+        let integrity = self.check_integrity();
+        let analysis = self.analyze_brute_force();
+
+        // Commits the buffered samples before rolling up, so complete hours have them all
+        let flushed = self.writes.flush(&self.db, &mut self.series, false);
+
+        // Rollups and pruning, a bounded amount per tick
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let retention = self.retention.step(&self.db, now);
+
+        auth_log.and(integrity).and(analysis).and(flushed).and(retention)
+    }
+
+    fn update(&mut self) -> Update {
+        let auth_error = match &self.auth_source {
+            Err(e) => Some(e.clone()),
+            Ok(_) => self.auth_source_status.clone(),
+        };
+        Update {
+            events: std::mem::take(&mut self.events),
+            status: Status {
+                auth_error,
+                geoip_enabled: matches!(self.geoip, Ok(Some(_))),
+                geoip_error: self.geoip.as_ref().err().cloned(),
+                brute_force: self.brute_force.clone(),
+                active_bans: self.active_bans.clone(),
+                ban_error: self.ban_engine.as_ref().and_then(|e| e.last_error.clone()),
+                login_alerts: self.login_watch.pending.clone(),
+                login_error: self.login_watch.last_error.clone(),
+                write_error: self.writes.last_error.clone(),
+                queued_rows: self.writes.queued_rows(),
+                dropped_rows: self.writes.dropped,
+                db_error: self.db_error.clone(),
+            },
+        }
+    }
+
+    fn check_integrity(&mut self) -> Result<()> {
+        let now = SystemTime::now();
+        if let Some(last) = self.last_integrity_check {
+            if now.duration_since(last).unwrap_or_default() < Duration::from_secs(self.config.integrity_interval) {
+                return Ok(());
+            }
+        }
+        self.last_integrity_check = Some(now);
+
+        let timestamp_us = now.duration_since(UNIX_EPOCH).unwrap().as_micros() as u64;
+        let files = self.integrity.files(self.kaspad_exe.clone());
+        for event in self.integrity.check(&self.db, &files, timestamp_us / 1_000_000)? {
+            security::insert(&self.db, timestamp_us, None, &event, None)?;
+            self.events.push((timestamp_us / 1_000_000, event, None));
+        }
+        Ok(())
+    }
+
+    fn read_auth_log(&mut self) -> Result<()> {
+        let source = match &mut self.auth_source {
+            Ok(source) => source,
+            Err(_) => return Ok(()),
+        };
+
+        // Resume after the last record read, even across restarts
+        let state_key = source.state_key();
+        let position = db::get_state(&self.db, &state_key)?;
+        let (records, next_position) = match source.read(position.as_deref()) {
+            Ok(result) => result,
+            Err(e) => {
+                self.auth_source_status = Some(e.to_string());
+                return Ok(());
+            }
+        };
+        self.auth_source_status = None;
+        if records.is_empty() && next_position.is_none() {
+            return Ok(());
+        }
+
+        // The entries of one read and the new position are committed together
+        self.db.execute_batch("BEGIN IMMEDIATE")?;
+        let result = self.store_auth_records(&records, &state_key, position.as_deref(), next_position);
+        match result {
+            Ok(()) => self.db.execute_batch("COMMIT"),
+            Err(e) => {
+                let _ = self.db.execute_batch("ROLLBACK");
+                Err(e)
+            }
+        }
+    }
+
+    fn store_auth_records(
+        &mut self,
+        records: &[LogRecord],
+        state_key: &str,
+        position: Option<&str>,
+        next_position: Option<String>,
+    ) -> Result<()> {
+        for record in records {
+            let event = match security::parse_line(&record.message) {
+                Some(event) => event,
+                None => continue,
+            };
+            let timestamp = record.timestamp_secs();
+            let geo = match (&self.geoip, event.ssh.as_ref().and_then(|s| s.ip.as_ref())) {
+                (Ok(Some(geoip)), Some(ip)) => geoip.lookup(ip),
+                _ => None,
+            };
+            let geo_ref = geo.as_ref();
+
+            let inserted =
+                security::insert(&self.db, record.timestamp_us, Some(&record.cursor), &event, geo_ref)?;
+
+            // An entry already stored was already shown (and checked) too
+            if inserted {
+                if let Some(ssh) = event.ssh.as_ref().filter(|s| s.event_type == SshEventType::Accepted) {
+                    self.login_watch.check(
+                        &self.db,
+                        ssh.ip_or_unknown(),
+                        ssh.username.as_deref(),
+                        geo_ref.and_then(|g| g.country.as_deref()),
+                        timestamp,
+                    )?;
+                }
+                self.events.push((timestamp, event, geo));
+            }
+        }
+
+        if let Some(next_position) = next_position {
+            if position != Some(next_position.as_str()) {
+                db::set_state(&self.db, state_key, &next_position)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn analyze_brute_force(&mut self) -> Result<()> {
+        let due = self
+            .last_analysis
+            .and_then(|t| t.elapsed().ok())
+            .map(|elapsed| elapsed >= ANALYSIS_INTERVAL)
+            .unwrap_or(true);
+        if !due {
+            return Ok(());
+        }
+        self.last_analysis = Some(SystemTime::now());
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        self.brute_force = bruteforce::analyze(&self.db, &self.config.brute_force, now)?;
+
+        if let Some(engine) = &mut self.ban_engine {
+            let reason = format!(
+                "ssh brute-force: >= {} failures in {}s",
+                self.config.brute_force.max_failures, self.config.brute_force.rate_window
+            );
+            engine.apply(&self.db, &self.brute_force.brute_forcers, &reason, now)?;
+            self.active_bans = BanEngine::active_bans(&self.db, engine.dry_run())?;
+        }
+        Ok(())
+    }
+}
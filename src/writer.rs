@@ -0,0 +1,201 @@
+// Buffered writes of the time-series tables: rows and sample batches are queued during a
+// tick and committed together in one transaction every commit interval, instead of one
+// autocommit (and fsync) per statement. The queue is bounded; when the database can't be
+// written, the oldest entries are dropped once it is full.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, Result};
+
+use crate::series::{Batch, SeriesStore};
+
+enum Pending {
+    Row { sql: &'static str, values: Vec<Value> },
+    Samples(Batch),
+}
+
+impl Pending {
+    // Rows this entry writes, counted against the capacity
+    fn size(&self) -> usize {
+        match self {
+            Pending::Row { .. } => 1,
+            Pending::Samples(batch) => batch.len(),
+        }
+    }
+}
+
+pub struct WriteBuffer {
+    queue: VecDeque<Pending>,
+    queued_rows: usize,
+    capacity: usize,
+    interval: Duration,
+    last_flush: Option<Instant>,
+    // Rows dropped because the queue was full
+    pub dropped: u64,
+    // Error of the last failed commit, cleared by the next successful one
+    pub last_error: Option<String>,
+}
+
+impl WriteBuffer {
+    pub fn new(interval: Duration, capacity: usize) -> Self {
+        WriteBuffer {
+            queue: VecDeque::new(),
+            queued_rows: 0,
+            capacity,
+            interval,
+            last_flush: None,
+            dropped: 0,
+            last_error: None,
+        }
+    }
+
+    // Queues an INSERT (or any statement) with its parameters
+    pub fn push(&mut self, sql: &'static str, values: Vec<Value>) {
+        self.enqueue(Pending::Row { sql, values });
+    }
+
+    pub fn push_samples(&mut self, batch: Batch) {
+        if !batch.is_empty() {
+            self.enqueue(Pending::Samples(batch));
+        }
+    }
+
+    fn enqueue(&mut self, pending: Pending) {
+        self.queued_rows += pending.size();
+        self.queue.push_back(pending);
+        while self.queued_rows > self.capacity && self.queue.len() > 1 {
+            if let Some(oldest) = self.queue.pop_front() {
+                self.queued_rows -= oldest.size();
+                self.dropped += oldest.size() as u64;
+            }
+        }
+    }
+
+    pub fn queued_rows(&self) -> usize {
+        self.queued_rows
+    }
+
+    // Commits the queue when the commit interval has elapsed, or right away with `force`.
+    // A failed commit keeps the queue for the next attempt; only a forced flush returns
+    // the error.
+    pub fn flush(&mut self, db: &Connection, series: &mut SeriesStore, force: bool) -> Result<()> {
+        let due = self.last_flush.is_none_or(|last| last.elapsed() >= self.interval);
+        if self.queue.is_empty() || !(due || force) {
+            return Ok(());
+        }
+        self.last_flush = Some(Instant::now());
+
+        match self.commit(db, series) {
+            Ok(()) => {
+                self.queue.clear();
+                self.queued_rows = 0;
+                self.last_error = None;
+                Ok(())
+            }
+            Err(e) if force => Err(e),
+            Err(e) => {
+                self.last_error = Some(e.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    fn commit(&self, db: &Connection, series: &mut SeriesStore) -> Result<()> {
+        db.execute_batch("BEGIN IMMEDIATE")?;
+        let result = (|| {
+            for pending in &self.queue {
+                match pending {
+                    Pending::Row { sql, values } => {
+                        db.prepare_cached(sql)?.execute(params_from_iter(values.iter()))?;
+                    }
+                    Pending::Samples(batch) => series.write(db, batch)?,
+                }
+            }
+            Ok(())
+        })()
+        .and_then(|()| db.execute_batch("COMMIT"));
+        if let Err(e) = result {
+            // A failed COMMIT (busy, disk full) leaves the transaction open
+            if !db.is_autocommit() {
+                let _ = db.execute_batch("ROLLBACK");
+            }
+            // Series created by the batches were rolled back with it
+            series.forget();
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_database;
+    use crate::series::Unit;
+
+    fn batch(timestamp_ms: i64) -> Batch {
+        let mut batch = Batch::new(timestamp_ms);
+        batch.record("kaspad_cpu_percent", Unit::Percent, 12.5);
+        batch
+    }
+
+    fn count(db: &Connection, table: &str) -> i64 {
+        db.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn commits_rows_and_samples_together() {
+        let db = test_database();
+        let mut series = SeriesStore::new();
+        let mut writes = WriteBuffer::new(Duration::from_secs(60), 100);
+        writes.push("INSERT INTO state (key, value) VALUES (?1, ?2)", vec!["a".to_string().into(), "1".to_string().into()]);
+        writes.push_samples(batch(1_000));
+        assert_eq!(writes.queued_rows(), 2);
+
+        writes.flush(&db, &mut series, false).unwrap();
+        assert_eq!(writes.queued_rows(), 0);
+        assert_eq!((count(&db, "state"), count(&db, "samples")), (1, 1));
+
+        // Not due yet
+        writes.push_samples(batch(2_000));
+        writes.flush(&db, &mut series, false).unwrap();
+        assert_eq!(writes.queued_rows(), 1);
+    }
+
+    #[test]
+    fn forgets_series_rolled_back_with_the_transaction() {
+        let db = test_database();
+        let mut series = SeriesStore::new();
+        db.execute("DELETE FROM series WHERE name = 'kaspad_cpu_percent'", []).unwrap();
+        let known = count(&db, "series");
+
+        // The batch creates its series, then a later row fails the whole transaction
+        let mut failing = WriteBuffer::new(Duration::from_secs(60), 100);
+        failing.push_samples(batch(1_000));
+        failing.push("INSERT INTO missing (value) VALUES (?1)", vec![1.into()]);
+        assert!(failing.flush(&db, &mut series, true).is_err());
+        assert!(db.is_autocommit());
+        assert_eq!((count(&db, "series"), count(&db, "samples")), (known, 0));
+
+        // Another series takes the rolled back ID, and the next batch still gets its own
+        db.execute("INSERT INTO series (name, labels, unit) VALUES ('other', '', '%')", []).unwrap();
+        let mut writes = WriteBuffer::new(Duration::from_secs(60), 100);
+        writes.push_samples(batch(2_000));
+        writes.flush(&db, &mut series, true).unwrap();
+        let name: String = db
+            .query_row("SELECT name FROM samples JOIN series ON series.id = samples.series_id", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(name, "kaspad_cpu_percent");
+    }
+
+    #[test]
+    fn drops_the_oldest_entries_when_full() {
+        let mut writes = WriteBuffer::new(Duration::from_secs(60), 2);
+        for ts in 0..3 {
+            writes.push_samples(batch(ts));
+        }
+        assert_eq!((writes.queued_rows(), writes.dropped), (2, 1));
+    }
+}